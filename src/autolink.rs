@@ -0,0 +1,561 @@
+//! Post-processing of plain text: `:shortcode:` emoji, bare URLs and email
+//! addresses, and GitHub-style issue references.
+
+use crate::front_matter::FrontMatter;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+/// GitHub shortcodes for the emoji most often seen in READMEs and changelogs.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("alarm_clock", "⏰"),
+    ("ambulance", "🚑"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇"),
+    ("arrow_left", "⬅"),
+    ("arrow_right", "➡"),
+    ("arrow_up", "⬆"),
+    ("art", "🎨"),
+    ("bangbang", "‼"),
+    ("beers", "🍻"),
+    ("bell", "🔔"),
+    ("bento", "🍱"),
+    ("bookmark", "🔖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("bug", "🐛"),
+    ("building_construction", "🏗"),
+    ("bulb", "💡"),
+    ("calendar", "📆"),
+    ("camera", "📷"),
+    ("card_file_box", "🗃"),
+    ("chart_with_upwards_trend", "📈"),
+    ("checkered_flag", "🏁"),
+    ("children_crossing", "🚸"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("clock1", "🕐"),
+    ("closed_lock_with_key", "🔐"),
+    ("cloud", "☁"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("construction_worker", "👷"),
+    ("cry", "😢"),
+    ("dart", "🎯"),
+    ("dizzy", "💫"),
+    ("egg", "🥚"),
+    ("exclamation", "❗"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("floppy_disk", "💾"),
+    ("gear", "⚙"),
+    ("gem", "💎"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("goal_net", "🥅"),
+    ("green_heart", "💚"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("hammer_and_wrench", "🛠"),
+    ("hankey", "💩"),
+    ("heart", "❤"),
+    ("heavy_check_mark", "✔"),
+    ("heavy_minus_sign", "➖"),
+    ("heavy_plus_sign", "➕"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("information_source", "ℹ"),
+    ("iphone", "📱"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("label", "🏷"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lipstick", "💄"),
+    ("lock", "🔒"),
+    ("loud_sound", "🔊"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("money_with_wings", "💸"),
+    ("monocle_face", "🧐"),
+    ("mute", "🔇"),
+    ("new", "🆕"),
+    ("no_entry", "⛔"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("page_facing_up", "📄"),
+    ("paperclip", "📎"),
+    ("passport_control", "🛂"),
+    ("pencil", "📝"),
+    ("pencil2", "✏"),
+    ("poop", "💩"),
+    ("pray", "🙏"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻"),
+    ("red_circle", "🔴"),
+    ("rewind", "⏪"),
+    ("rocket", "🚀"),
+    ("rotating_light", "🚨"),
+    ("see_no_evil", "🙈"),
+    ("seedling", "🌱"),
+    ("shipit", "🐿"),
+    ("shirt", "👕"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("speech_balloon", "💬"),
+    ("star", "⭐"),
+    ("star2", "🌟"),
+    ("stethoscope", "🩺"),
+    ("sunglasses", "😎"),
+    ("tada", "🎉"),
+    ("test_tube", "🧪"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("tools", "🛠"),
+    ("trash", "🗑"),
+    ("triangular_flag_on_post", "🚩"),
+    ("truck", "🚚"),
+    ("twisted_rightwards_arrows", "🔀"),
+    ("unlock", "🔓"),
+    ("warning", "⚠"),
+    ("wastebasket", "🗑"),
+    ("wave", "👋"),
+    ("wheelchair", "♿"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+];
+
+/// How issue references like `#12` and `owner/repo#12` become links.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkSettings {
+    /// URL with `{repo}` (as `owner/name`) and `{number}` placeholders.
+    pub issue_url_template: String,
+    /// Repository for bare `#12` references; they stay plain text without one.
+    pub repository: String,
+}
+
+impl Default for LinkSettings {
+    fn default() -> Self {
+        Self {
+            issue_url_template: "https://github.com/{repo}/issues/{number}".to_string(),
+            repository: String::new(),
+        }
+    }
+}
+
+impl LinkSettings {
+    /// Settings overridden by the document's `issue_url` and `repository` keys.
+    pub fn with_front_matter(&self, front_matter: &FrontMatter) -> Self {
+        Self {
+            issue_url_template: front_matter
+                .issue_url_template()
+                .unwrap_or_else(|| self.issue_url_template.clone()),
+            repository: front_matter
+                .repository()
+                .unwrap_or_else(|| self.repository.clone()),
+        }
+    }
+
+    fn issue_url(&self, repository: &str, number: &str) -> Option<String> {
+        let template = self.issue_url_template.trim();
+        if template.is_empty() || (repository.is_empty() && template.contains("{repo}")) {
+            return None;
+        }
+        Some(
+            template
+                .replace("{repo}", repository)
+                .replace("{number}", number),
+        )
+    }
+}
+
+/// Draws the issue link options. Returns `true` if anything changed.
+pub fn link_settings_ui(ui: &mut egui::Ui, settings: &mut LinkSettings) -> bool {
+    let mut changed = false;
+    egui::Grid::new("link_settings_grid")
+        .num_columns(2)
+        .spacing([8.0, 4.0])
+        .show(ui, |ui| {
+            ui.label("Issue URL");
+            changed |= ui
+                .add(
+                    egui::TextEdit::singleline(&mut settings.issue_url_template)
+                        .desired_width(320.0),
+                )
+                .on_hover_text("{repo} and {number} are replaced for each reference")
+                .changed();
+            ui.end_row();
+            ui.label("Repository");
+            changed |= ui
+                .add(
+                    egui::TextEdit::singleline(&mut settings.repository)
+                        .hint_text("owner/name")
+                        .desired_width(320.0),
+                )
+                .on_hover_text("Used for #123 references without a repository")
+                .changed();
+            ui.end_row();
+        });
+    ui.label(
+        egui::RichText::new(
+            "Documents can override these with `issue_url` and `repository` front matter keys.",
+        )
+        .weak()
+        .small(),
+    );
+    if ui.button("Reset to defaults").clicked() {
+        changed |= *settings != LinkSettings::default();
+        *settings = LinkSettings::default();
+    }
+    changed
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Span {
+    Text(String),
+    Link { text: String, url: String },
+}
+
+/// Expands shortcodes in `text` and, if `autolink` is set, splits out bare
+/// URLs, email addresses and issue references as links.
+pub fn expand(text: &str, settings: &LinkSettings, autolink: bool) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    let mut previous: Option<char> = None;
+    while let Some(c) = rest.chars().next() {
+        let word_start = !previous.is_some_and(|p| p.is_alphanumeric() || "_/#@.-".contains(p));
+        if c == ':' {
+            if let Some((emoji, len)) = shortcode(rest) {
+                plain.push_str(emoji);
+                rest = &rest[len..];
+                previous = Some(':');
+                continue;
+            }
+        }
+        if autolink && word_start {
+            if let Some((link_text, url)) = url_at(rest)
+                .or_else(|| email_at(rest))
+                .or_else(|| issue_at(rest, settings))
+            {
+                if !plain.is_empty() {
+                    spans.push(Span::Text(std::mem::take(&mut plain)));
+                }
+                rest = &rest[link_text.len()..];
+                previous = link_text.chars().last();
+                spans.push(Span::Link {
+                    text: link_text.to_string(),
+                    url,
+                });
+                continue;
+            }
+        }
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+        previous = Some(c);
+    }
+    if !plain.is_empty() {
+        spans.push(Span::Text(plain));
+    }
+    spans
+}
+
+/// `:name:` at the start of `text`, as the emoji and the shortcode's length.
+fn shortcode(text: &str) -> Option<(&'static str, usize)> {
+    let end = text[1..].find(':')? + 1;
+    let name = &text[1..end];
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'))
+    {
+        return None;
+    }
+    SHORTCODES
+        .iter()
+        .find(|(code, _)| *code == name)
+        .map(|(_, emoji)| (*emoji, end + 1))
+}
+
+/// A bare `http(s)://` or `www.` URL, without trailing punctuation.
+fn url_at(text: &str) -> Option<(&str, String)> {
+    let starts_with = |prefix: &str| {
+        text.as_bytes()
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix.as_bytes()))
+    };
+    let www = starts_with("www.");
+    if !(www || starts_with("http://") || starts_with("https://")) {
+        return None;
+    }
+    let end = text
+        .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+        .unwrap_or(text.len());
+    let mut candidate = &text[..end];
+    loop {
+        let trimmed = candidate.trim_end_matches(['.', ',', ':', ';', '!', '?', '\'', '*', '_']);
+        // A closing parenthesis belongs to the URL only if it's balanced.
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(inner) if inner.matches('(').count() < trimmed.matches(')').count() => inner,
+            _ => trimmed,
+        };
+        if trimmed.len() == candidate.len() {
+            break;
+        }
+        candidate = trimmed;
+    }
+    let host = candidate.split("://").nth(1).unwrap_or(candidate);
+    if host.len() < 4 || (!host.contains('.') && !host.starts_with("localhost")) {
+        return None;
+    }
+    let url = if www {
+        format!("http://{}", candidate)
+    } else {
+        candidate.to_string()
+    };
+    Some((candidate, url))
+}
+
+/// `name@example.com`
+fn email_at(text: &str) -> Option<(&str, String)> {
+    let local = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-')))
+        .unwrap_or(text.len());
+    if local == 0 || !text[local..].starts_with('@') {
+        return None;
+    }
+    let domain_text = &text[local + 1..];
+    let domain = domain_text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '-')))
+        .unwrap_or(domain_text.len());
+    let domain = domain_text[..domain].trim_end_matches(['.', '-']);
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2 || labels.iter().any(|label| label.is_empty()) {
+        return None;
+    }
+    let address = &text[..local + 1 + domain.len()];
+    Some((address, format!("mailto:{}", address)))
+}
+
+/// `#12` or `owner/repo#12`, followed by a non-word character.
+fn issue_at<'t>(text: &'t str, settings: &LinkSettings) -> Option<(&'t str, String)> {
+    let word = &text[..text.find(char::is_whitespace).unwrap_or(text.len())];
+    let hash = word.find('#')?;
+    let repository = &text[..hash];
+    if !repository.is_empty() {
+        let (owner, name) = repository.split_once('/')?;
+        let valid = |part: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        };
+        if !valid(owner) || !valid(name) {
+            return None;
+        }
+    }
+    let digits = &text[hash + 1..];
+    let len = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    if len == 0 || digits[len..].starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    let number = &digits[..len];
+    let url = if repository.is_empty() {
+        settings.issue_url(&settings.repository, number)?
+    } else {
+        settings.issue_url(repository, number)?
+    };
+    Some((&text[..hash + 1 + len], url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> LinkSettings {
+        LinkSettings {
+            repository: "owner/name".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn text(text: &str) -> Span {
+        Span::Text(text.to_string())
+    }
+
+    fn link(text: &str, url: &str) -> Span {
+        Span::Link {
+            text: text.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    fn expand_all(input: &str) -> Vec<Span> {
+        expand(input, &settings(), true)
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            expand_all("See https://example.com/a."),
+            [
+                text("See "),
+                link("https://example.com/a", "https://example.com/a"),
+                text(".")
+            ]
+        );
+        assert_eq!(
+            expand_all("HTTP://Example.com/?q=1!"),
+            [
+                link("HTTP://Example.com/?q=1", "HTTP://Example.com/?q=1"),
+                text("!")
+            ]
+        );
+        assert_eq!(
+            expand_all("<http://localhost:8080/>"),
+            [
+                text("<"),
+                link("http://localhost:8080/", "http://localhost:8080/"),
+                text(">")
+            ]
+        );
+        // Too short to be a host.
+        assert_eq!(expand_all("https://x"), [text("https://x")]);
+    }
+
+    #[test]
+    fn url_parentheses() {
+        let url = "https://en.wikipedia.org/wiki/Rust_(language)";
+        assert_eq!(
+            expand_all(&format!("(see {}).", url)),
+            [text("(see "), link(url, url), text(").")]
+        );
+        assert_eq!(
+            expand_all("(https://example.com/a)"),
+            [
+                text("("),
+                link("https://example.com/a", "https://example.com/a"),
+                text(")")
+            ]
+        );
+    }
+
+    #[test]
+    fn www_urls() {
+        assert_eq!(
+            expand_all("Go to www.rust-lang.org, then"),
+            [
+                text("Go to "),
+                link("www.rust-lang.org", "http://www.rust-lang.org"),
+                text(", then")
+            ]
+        );
+        assert_eq!(
+            expand_all("WWW.Example.com"),
+            [link("WWW.Example.com", "http://WWW.Example.com")]
+        );
+        // Only at the start of a word.
+        assert_eq!(expand_all("awww.example.com"), [text("awww.example.com")]);
+    }
+
+    #[test]
+    fn non_ascii_text() {
+        assert_eq!(
+            expand_all("日本語 https://example.com です"),
+            [
+                text("日本語 "),
+                link("https://example.com", "https://example.com"),
+                text(" です")
+            ]
+        );
+        assert_eq!(
+            expand_all("日本語https://example.com"),
+            [text("日本語https://example.com")]
+        );
+        assert_eq!(expand_all("🎉🎉 wwwé"), [text("🎉🎉 wwwé")]);
+    }
+
+    #[test]
+    fn emails() {
+        assert_eq!(
+            expand_all("Mail me@example.com."),
+            [
+                text("Mail "),
+                link("me@example.com", "mailto:me@example.com"),
+                text(".")
+            ]
+        );
+        assert_eq!(
+            expand_all("(first.last+tag@mail.example.org)"),
+            [
+                text("("),
+                link(
+                    "first.last+tag@mail.example.org",
+                    "mailto:first.last+tag@mail.example.org"
+                ),
+                text(")")
+            ]
+        );
+        assert_eq!(expand_all("me@localhost"), [text("me@localhost")]);
+        assert_eq!(
+            expand_all("path/me@example.com"),
+            [text("path/me@example.com")]
+        );
+    }
+
+    #[test]
+    fn issues() {
+        assert_eq!(
+            expand_all("Fixes #12."),
+            [
+                text("Fixes "),
+                link("#12", "https://github.com/owner/name/issues/12"),
+                text(".")
+            ]
+        );
+        assert_eq!(
+            expand_all("See rust-lang/rust#99"),
+            [
+                text("See "),
+                link(
+                    "rust-lang/rust#99",
+                    "https://github.com/rust-lang/rust/issues/99"
+                )
+            ]
+        );
+        assert_eq!(expand_all("abc#12"), [text("abc#12")]);
+        assert_eq!(expand_all("#12a #x"), [text("#12a #x")]);
+        // Bare references need a repository.
+        assert_eq!(expand("#12", &LinkSettings::default(), true), [text("#12")]);
+        assert_eq!(expand("#12", &settings(), false), [text("#12")]);
+    }
+
+    #[test]
+    fn shortcodes() {
+        assert_eq!(
+            expand_all(":tada: at 10:30 :smile:"),
+            [text("🎉 at 10:30 😄")]
+        );
+        assert_eq!(expand_all("10:30:smile:"), [text("10:30😄")]);
+        assert_eq!(expand_all(":not_a_code: :+1:"), [text(":not_a_code: 👍")]);
+        assert_eq!(expand_all("a: b :"), [text("a: b :")]);
+        assert_eq!(expand(":x:", &settings(), false), [text("❌")]);
+    }
+}
@@ -0,0 +1,303 @@
+use eframe::egui::{self, FontData, FontDefinitions, FontFamily};
+use lazy_static::lazy_static;
+use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, Once, OnceLock};
+
+/// Installed fonts and their family names, sorted.
+struct SystemFonts {
+    db: fontdb::Database,
+    families: Vec<String>,
+}
+
+/// Filled by the indexing thread, which can take seconds on systems with
+/// many fonts.
+static SYSTEM_FONTS: OnceLock<SystemFonts> = OnceLock::new();
+static INDEXING: Once = Once::new();
+
+lazy_static! {
+    /// The fonts asked for while the system fonts were still being indexed,
+    /// applied once they are.
+    static ref PENDING: Mutex<Option<(egui::Context, FontSettings)>> = Mutex::new(None);
+}
+
+fn index_system_fonts() -> SystemFonts {
+    let mut db = fontdb::Database::new();
+    db.load_system_fonts();
+    log::info!("Indexed {} system font faces.", db.len());
+    let mut families: Vec<String> = db
+        .faces()
+        .filter_map(|face| face.families.first().map(|(name, _)| name.clone()))
+        .collect();
+    families.sort_by_key(|name| name.to_lowercase());
+    families.dedup();
+    SystemFonts { db, families }
+}
+
+/// Name of the egui font family used for headings.
+pub const HEADING_FAMILY: &str = "heading";
+
+// Tried in order; the first installed family of each list joins the fallback chain.
+const CJK_FALLBACKS: &[&str] = &[
+    "Yu Gothic UI",
+    "Yu Gothic",
+    "Meiryo",
+    "MS Gothic",
+    "Microsoft YaHei",
+    "Malgun Gothic",
+    "Hiragino Sans",
+    "Noto Sans CJK JP",
+    "Noto Sans JP",
+    "Source Han Sans",
+    "PingFang SC",
+    "WenQuanYi Micro Hei",
+    "Droid Sans Fallback",
+];
+const EMOJI_FALLBACKS: &[&str] = &[
+    "Segoe UI Emoji",
+    "Noto Color Emoji",
+    "Noto Emoji",
+    "Symbola",
+];
+const SYMBOL_FALLBACKS: &[&str] = &["Segoe UI Symbol", "DejaVu Sans"];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FontChoice {
+    #[default]
+    Default,
+    System(String),
+    /// A font file. Only the first font of a `.ttc` collection is used.
+    File(PathBuf),
+}
+
+impl FontChoice {
+    fn label(&self) -> String {
+        match self {
+            FontChoice::Default => "Default".to_string(),
+            FontChoice::System(family) => family.clone(),
+            FontChoice::File(path) => path.file_name().map_or_else(
+                || path.display().to_string(),
+                |n| n.to_string_lossy().to_string(),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FontSettings {
+    pub body: FontChoice,
+    pub heading: FontChoice,
+    pub monospace: FontChoice,
+}
+
+pub fn heading_family() -> FontFamily {
+    FontFamily::Name(HEADING_FAMILY.into())
+}
+
+/// Installs the chosen fonts, followed by CJK and emoji fallbacks, into `ctx`.
+/// Until the system fonts are indexed in the background, only egui's own
+/// fonts and font files are used, and the rest follow once they are.
+pub fn apply_fonts(ctx: &egui::Context, settings: &FontSettings) {
+    // Checked under the lock so the indexing thread can't miss these settings.
+    let mut pending = PENDING.lock().unwrap();
+    let system = SYSTEM_FONTS.get();
+    ctx.set_fonts(build_font_definitions(settings, system));
+    if system.is_some() {
+        return;
+    }
+    *pending = Some((ctx.clone(), settings.clone()));
+    drop(pending);
+    INDEXING.call_once(|| {
+        let spawned = std::thread::Builder::new()
+            .name("fonts".to_string())
+            .spawn(|| {
+                let system = index_system_fonts();
+                let mut pending = PENDING.lock().unwrap();
+                let system = SYSTEM_FONTS.get_or_init(|| system);
+                if let Some((ctx, settings)) = pending.take() {
+                    ctx.set_fonts(build_font_definitions(&settings, Some(system)));
+                    ctx.request_repaint();
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("Indexing system fonts on the UI thread: {}", e);
+            let system = SYSTEM_FONTS.get_or_init(index_system_fonts);
+            if let Some((ctx, settings)) = PENDING.lock().unwrap().take() {
+                ctx.set_fonts(build_font_definitions(&settings, Some(system)));
+            }
+        }
+    });
+}
+
+fn build_font_definitions(
+    settings: &FontSettings,
+    system: Option<&SystemFonts>,
+) -> FontDefinitions {
+    let mut fonts = FontDefinitions::default();
+    let default_proportional = fonts.families[&FontFamily::Proportional].clone();
+    let default_monospace = fonts.families[&FontFamily::Monospace].clone();
+
+    let fallbacks: Vec<String> = [CJK_FALLBACKS, EMOJI_FALLBACKS, SYMBOL_FALLBACKS]
+        .iter()
+        .filter_map(|candidates| {
+            candidates
+                .iter()
+                .find_map(|family| load_system_family(&mut fonts, system?, family))
+        })
+        .collect();
+
+    let body = load_choice(&mut fonts, system, &settings.body);
+    let heading = load_choice(&mut fonts, system, &settings.heading).or_else(|| body.clone());
+    let monospace = load_choice(&mut fonts, system, &settings.monospace);
+
+    fonts.families.insert(
+        FontFamily::Proportional,
+        fallback_chain(body, &default_proportional, &fallbacks),
+    );
+    fonts.families.insert(
+        FontFamily::Monospace,
+        fallback_chain(monospace, &default_monospace, &fallbacks),
+    );
+    fonts.families.insert(
+        heading_family(),
+        fallback_chain(heading, &default_proportional, &fallbacks),
+    );
+    fonts
+}
+
+/// `primary`, then egui's own text fonts, then the system fallbacks, and egui's
+/// bundled emoji fonts last since they only cover a small set of symbols.
+fn fallback_chain(
+    primary: Option<String>,
+    defaults: &[String],
+    fallbacks: &[String],
+) -> Vec<String> {
+    let emoji_start = defaults
+        .iter()
+        .position(|name| name == "NotoEmoji-Regular")
+        .unwrap_or(defaults.len());
+    let mut chain: Vec<String> = primary.into_iter().collect();
+    chain.extend_from_slice(&defaults[..emoji_start]);
+    chain.extend_from_slice(fallbacks);
+    chain.extend_from_slice(&defaults[emoji_start..]);
+    let mut seen = HashSet::new();
+    chain.retain(|name| seen.insert(name.clone()));
+    chain
+}
+
+fn load_choice(
+    fonts: &mut FontDefinitions,
+    system: Option<&SystemFonts>,
+    choice: &FontChoice,
+) -> Option<String> {
+    match choice {
+        FontChoice::Default => None,
+        // Applied again once the system fonts are indexed.
+        FontChoice::System(_) if system.is_none() => None,
+        FontChoice::System(family) => {
+            let key = load_system_family(fonts, system?, family);
+            if key.is_none() {
+                log::warn!("Font family '{}' is not installed.", family);
+            }
+            key
+        }
+        FontChoice::File(path) => match fs::read(path) {
+            Ok(bytes) => {
+                let key = format!("file:{}", path.display());
+                fonts
+                    .font_data
+                    .insert(key.clone(), FontData::from_owned(bytes));
+                Some(key)
+            }
+            Err(e) => {
+                log::error!("Failed to read font file {}: {}", path.display(), e);
+                None
+            }
+        },
+    }
+}
+
+fn load_system_family(
+    fonts: &mut FontDefinitions,
+    system: &SystemFonts,
+    family: &str,
+) -> Option<String> {
+    let key = format!("system:{}", family);
+    if fonts.font_data.contains_key(&key) {
+        return Some(key);
+    }
+    let id = system.db.query(&fontdb::Query {
+        families: &[fontdb::Family::Name(family)],
+        ..Default::default()
+    })?;
+    let (bytes, index) = system
+        .db
+        .with_face_data(id, |data, index| (data.to_vec(), index))?;
+    let mut font_data = FontData::from_owned(bytes);
+    font_data.index = index;
+    fonts.font_data.insert(key.clone(), font_data);
+    log::info!("Loaded system font '{}'.", family);
+    Some(key)
+}
+
+/// Draws pickers for the body, heading and monospace fonts. Returns `true` if
+/// anything changed and the fonts need to be re-applied.
+pub fn font_settings_ui(ui: &mut egui::Ui, settings: &mut FontSettings) -> bool {
+    let mut changed = false;
+    egui::Grid::new("font_settings_grid")
+        .num_columns(2)
+        .spacing([8.0, 4.0])
+        .show(ui, |ui| {
+            changed |= font_picker(ui, "Body", &mut settings.body);
+            changed |= font_picker(ui, "Headings", &mut settings.heading);
+            changed |= font_picker(ui, "Monospace", &mut settings.monospace);
+        });
+    if ui.button("Reset to defaults").clicked() {
+        changed |= *settings != FontSettings::default();
+        *settings = FontSettings::default();
+    }
+    changed
+}
+
+fn font_picker(ui: &mut egui::Ui, label: &str, choice: &mut FontChoice) -> bool {
+    let mut changed = false;
+    ui.label(label);
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source(("font_picker", label))
+            .selected_text(choice.label())
+            .width(200.0)
+            .show_ui(ui, |ui| {
+                changed |= ui
+                    .selectable_value(choice, FontChoice::Default, "Default")
+                    .changed();
+                let Some(system) = SYSTEM_FONTS.get() else {
+                    ui.label("Looking for installed fonts…");
+                    return;
+                };
+                for family in &system.families {
+                    changed |= ui
+                        .selectable_value(choice, FontChoice::System(family.clone()), family)
+                        .changed();
+                }
+            });
+        if ui
+            .button("📂")
+            .on_hover_text("Use a font file (.ttf, .otf, or the first font of a .ttc)")
+            .clicked()
+        {
+            if let Some(path) = FileDialog::new()
+                .add_filter("Fonts", &["ttf", "otf", "ttc"])
+                .pick_file()
+            {
+                *choice = FontChoice::File(path);
+                changed = true;
+            }
+        }
+    });
+    ui.end_row();
+    changed
+}
@@ -0,0 +1,201 @@
+//! YAML (`---`) and TOML (`+++`) front matter at the start of a document.
+
+use pulldown_cmark::MetadataBlockKind;
+
+/// A front matter value, independent of the format it was written in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Text(String),
+    List(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// A single-line rendering for the key/value view.
+    pub fn display(&self) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            Value::List(items) => items
+                .iter()
+                .map(Value::display)
+                .collect::<Vec<_>>()
+                .join(", "),
+            Value::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.display()))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+        }
+    }
+
+    /// Items of a list, or a comma-separated string split into items.
+    fn items(&self) -> Vec<String> {
+        match self {
+            Value::Text(text) => text
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Value::List(items) => items.iter().map(Value::display).collect(),
+            Value::Map(_) => vec![self.display()],
+        }
+    }
+
+    /// A person given as a plain string or as a map with a `name` key.
+    fn name(&self) -> String {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(key, _)| key == "name")
+                .map_or_else(|| self.display(), |(_, name)| name.display()),
+            Value::List(items) => items.iter().map(Value::name).collect::<Vec<_>>().join(", "),
+            Value::Text(text) => text.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FrontMatter {
+    /// Top-level keys in document order.
+    pub fields: Vec<(String, Value)>,
+    pub raw: String,
+    /// Set if the block couldn't be parsed; `fields` is empty then.
+    pub error: Option<String>,
+}
+
+impl FrontMatter {
+    pub fn parse(raw: &str, kind: MetadataBlockKind) -> Self {
+        let parsed = match kind {
+            MetadataBlockKind::YamlStyle => parse_yaml(raw),
+            MetadataBlockKind::PlusesStyle => parse_toml(raw),
+        };
+        match parsed {
+            Ok(fields) => Self {
+                fields,
+                raw: raw.to_string(),
+                error: None,
+            },
+            Err(error) => {
+                log::warn!("Failed to parse front matter: {}", error);
+                Self {
+                    fields: Vec::new(),
+                    raw: raw.to_string(),
+                    error: Some(error),
+                }
+            }
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.get("title")
+            .map(Value::display)
+            .filter(|title| !title.trim().is_empty())
+    }
+
+    pub fn author(&self) -> Option<String> {
+        self.get("author")
+            .or_else(|| self.get("authors"))
+            .map(Value::name)
+            .filter(|author| !author.is_empty())
+    }
+
+    pub fn date(&self) -> Option<String> {
+        self.get("date")
+            .map(Value::display)
+            .filter(|date| !date.is_empty())
+    }
+
+    /// Issue link template, see `autolink::LinkSettings`.
+    pub fn issue_url_template(&self) -> Option<String> {
+        self.get("issue_url")
+            .or_else(|| self.get("issue_url_template"))
+            .map(Value::display)
+            .filter(|template| !template.is_empty())
+    }
+
+    pub fn repository(&self) -> Option<String> {
+        self.get("repository")
+            .or_else(|| self.get("repo"))
+            .map(Value::display)
+            .filter(|repository| !repository.is_empty())
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        self.get("tags")
+            .or_else(|| self.get("keywords"))
+            .map(Value::items)
+            .unwrap_or_default()
+    }
+}
+
+fn parse_yaml(raw: &str) -> Result<Vec<(String, Value)>, String> {
+    match serde_yaml::from_str::<serde_yaml::Value>(raw).map_err(|e| e.to_string())? {
+        serde_yaml::Value::Mapping(mapping) => Ok(mapping
+            .into_iter()
+            .map(|(key, value)| (yaml_key(key), from_yaml(value)))
+            .collect()),
+        serde_yaml::Value::Null => Ok(Vec::new()),
+        _ => Err("front matter is not a set of key/value pairs".to_string()),
+    }
+}
+
+fn yaml_key(key: serde_yaml::Value) -> String {
+    match from_yaml(key) {
+        Value::Text(text) => text,
+        other => other.display(),
+    }
+}
+
+fn from_yaml(value: serde_yaml::Value) -> Value {
+    match value {
+        serde_yaml::Value::Null => Value::Text(String::new()),
+        serde_yaml::Value::Bool(b) => Value::Text(b.to_string()),
+        serde_yaml::Value::Number(n) => Value::Text(n.to_string()),
+        serde_yaml::Value::String(s) => Value::Text(s),
+        serde_yaml::Value::Sequence(items) => {
+            Value::List(items.into_iter().map(from_yaml).collect())
+        }
+        serde_yaml::Value::Mapping(mapping) => Value::Map(
+            mapping
+                .into_iter()
+                .map(|(key, value)| (yaml_key(key), from_yaml(value)))
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => from_yaml(tagged.value),
+    }
+}
+
+fn parse_toml(raw: &str) -> Result<Vec<(String, Value)>, String> {
+    let table: toml::Table = raw.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    Ok(table
+        .into_iter()
+        .map(|(key, value)| (key, from_toml(value)))
+        .collect())
+}
+
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::Text(s),
+        toml::Value::Integer(i) => Value::Text(i.to_string()),
+        toml::Value::Float(f) => Value::Text(f.to_string()),
+        toml::Value::Boolean(b) => Value::Text(b.to_string()),
+        toml::Value::Datetime(d) => Value::Text(d.to_string()),
+        toml::Value::Array(items) => Value::List(items.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Map(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, from_toml(value)))
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,264 @@
+//! Syntax definitions and code themes: syntect's defaults plus
+//! `.sublime-syntax` and `.tmTheme` files from the config directory.
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+use syntect::dumps::{dump_to_uncompressed_file, from_uncompressed_dump_file};
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+/// Bump when the dump format changes, e.g. after upgrading syntect.
+const CACHE_VERSION: u32 = 1;
+
+fn syntaxes_dir() -> Option<PathBuf> {
+    crate::config_dir().map(|dir| dir.join("syntaxes"))
+}
+
+fn themes_dir() -> Option<PathBuf> {
+    crate::config_dir().map(|dir| dir.join("themes"))
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    crate::config_dir().map(|dir| dir.join("cache"))
+}
+
+/// Every file under `dir` with the given extension, sorted.
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e == extension) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Changes whenever a syntax file is added, removed or edited.
+fn fingerprint(files: &[PathBuf]) -> String {
+    let mut hasher = DefaultHasher::new();
+    CACHE_VERSION.hash(&mut hasher);
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    for file in files {
+        file.hash(&mut hasher);
+        if let Ok(metadata) = fs::metadata(file) {
+            metadata.len().hash(&mut hasher);
+            let modified = metadata.modified().ok();
+            let since_epoch = modified.and_then(|m| m.duration_since(UNIX_EPOCH).ok());
+            since_epoch.map(|d| d.as_nanos()).hash(&mut hasher);
+        }
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// The default syntaxes plus the user's. Compiling syntax files is slow, so
+/// the combined set is cached as a binary dump until the files change.
+fn load_syntaxes() -> SyntaxSet {
+    let Some(dir) = syntaxes_dir().filter(|dir| dir.is_dir()) else {
+        return SyntaxSet::load_defaults_newlines();
+    };
+    let files = files_with_extension(&dir, "sublime-syntax");
+    if files.is_empty() {
+        return SyntaxSet::load_defaults_newlines();
+    }
+    let fingerprint = fingerprint(&files);
+    let cache = cache_dir().map(|dir| {
+        (
+            dir.join("syntaxes.packdump"),
+            dir.join("syntaxes.fingerprint"),
+        )
+    });
+    if let Some((dump, stamp)) = &cache {
+        if fs::read_to_string(stamp).is_ok_and(|s| s.trim() == fingerprint) {
+            match from_uncompressed_dump_file(dump) {
+                Ok(set) => {
+                    log::info!("Loaded syntaxes from {}.", dump.display());
+                    return set;
+                }
+                Err(e) => log::warn!("Ignoring syntax cache {}: {}", dump.display(), e),
+            }
+        }
+    }
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    if let Err(e) = builder.add_from_folder(&dir, true) {
+        log::error!("Failed to load syntaxes from {}: {}", dir.display(), e);
+        return SyntaxSet::load_defaults_newlines();
+    }
+    let set = builder.build();
+    log::info!(
+        "Loaded {} syntax files from {}.",
+        files.len(),
+        dir.display()
+    );
+    if let Some((dump, stamp)) = &cache {
+        let written = dump
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| dump_to_uncompressed_file(&set, dump).map_err(|e| e.to_string()))
+            .and_then(|_| fs::write(stamp, &fingerprint).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            log::warn!("Failed to cache syntaxes in {}: {}", dump.display(), e);
+        }
+    }
+    set
+}
+
+/// The default themes plus every `.tmTheme` in the themes directory, named
+/// after the file.
+fn load_themes() -> ThemeSet {
+    let mut set = ThemeSet::load_defaults();
+    let Some(dir) = themes_dir().filter(|dir| dir.is_dir()) else {
+        return set;
+    };
+    for file in files_with_extension(&dir, "tmTheme") {
+        let Some(name) = file.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        match ThemeSet::get_theme(&file) {
+            Ok(theme) => {
+                set.themes.insert(name, theme);
+            }
+            Err(e) => log::error!("Failed to load theme {}: {}", file.display(), e),
+        }
+    }
+    set
+}
+
+/// Loads syntaxes and themes on a background thread so they don't hold up
+/// the first frame, and repaints `ctx` once they're ready.
+pub fn load_in_background(ctx: egui::Context) {
+    let spawned = std::thread::Builder::new()
+        .name("syntect".to_string())
+        .spawn({
+            let ctx = ctx.clone();
+            move || {
+                THEMES.get_or_init(load_themes);
+                SYNTAXES.get_or_init(load_syntaxes);
+                log::info!("Syntax highlighting is ready.");
+                ctx.request_repaint();
+            }
+        });
+    if let Err(e) = spawned {
+        log::warn!("Loading syntaxes on the UI thread: {}", e);
+        THEMES.get_or_init(load_themes);
+        SYNTAXES.get_or_init(load_syntaxes);
+    }
+}
+
+/// `None` until `load_in_background` has finished.
+pub fn syntaxes() -> Option<&'static SyntaxSet> {
+    SYNTAXES.get()
+}
+
+/// `None` until `load_in_background` has loaded the themes.
+pub fn themes() -> Option<&'static ThemeSet> {
+    THEMES.get()
+}
+
+/// Code themes picked for dark and light mode, independently of the UI theme.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CodeThemes {
+    pub dark: String,
+    pub light: String,
+}
+
+impl Default for CodeThemes {
+    fn default() -> Self {
+        Self {
+            dark: "base16-ocean.dark".to_string(),
+            light: "base16-ocean.light".to_string(),
+        }
+    }
+}
+
+impl CodeThemes {
+    /// The theme for the current mode, falling back to the default when a
+    /// user theme has been removed.
+    pub fn get<'t>(&self, set: &'t ThemeSet, dark_mode: bool) -> &'t Theme {
+        let (name, default) = if dark_mode {
+            (&self.dark, Self::default().dark)
+        } else {
+            (&self.light, Self::default().light)
+        };
+        set.themes.get(name).unwrap_or_else(|| {
+            log::warn!("Syntax theme '{}' not found, falling back.", name);
+            &set.themes[&default]
+        })
+    }
+}
+
+/// The code theme for the current mode, looked up again only when the choice
+/// or the mode changes so that a missing theme is reported once.
+#[derive(Default)]
+pub struct ResolvedCodeTheme {
+    chosen: Option<(CodeThemes, bool)>,
+    theme: Option<&'static Theme>,
+}
+
+impl ResolvedCodeTheme {
+    /// `None` until `load_in_background` has loaded the themes.
+    pub fn get(&mut self, themes: &CodeThemes, dark_mode: bool) -> Option<&'static Theme> {
+        let set = self::themes()?;
+        let unchanged = self
+            .chosen
+            .as_ref()
+            .is_some_and(|(chosen, dark)| chosen == themes && *dark == dark_mode);
+        if !unchanged {
+            self.chosen = Some((themes.clone(), dark_mode));
+            self.theme = Some(themes.get(set, dark_mode));
+        }
+        self.theme
+    }
+}
+
+/// Menu contents for picking the code themes. Returns true if one changed.
+pub fn code_theme_ui(ui: &mut egui::Ui, themes: &mut CodeThemes, set: &ThemeSet) -> bool {
+    let before = themes.clone();
+    for (label, selected) in [
+        ("Dark mode", &mut themes.dark),
+        ("Light mode", &mut themes.light),
+    ] {
+        egui::ComboBox::from_label(label)
+            .selected_text(selected.as_str())
+            .width(200.0)
+            .show_ui(ui, |ui| {
+                for name in set.themes.keys() {
+                    ui.selectable_value(selected, name.clone(), name);
+                }
+            });
+    }
+    if let Some(dir) = themes_dir() {
+        ui.separator();
+        ui.set_max_width(320.0);
+        ui.label(
+            egui::RichText::new(format!(
+                "Add .tmTheme files to {} and .sublime-syntax files to {} and restart.",
+                dir.display(),
+                syntaxes_dir().unwrap_or_default().display()
+            ))
+            .small()
+            .weak(),
+        );
+    }
+    *themes != before
+}
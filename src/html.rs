@@ -0,0 +1,574 @@
+//! A small, forgiving HTML parser for the tags commonly found in READMEs.
+//!
+//! It only builds a tree; deciding which elements are rendered (and dropping
+//! everything else) is up to the renderer. Event handlers and script links
+//! never make it into the tree.
+
+/// Elements that never have children or an end tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements dropped together with their content.
+const DROPPED_ELEMENTS: &[&str] = &["script", "style", "template", "noscript", "iframe"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Start {
+        name: String,
+        attrs: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    End(String),
+    Text(String),
+}
+
+#[derive(Clone, Debug)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+    /// An end tag without a matching start tag in this fragment, e.g. the
+    /// `</details>` closing a block opened further up the document.
+    StrayEnd(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+    /// `false` if the fragment ended before the element's end tag.
+    pub closed: bool,
+}
+
+impl Element {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Concatenated text of all descendants, with whitespace collapsed.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        collect_text(&self.children, &mut text);
+        collapse_whitespace(&text).trim().to_string()
+    }
+}
+
+fn collect_text(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Element(element) if element.name == "br" => out.push('\n'),
+            Node::Element(element) => collect_text(&element.children, out),
+            Node::StrayEnd(_) => {}
+        }
+    }
+}
+
+pub fn is_void(name: &str) -> bool {
+    VOID_ELEMENTS.contains(&name)
+}
+
+pub fn is_dropped(name: &str) -> bool {
+    DROPPED_ELEMENTS.contains(&name)
+}
+
+/// Splits `html` into tags and text. Comments, doctypes and processing
+/// instructions are skipped, as is the content of dropped elements.
+pub fn tokenize(html: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            text.push_str(rest);
+            break;
+        };
+        text.push_str(&rest[..lt]);
+        rest = &rest[lt..];
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+        let Some((token, consumed)) = parse_tag(rest) else {
+            text.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        if !text.is_empty() {
+            tokens.push(Token::Text(decode_entities(&std::mem::take(&mut text))));
+        }
+        rest = &rest[consumed..];
+        if let Token::Start {
+            name,
+            self_closing: false,
+            ..
+        } = &token
+        {
+            if is_dropped(name) {
+                // Skip to the matching end tag without tokenizing the content.
+                let close = format!("</{}", name);
+                rest = find_ignore_ascii_case(rest, &close)
+                    .and_then(|start| rest[start..].find('>').map(|end| start + end + 1))
+                    .map_or("", |end| &rest[end..]);
+                continue;
+            }
+        }
+        tokens.push(token);
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(decode_entities(&text)));
+    }
+    tokens
+}
+
+fn find_ignore_ascii_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Parses a start or end tag at the beginning of `input`, returning the token
+/// and the number of bytes consumed.
+fn parse_tag(input: &str) -> Option<(Token, usize)> {
+    let bytes = input.as_bytes();
+    let mut pos = 1;
+    let is_end = bytes.get(pos) == Some(&b'/');
+    if is_end {
+        pos += 1;
+    }
+    if !bytes.get(pos)?.is_ascii_alphabetic() {
+        return None;
+    }
+    let name_start = pos;
+    while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'-') {
+        pos += 1;
+    }
+    let name = input[name_start..pos].to_ascii_lowercase();
+    let mut attrs = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        match bytes.get(pos)? {
+            b'>' => {
+                pos += 1;
+                break;
+            }
+            b'/' => {
+                self_closing = true;
+                pos += 1;
+            }
+            _ => {
+                let attr_start = pos;
+                while pos < bytes.len()
+                    && !bytes[pos].is_ascii_whitespace()
+                    && !matches!(bytes[pos], b'=' | b'>' | b'/')
+                {
+                    pos += 1;
+                }
+                let attr_name = input[attr_start..pos].to_ascii_lowercase();
+                while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                    pos += 1;
+                }
+                let mut value = String::new();
+                if bytes.get(pos) == Some(&b'=') {
+                    pos += 1;
+                    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                        pos += 1;
+                    }
+                    match bytes.get(pos)? {
+                        quote @ (b'"' | b'\'') => {
+                            let end = input[pos + 1..].find(*quote as char)? + pos + 1;
+                            value = decode_entities(&input[pos + 1..end]);
+                            pos = end + 1;
+                        }
+                        _ => {
+                            let value_start = pos;
+                            while pos < bytes.len()
+                                && !bytes[pos].is_ascii_whitespace()
+                                && bytes[pos] != b'>'
+                            {
+                                pos += 1;
+                            }
+                            value = decode_entities(&input[value_start..pos]);
+                        }
+                    }
+                }
+                if !attr_name.is_empty() && !is_unsafe_attr(&attr_name, &value) {
+                    attrs.push((attr_name, value));
+                }
+            }
+        }
+    }
+    let token = if is_end {
+        Token::End(name)
+    } else {
+        Token::Start {
+            name,
+            attrs,
+            self_closing,
+        }
+    };
+    Some((token, pos))
+}
+
+/// `onclick` and friends, and links that would run a script when opened.
+fn is_unsafe_attr(name: &str, value: &str) -> bool {
+    if name.starts_with("on") {
+        return true;
+    }
+    if !matches!(name, "href" | "src") {
+        return false;
+    }
+    let Some((scheme, _)) = value.split_once(':') else {
+        return false;
+    };
+    // Browsers ignore whitespace and control characters inside the scheme.
+    let scheme: String = scheme
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+    ["javascript", "vbscript"]
+        .iter()
+        .any(|unsafe_scheme| scheme.eq_ignore_ascii_case(unsafe_scheme))
+        || (name == "href" && scheme.eq_ignore_ascii_case("data"))
+}
+
+/// Builds a tree from `html`. Unclosed elements are closed at the end of the
+/// fragment and unmatched end tags are kept as [`Node::StrayEnd`].
+pub fn parse_fragment(html: &str) -> Vec<Node> {
+    // The bottom of the stack collects the top-level nodes.
+    let mut stack: Vec<Element> = vec![Element {
+        name: String::new(),
+        attrs: Vec::new(),
+        children: Vec::new(),
+        closed: true,
+    }];
+    for token in tokenize(html) {
+        match token {
+            Token::Text(text) => stack.last_mut().unwrap().children.push(Node::Text(text)),
+            Token::Start {
+                name,
+                attrs,
+                self_closing,
+            } => {
+                let element = Element {
+                    closed: true,
+                    children: Vec::new(),
+                    attrs,
+                    name,
+                };
+                if self_closing || is_void(&element.name) {
+                    stack
+                        .last_mut()
+                        .unwrap()
+                        .children
+                        .push(Node::Element(element));
+                } else {
+                    stack.push(element);
+                }
+            }
+            Token::End(name) => {
+                match stack.iter().skip(1).rposition(|e| e.name == name) {
+                    Some(index) => {
+                        // Implicitly close anything opened inside the element.
+                        while stack.len() > index + 1 {
+                            let element = stack.pop().unwrap();
+                            stack
+                                .last_mut()
+                                .unwrap()
+                                .children
+                                .push(Node::Element(element));
+                        }
+                    }
+                    None if is_void(&name) => {}
+                    None => stack
+                        .last_mut()
+                        .unwrap()
+                        .children
+                        .push(Node::StrayEnd(name)),
+                }
+            }
+        }
+    }
+    while stack.len() > 1 {
+        let mut element = stack.pop().unwrap();
+        element.closed = false;
+        stack
+            .last_mut()
+            .unwrap()
+            .children
+            .push(Node::Element(element));
+    }
+    stack.pop().unwrap().children
+}
+
+/// Replaces runs of whitespace with a single space, as HTML rendering does.
+pub fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last_was_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_was_space {
+                out.push(' ');
+            }
+            last_was_space = true;
+        } else {
+            out.push(c);
+            last_was_space = false;
+        }
+    }
+    out
+}
+
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| {
+                let entity = &rest[1..end + 1];
+                let c = match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some('\u{a0}'),
+                    "copy" => Some('©'),
+                    "reg" => Some('®'),
+                    "trade" => Some('™'),
+                    "hellip" => Some('…'),
+                    "mdash" => Some('—'),
+                    "ndash" => Some('–'),
+                    "middot" => Some('·'),
+                    "times" => Some('×'),
+                    "larr" => Some('←'),
+                    "rarr" => Some('→'),
+                    "uarr" => Some('↑'),
+                    "darr" => Some('↓'),
+                    _ => {
+                        let number = entity.strip_prefix('#')?;
+                        let code = match number.strip_prefix(['x', 'X']) {
+                            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                            None => number.parse().ok()?,
+                        };
+                        char::from_u32(code)
+                    }
+                }?;
+                Some((c, end + 2))
+            });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(name: &str, attrs: &[(&str, &str)]) -> Token {
+        Token::Start {
+            name: name.to_string(),
+            attrs: attrs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            self_closing: false,
+        }
+    }
+
+    fn text(text: &str) -> Token {
+        Token::Text(text.to_string())
+    }
+
+    /// The tree as a compact string: elements as `name(children)`, with `!`
+    /// after the name of unclosed ones, and stray end tags as `</name>`.
+    fn outline(nodes: &[Node]) -> String {
+        nodes
+            .iter()
+            .map(|node| match node {
+                Node::Text(text) => format!("{:?}", text),
+                Node::StrayEnd(name) => format!("</{}>", name),
+                Node::Element(e) => format!(
+                    "{}{}({})",
+                    e.name,
+                    if e.closed { "" } else { "!" },
+                    outline(&e.children)
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize("<P Class=intro>Hi<br/><!-- note --><!DOCTYPE html>&amp;</p>"),
+            [
+                start("p", &[("class", "intro")]),
+                text("Hi"),
+                Token::Start {
+                    name: "br".to_string(),
+                    attrs: Vec::new(),
+                    self_closing: true,
+                },
+                text("&"),
+                Token::End("p".to_string()),
+            ]
+        );
+        // Not a tag, so it stays as text.
+        assert_eq!(tokenize("a < b <3"), [text("a < b <3")]);
+        assert_eq!(
+            tokenize("<img alt='a > b' src=x.png>"),
+            [start("img", &[("alt", "a > b"), ("src", "x.png")])]
+        );
+    }
+
+    #[test]
+    fn dropped_elements() {
+        assert_eq!(
+            tokenize("a<script>if (x < 1) { y = '</p>'; }</script>b"),
+            [text("a"), text("b")]
+        );
+        assert_eq!(
+            tokenize("<STYLE>p { color: red }</Style><iframe src=x></iframe>c"),
+            [text("c")]
+        );
+        // An unclosed script swallows the rest.
+        assert_eq!(tokenize("a<script>alert(1)<p>b"), [text("a")]);
+        assert_eq!(
+            outline(&parse_fragment("<div><script>x</script></div>")),
+            "div()"
+        );
+    }
+
+    #[test]
+    fn unsafe_attributes() {
+        assert_eq!(
+            tokenize(r#"<a href="page.html" onclick="steal()" ONMOUSEOVER=x title=t>"#),
+            [start("a", &[("href", "page.html"), ("title", "t")])]
+        );
+        for href in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            " java\tscript:alert(1)",
+            "&#106;avascript:alert(1)",
+            "vbscript:msgbox",
+            "data:text/html,<script>",
+        ] {
+            let html = format!("<a href=\"{}\">x</a>", href);
+            let nodes = parse_fragment(&html);
+            let Node::Element(a) = &nodes[0] else {
+                panic!("{:?}", nodes);
+            };
+            assert_eq!(a.attr("href"), None, "{}", href);
+        }
+        assert_eq!(
+            tokenize("<img src=javascript:x><a href=javascript.html><a href=https://a.b/c:d>"),
+            [
+                start("img", &[]),
+                start("a", &[("href", "javascript.html")]),
+                start("a", &[("href", "https://a.b/c:d")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn implicit_closing() {
+        assert_eq!(
+            outline(&parse_fragment("<p>a<b>bold<i>both</p>c")),
+            r#"p("a" b("bold" i("both"))) "c""#
+        );
+        assert_eq!(
+            outline(&parse_fragment(
+                "<details><summary>More</summary><ul><li>one"
+            )),
+            r#"details!(summary("More") ul!(li!("one")))"#
+        );
+        assert_eq!(
+            outline(&parse_fragment("a<br>b</br><img src=x.png></img>")),
+            r#""a" br() "b" img()"#
+        );
+    }
+
+    #[test]
+    fn stray_end_tags() {
+        assert_eq!(
+            outline(&parse_fragment("done</details>\n<p>after")),
+            r#""done" </details> "\n" p!("after")"#
+        );
+        assert_eq!(
+            outline(&parse_fragment("<div>a</span>b</div>")),
+            r#"div("a" </span> "b")"#
+        );
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(decode_entities("a &lt;b&gt; &amp;&amp; c"), "a <b> && c");
+        assert_eq!(decode_entities("&#65;&#x42;&#X43;&#128512;"), "ABC😀");
+        assert_eq!(decode_entities("&copy;&nbsp;&hellip;"), "©\u{a0}…");
+        // Unknown, unterminated and invalid entities are left as they are.
+        for text in [
+            "&bogus;",
+            "AT&T",
+            "a & b",
+            "&amp",
+            "&#;",
+            "&#x;",
+            "&#xD800;",
+            "&#99999999;",
+            "&#12a;",
+            "&averyverylongname;",
+        ] {
+            assert_eq!(decode_entities(text), text);
+        }
+        assert_eq!(decode_entities("&&amp;;"), "&&;");
+        assert_eq!(
+            tokenize("<a title=\"&quot;hi&quot;\">x &gt; y</a>"),
+            [
+                start("a", &[("title", "\"hi\"")]),
+                text("x > y"),
+                Token::End("a".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn element_text() {
+        let nodes = parse_fragment("<p>  Hello\n  <b>big</b><br>world  </p>");
+        let Node::Element(p) = &nodes[0] else {
+            panic!("{:?}", nodes);
+        };
+        assert_eq!(p.text(), "Hello big world");
+    }
+}
@@ -0,0 +1,492 @@
+//! Keyboard commands for reading without the mouse, and the keymap that
+//! binds them, loaded from `keymap.toml` in the config directory and
+//! reloaded when it changes.
+
+use eframe::egui::{self, Event, Key, Modifiers};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::SystemTime;
+
+/// Longest pause between the keys of a sequence like `] ]`.
+const SEQUENCE_TIMEOUT: f64 = 1.5;
+
+const KEYMAP_FILE: &str = "keymap.toml";
+
+/// Written when the keymap file is first created, to be edited.
+const TEMPLATE: &str = r#"# Keys for reading with the keyboard. Each command takes a list of keys, and
+# commands left out keep their default keys.
+#
+# A key is a character, like "j" or "G", or a key name: Space, Enter, Tab,
+# Escape, Home, End, PageUp, PageDown, ArrowUp, ArrowDown, ArrowLeft,
+# ArrowRight or F1 to F20. Names and letters can take Ctrl+, Alt+, Shift+ and
+# Cmd+ (Ctrl on Windows and Linux). Separate the keys of a sequence with
+# spaces, like "g g".
+#
+# Emacs users might prefer:
+# page_down = ["Ctrl+V", "Space"]
+# page_up = ["Alt+V", "Shift+Space"]
+# line_down = ["Ctrl+N"]
+# line_up = ["Ctrl+P"]
+
+page_down = ["Space", "PageDown"]
+page_up = ["Shift+Space", "PageUp"]
+top = ["Home", "g g"]
+bottom = ["End", "G"]
+line_down = ["j", "ArrowDown"]
+line_up = ["k", "ArrowUp"]
+next_heading = ["] ]"]
+previous_heading = ["[ ["]
+next_link = ["Tab"]
+previous_link = ["Shift+Tab"]
+follow_link = ["Enter"]
+"#;
+
+/// Something the keyboard can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    LineDown,
+    LineUp,
+    NextHeading,
+    PreviousHeading,
+    NextLink,
+    PreviousLink,
+    FollowLink,
+}
+
+impl Command {
+    fn label(self) -> &'static str {
+        match self {
+            Self::PageDown => "Page down",
+            Self::PageUp => "Page up",
+            Self::Top => "Go to the top",
+            Self::Bottom => "Go to the bottom",
+            Self::LineDown => "Scroll down",
+            Self::LineUp => "Scroll up",
+            Self::NextHeading => "Next heading",
+            Self::PreviousHeading => "Previous heading",
+            Self::NextLink => "Focus the next link",
+            Self::PreviousLink => "Focus the previous link",
+            Self::FollowLink => "Follow the focused link",
+        }
+    }
+}
+
+/// One key of a sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stroke {
+    /// A typed character, whatever keys it took on the user's layout.
+    Char(char),
+    /// A named key or a shortcut with Ctrl, Alt or Cmd.
+    Key(Key, Modifiers),
+}
+
+impl Stroke {
+    fn parse(text: &str) -> Result<Self, String> {
+        // The last part is the key itself, which may be a `+`.
+        let (prefix, name) = match text.strip_suffix('+') {
+            Some(prefix) if prefix.is_empty() || prefix.ends_with('+') => (prefix, "+"),
+            _ => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+        let mut modifiers = Modifiers::NONE;
+        for modifier in prefix.split('+').filter(|m| !m.is_empty()) {
+            modifiers = modifiers
+                | match modifier.to_lowercase().as_str() {
+                    "ctrl" | "control" => Modifiers::CTRL,
+                    "alt" | "meta" | "option" => Modifiers::ALT,
+                    "shift" => Modifiers::SHIFT,
+                    "cmd" | "command" => Modifiers::COMMAND,
+                    _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, text)),
+                };
+        }
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            // Shift is part of the character, like `G`.
+            if !modifiers.alt && !modifiers.ctrl && !modifiers.command {
+                return Ok(Self::Char(c));
+            }
+        }
+        let key = Key::from_name(name).ok_or_else(|| format!("unknown key '{}'", name))?;
+        Ok(Self::Key(key, modifiers))
+    }
+
+    fn matches(self, other: Self) -> bool {
+        match (self, other) {
+            (Self::Char(a), Self::Char(b)) => a == b,
+            (Self::Key(a, pattern), Self::Key(b, pressed)) => {
+                a == b && pressed.matches_exact(pattern)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses a sequence like `g g` or `Ctrl+V`.
+fn parse_sequence(text: &str) -> Result<Vec<Stroke>, String> {
+    let strokes = text
+        .split_whitespace()
+        .map(Stroke::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    if strokes.is_empty() {
+        return Err("empty key".to_string());
+    }
+    Ok(strokes)
+}
+
+/// The key sequences for each command in a keymap file.
+fn parse_keymap(text: &str) -> Result<BTreeMap<Command, Vec<Vec<Stroke>>>, String> {
+    let commands: BTreeMap<Command, Vec<String>> =
+        toml::from_str(text).map_err(|e| e.message().trim().replace('\n', ", "))?;
+    commands
+        .into_iter()
+        .map(|(command, keys)| {
+            let sequences = keys.iter().map(|key| parse_sequence(key));
+            Ok((command, sequences.collect::<Result<_, String>>()?))
+        })
+        .collect()
+}
+
+fn bindings(commands: BTreeMap<Command, Vec<Vec<Stroke>>>) -> Vec<(Vec<Stroke>, Command)> {
+    commands
+        .into_iter()
+        .flat_map(|(command, sequences)| sequences.into_iter().map(move |keys| (keys, command)))
+        .collect()
+}
+
+fn keymap_path() -> Option<PathBuf> {
+    crate::config_dir().map(|dir| dir.join(KEYMAP_FILE))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The key bindings and the keys pressed so far of a sequence.
+pub struct Keymap {
+    bindings: Vec<(Vec<Stroke>, Command)>,
+    /// Whether the file has been loaded.
+    loaded: bool,
+    /// Sent to when the file changes.
+    changes: Option<Receiver<()>>,
+    pending: Vec<Stroke>,
+    pending_since: f64,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: bindings(parse_keymap(TEMPLATE).expect("default keymap parses")),
+            loaded: false,
+            changes: None,
+            pending: Vec::new(),
+            pending_since: 0.0,
+        }
+    }
+}
+
+impl Keymap {
+    /// Loads the keymap file on the first call and reloads it whenever it's
+    /// saved. Returns a status message if it was reloaded or has errors.
+    pub fn poll(&mut self, ctx: &egui::Context) -> Option<String> {
+        let first = !self.loaded;
+        let changed = self
+            .changes
+            .as_ref()
+            .is_some_and(|changes| changes.try_iter().count() > 0);
+        if !first && !changed {
+            return None;
+        }
+        self.loaded = true;
+        let path = keymap_path()?;
+        if first {
+            let watched = path.clone();
+            self.changes = crate::watch::changes("keymap watch", ctx, modified(&path), move || {
+                modified(&watched)
+            });
+            // The defaults stay unless the file exists.
+            if !path.exists() {
+                return None;
+            }
+        }
+        let mut keymap = Self::default();
+        let result = match fs::read_to_string(&path) {
+            Ok(text) => parse_keymap(&text),
+            // Removing the file restores the defaults.
+            Err(_) => Ok(BTreeMap::new()),
+        };
+        match result {
+            Ok(commands) => {
+                // Keys in the file replace the defaults for their commands.
+                keymap
+                    .bindings
+                    .retain(|(_, command)| !commands.contains_key(command));
+                keymap.bindings.extend(bindings(commands));
+                self.bindings = keymap.bindings;
+                log::info!("Loaded keymap from {}.", path.display());
+                (!first).then(|| "Keymap reloaded.".to_string())
+            }
+            Err(e) => {
+                log::error!("Failed to load keymap {}: {}", path.display(), e);
+                Some(format!("Keymap error in {}: {}", path.display(), e))
+            }
+        }
+    }
+
+    /// Takes the key presses that make up commands out of `events`, returning
+    /// the commands. Keys of an unfinished sequence are held back too.
+    pub fn take_commands(&mut self, events: &mut Vec<Event>, now: f64) -> Vec<Command> {
+        if now - self.pending_since > SEQUENCE_TIMEOUT {
+            self.pending.clear();
+        }
+        let mut commands = Vec::new();
+        events.retain(|event| {
+            let stroke = match event {
+                Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => Stroke::Key(*key, *modifiers),
+                Event::Text(text) => {
+                    let mut chars = text.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => Stroke::Char(c),
+                        _ => return true,
+                    }
+                }
+                _ => return true,
+            };
+            match self.feed(stroke) {
+                Some(Fed::Command(command)) => {
+                    commands.push(command);
+                    false
+                }
+                Some(Fed::Pending) => {
+                    self.pending_since = now;
+                    false
+                }
+                None => true,
+            }
+        });
+        commands
+    }
+
+    fn feed(&mut self, stroke: Stroke) -> Option<Fed> {
+        let bound = |s: &Stroke| s.matches(stroke);
+        if !self.bindings.iter().any(|(keys, _)| keys.iter().any(bound)) {
+            // A character nobody uses breaks a sequence; the key events that
+            // come with typed characters don't.
+            if matches!(stroke, Stroke::Char(_)) {
+                self.pending.clear();
+            }
+            return None;
+        }
+        let mut keys = std::mem::take(&mut self.pending);
+        keys.push(stroke);
+        loop {
+            let starts_with = |binding: &[Stroke]| {
+                binding.len() >= keys.len() && keys.iter().zip(binding).all(|(k, b)| b.matches(*k))
+            };
+            if let Some((_, command)) = self
+                .bindings
+                .iter()
+                .find(|(binding, _)| binding.len() == keys.len() && starts_with(binding))
+            {
+                return Some(Fed::Command(*command));
+            }
+            if self
+                .bindings
+                .iter()
+                .any(|(binding, _)| starts_with(binding))
+            {
+                self.pending = keys;
+                return Some(Fed::Pending);
+            }
+            if keys.len() == 1 {
+                return None;
+            }
+            // Start over from this key.
+            keys = vec![stroke];
+        }
+    }
+}
+
+enum Fed {
+    Command(Command),
+    Pending,
+}
+
+/// Settings window contents: the bindings and a button to edit them.
+/// Returns a status message once the button opened the keymap file.
+pub fn keymap_ui(ui: &mut egui::Ui, keymap: &Keymap) -> Option<String> {
+    let mut commands: BTreeMap<Command, Vec<String>> = BTreeMap::new();
+    for (keys, command) in &keymap.bindings {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|stroke| match stroke {
+                Stroke::Char(' ') => "Space".to_string(),
+                Stroke::Char(c) => c.to_string(),
+                Stroke::Key(key, modifiers) => {
+                    let mut name = String::new();
+                    for (on, prefix) in [
+                        (modifiers.ctrl || modifiers.command, "Ctrl+"),
+                        (modifiers.alt, "Alt+"),
+                        (modifiers.shift, "Shift+"),
+                    ] {
+                        if on {
+                            name.push_str(prefix);
+                        }
+                    }
+                    name.push_str(key.name());
+                    name
+                }
+            })
+            .collect();
+        commands.entry(*command).or_default().push(keys.join(" "));
+    }
+    egui::Grid::new("keymap_grid")
+        .num_columns(2)
+        .spacing([12.0, 2.0])
+        .show(ui, |ui| {
+            for (command, keys) in &commands {
+                ui.label(command.label());
+                ui.label(egui::RichText::new(keys.join(", ")).monospace());
+                ui.end_row();
+            }
+        });
+    let path = keymap_path()?;
+    let exists = path.exists();
+    let label = if exists {
+        "📝 Edit Keymap File"
+    } else {
+        "📄 New Keymap File"
+    };
+    let mut message = None;
+    if ui
+        .button(label)
+        .on_hover_text(path.display().to_string())
+        .clicked()
+    {
+        let created = if exists {
+            Ok(())
+        } else {
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::write(&path, TEMPLATE))
+        };
+        message = Some(match created.and_then(|()| open::that(&path)) {
+            Ok(()) => format!("Editing {}. Changes apply when it's saved.", path.display()),
+            Err(e) => format!("Failed to open {}: {}", path.display(), e),
+        });
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        parse_keymap(text).expect_err("keymap should not parse")
+    }
+
+    fn text(t: &str) -> Event {
+        Event::Text(t.to_string())
+    }
+
+    fn key(key: Key, modifiers: Modifiers) -> Event {
+        Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn strokes() {
+        assert_eq!(Stroke::parse("g"), Ok(Stroke::Char('g')));
+        assert_eq!(Stroke::parse("Shift+G"), Ok(Stroke::Char('G')));
+        assert_eq!(Stroke::parse("+"), Ok(Stroke::Char('+')));
+        assert_eq!(
+            Stroke::parse("Ctrl++"),
+            Ok(Stroke::Key(Key::Plus, Modifiers::CTRL))
+        );
+        assert_eq!(
+            Stroke::parse("ctrl+alt+v"),
+            Ok(Stroke::Key(Key::V, Modifiers::CTRL | Modifiers::ALT))
+        );
+        assert_eq!(
+            Stroke::parse("Shift+Space"),
+            Ok(Stroke::Key(Key::Space, Modifiers::SHIFT))
+        );
+        assert_eq!(
+            parse_sequence("g g"),
+            Ok(vec![Stroke::Char('g'), Stroke::Char('g')])
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            error(r#"top = ["Hyper+x"]"#),
+            "unknown modifier 'Hyper' in 'Hyper+x'"
+        );
+        assert_eq!(error(r#"top = ["Ctrl+Nope"]"#), "unknown key 'Nope'");
+        assert_eq!(error(r#"top = [" "]"#), "empty key");
+        assert!(error(r#"topp = ["x"]"#).contains("unknown variant `topp`"));
+        assert!(error(r#"top = "x""#).contains("expected a sequence"));
+        assert!(error("top = [").contains("invalid array"));
+    }
+
+    #[test]
+    fn file_replaces_defaults() {
+        let mut keymap = Keymap::default();
+        keymap
+            .bindings
+            .retain(|(_, command)| *command != Command::PageDown);
+        keymap
+            .bindings
+            .extend(bindings(parse_keymap(r#"page_down = ["Ctrl+V"]"#).unwrap()));
+        let mut events = vec![
+            key(Key::Space, Modifiers::NONE),
+            text(" "),
+            key(Key::V, Modifiers::CTRL),
+        ];
+        assert_eq!(keymap.take_commands(&mut events, 0.0), [Command::PageDown]);
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn sequences() {
+        let mut keymap = Keymap::default();
+        let mut events = vec![text("g")];
+        assert!(keymap.take_commands(&mut events, 0.0).is_empty());
+        // The first key is held back while the sequence may go on.
+        assert!(events.is_empty());
+        let mut events = vec![text("g")];
+        assert_eq!(keymap.take_commands(&mut events, 1.0), [Command::Top]);
+        // Too slow, so the sequence starts over.
+        let mut events = vec![text("g")];
+        keymap.take_commands(&mut events, 2.0);
+        let mut events = vec![text("g")];
+        assert!(keymap.take_commands(&mut events, 10.0).is_empty());
+        // Unbound characters break a sequence and are left alone.
+        let mut events = vec![text("x"), text("]"), text("x"), text("]")];
+        assert!(keymap.take_commands(&mut events, 20.0).is_empty());
+        assert_eq!(events.len(), 2);
+        let mut events = vec![text("]"), text("]"), text("G")];
+        assert_eq!(
+            keymap.take_commands(&mut events, 30.0),
+            [Command::NextHeading, Command::Bottom]
+        );
+    }
+}
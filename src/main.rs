@@ -1,37 +1,66 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+mod autolink;
+mod fonts;
+mod front_matter;
+mod highlighting;
+mod html;
+mod keymap;
+mod math;
+mod mermaid;
+mod quick_open;
+mod reading;
+mod search;
+mod session;
+mod settings;
+mod theme;
+mod watch;
+mod wiki;
+mod workspace;
+
+use autolink::LinkSettings;
 use eframe::{egui, App, NativeOptions};
 use egui::{
-    text::LayoutJob, Align, Color32, FontId, Frame, Image, Layout, Margin, RichText, Rounding,
-    ScrollArea, Separator, Stroke, TextFormat, ViewportBuilder,
+    text::LayoutJob, Align, Color32, CursorIcon, FontId, Frame, Galley, Image, Layout, Margin,
+    Pos2, Rect, Response, RichText, Rounding, ScrollArea, Sense, Separator, Stroke, TextFormat,
+    ViewportBuilder,
 };
 #[allow(deprecated)] // Allow RetainedImage for now
 use egui_extras::RetainedImage;
+use front_matter::FrontMatter;
 use lazy_static::lazy_static;
 use open; // Keep open
-use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Parser, Tag, TagEnd};
 use rfd::FileDialog;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
-use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style as SyntectStyle, ThemeSet};
-use syntect::parsing::SyntaxSet;
+use syntect::highlighting::Style as SyntectStyle;
 use syntect::util::LinesWithEndings;
 #[cfg(windows)]
 use winreg::{enums::HKEY_CURRENT_USER, RegKey};
 
 lazy_static! {
-    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
-    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
     #[allow(deprecated)] // Allow RetainedImage for now
     static ref IMAGE_CACHE: Mutex<HashMap<String, RetainedImage>> = Mutex::new(HashMap::new());
 }
 
 const CODE_FONT_SIZE: f32 = 13.0;
 const BODY_FONT_SIZE: f32 = 14.0;
+const APP_NAME: &str = "Markdown Viewer";
+const MAX_RECENT_FILES: usize = 20;
+/// How far a line up or down scrolls from the keyboard.
+const LINE_SCROLL: f32 = 40.0;
+
+/// Where user syntaxes, themes and caches live; next to eframe's saved state.
+fn config_dir() -> Option<PathBuf> {
+    eframe::storage_dir(APP_NAME)
+}
 
 #[cfg(windows)]
 fn hide_console() {
@@ -56,9 +85,34 @@ struct MarkdownViewerApp {
     markdown: String,
     file_path: Option<PathBuf>,
     status_message: Option<(String, f64)>,
-    dark_mode: bool,
+    settings: settings::Settings,
+    config: settings::Config, // Where `settings` are saved
+    show_settings: bool,
+    themes: theme::Themes,
+    code_theme: highlighting::ResolvedCodeTheme, // From settings.code_themes
     last_modified: Option<SystemTime>,
-    scroll_offset: Option<f32>, // Store absolute Y offset
+    scroll_offset: Option<f32>,   // Store absolute Y offset
+    pending_link: Option<String>, // Clicked link awaiting confirmation
+    keymap: keymap::Keymap,
+    key_commands: Vec<keymap::Command>, // Typed since the last frame
+    link_focus: LinkFocus,              // Link picked with the keyboard
+    view_height: f32,                   // Of the document's scroll area, last frame
+    vault: Option<Arc<wiki::Vault>>,    // Files next to the open one, for wiki links
+    pending_vault: Option<Receiver<(Arc<wiki::Vault>, Vec<wiki::Backlink>)>>, // Scanning
+    backlinks: Vec<wiki::Backlink>,     // Notes in the vault linking to the open one
+    show_backlinks: bool,
+    search: search::SearchPanel,
+    show_search: bool,
+    search_highlight: Option<search::Highlight>, // Set when opened from a search result
+    workspace: Option<workspace::Workspace>,     // Folder shown in the file tree
+    recent_files: Vec<PathBuf>,                  // Most recent first
+    session: session::Session,                   // Documents opened so far, for restoring
+    layout: reading::Layout,                     // Heading positions as of the last frame
+    resume: Option<reading::ReadingPosition>,    // Offered when reopening a document
+    pending_resume: Option<reading::ReadingPosition>, // Scrolled to once laid out
+    quick_open: Option<quick_open::QuickOpen>,   // Open while the Ctrl+P overlay shows
+    title: Option<String>,                       // From the document's front matter
+    window_title: Option<String>,                // Last title sent to the window
 }
 
 impl MarkdownViewerApp {
@@ -69,12 +123,13 @@ impl MarkdownViewerApp {
                 let modified = metadata.modified().ok();
                 match fs::read_to_string(&path) {
                     Ok(content) => Self {
+                        title: front_matter_title(&content),
                         markdown: content,
                         file_path: Some(path),
                         status_message: Some(("File loaded.".to_string(), current_time())),
-                        dark_mode: true,
                         last_modified: modified,
                         scroll_offset: None, // Reset scroll on new file
+                        ..Default::default()
                     },
                     Err(e) => {
                         log::error!("Failed to read file {}: {}", path.display(), e);
@@ -93,7 +148,6 @@ impl MarkdownViewerApp {
         log::info!("Loading default content.");
         Self {
             markdown: String::from(DEFAULT_MARKDOWN),
-            dark_mode: true,
             ..Default::default()
         }
     }
@@ -101,16 +155,133 @@ impl MarkdownViewerApp {
     fn error(message: String) -> Self {
         Self {
             status_message: Some((message, current_time())),
-            dark_mode: true,
             ..Default::default()
         }
     }
 
+    /// Swaps in the document held by `next`, keeping app-wide preferences.
+    /// The folder of the previous file is reused if the new one shares it.
+    fn replace_document(&mut self, next: Self) {
+        let previous_vault = self.vault.take();
+        *self = Self {
+            settings: std::mem::take(&mut self.settings),
+            config: std::mem::take(&mut self.config),
+            show_settings: self.show_settings,
+            themes: std::mem::take(&mut self.themes),
+            code_theme: std::mem::take(&mut self.code_theme),
+            keymap: std::mem::take(&mut self.keymap),
+            show_backlinks: self.show_backlinks,
+            search: std::mem::take(&mut self.search),
+            show_search: self.show_search,
+            workspace: self.workspace.take(),
+            recent_files: std::mem::take(&mut self.recent_files),
+            session: std::mem::take(&mut self.session),
+            window_title: self.window_title.take(),
+            ..next
+        };
+        if let (Some(path), None, None) = (&self.file_path, &self.vault, &self.pending_vault) {
+            let dir = path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            self.pending_vault = wiki::Vault::load_in_background(dir, path, previous_vault);
+        }
+    }
+
+    fn open_file(&mut self, path: PathBuf) {
+        self.remember_position();
+        self.replace_document(Self::new_from_file(path));
+        self.remember_recent_file();
+        if let Some(path) = &self.file_path {
+            self.scroll_offset = Some(0.0);
+            self.resume = self
+                .session
+                .position(&wiki::absolute(path))
+                .filter(|position| !position.is_start())
+                .cloned();
+        }
+    }
+
+    /// Records where the open file has been read up to, for when it's opened
+    /// again. Until it has been scrolled, the previous position is kept.
+    fn remember_position(&mut self) {
+        if self.resume.is_some() || self.pending_resume.is_some() || self.layout.height <= 0.0 {
+            return;
+        }
+        if let (Some(path), Some(offset)) = (&self.file_path, self.scroll_offset) {
+            let position = reading::ReadingPosition::at(&self.layout, offset);
+            self.session.remember(wiki::absolute(path), position);
+        }
+    }
+
+    /// Takes over the reading positions of the last session. With `reopen`,
+    /// also reopens its folder and document, skipping any that have since
+    /// been moved or deleted.
+    fn restore_session(&mut self, mut session: session::Session, reopen: bool) {
+        if !reopen {
+            session.folder = None;
+            session.active = None;
+            self.session = session;
+            return;
+        }
+        let missing = session.remove_missing();
+        if let Some(folder) = &session.folder {
+            log::info!("Restoring folder: {}", folder.display());
+            self.workspace = Some(workspace::Workspace::open(folder));
+        }
+        let active = session.active.take();
+        self.session = session;
+        if let Some(path) = active {
+            log::info!("Restoring file: {}", path.display());
+            self.open_file(path);
+            self.pending_resume = self.resume.take();
+        }
+        if !missing.is_empty() {
+            for path in &missing {
+                log::warn!("Not restoring missing file: {}", path.display());
+            }
+            self.status_message = Some((session::missing_notice(&missing), current_time() + 10.0));
+        }
+    }
+
+    /// Moves the open file to the front of the recent files.
+    fn remember_recent_file(&mut self) {
+        if let Some(path) = &self.file_path {
+            let path = wiki::absolute(path);
+            self.recent_files.retain(|recent| *recent != path);
+            self.recent_files.insert(0, path);
+            self.recent_files.truncate(MAX_RECENT_FILES);
+        }
+    }
+
+    /// Opens `dir` in the file tree and shows its README or index, if any.
+    fn open_folder(&mut self, dir: PathBuf) {
+        log::info!("Opening folder: {}", dir.display());
+        self.workspace = Some(workspace::Workspace::open(&dir));
+        self.search = search::SearchPanel::default();
+        match workspace::default_document(&dir) {
+            Some(path) => self.open_file(path),
+            None => {
+                self.status_message = Some((
+                    "Folder opened. Pick a file from the sidebar.".to_string(),
+                    current_time(),
+                ));
+            }
+        }
+    }
+
     fn reload_file(&mut self) {
         if let Some(path) = self.file_path.clone() {
             log::info!("Reloading file: {}", path.display());
             let current_scroll = self.scroll_offset;
-            *self = Self::new_from_file(path);
+            let mut next = Self::new_from_file(path);
+            if next.file_path.is_some() {
+                // Only this file changed, so its folder isn't scanned again.
+                next.vault = self.vault.take();
+                next.backlinks = std::mem::take(&mut self.backlinks);
+                next.pending_vault = self.pending_vault.take();
+            }
+            self.replace_document(next);
             self.scroll_offset = current_scroll;
             self.status_message = Some(("File reloaded.".to_string(), current_time()));
         } else {
@@ -122,11 +293,37 @@ impl MarkdownViewerApp {
         }
     }
 
+    /// Picks up the open file's folder once it has been scanned.
+    fn poll_vault(&mut self, ctx: &egui::Context) {
+        let Some(receiver) = &self.pending_vault else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok((vault, backlinks)) => {
+                self.vault = Some(vault);
+                self.backlinks = backlinks;
+                self.pending_vault = None;
+                ctx.request_repaint();
+            }
+            Err(TryRecvError::Empty) => ctx.request_repaint_after(Duration::from_millis(100)),
+            Err(TryRecvError::Disconnected) => self.pending_vault = None,
+        }
+    }
+
     fn check_file_modified(&mut self, ctx: &egui::Context) {
+        let watching = self.settings.file_watching;
+        if watching == settings::FileWatching::Off {
+            return;
+        }
         if let Some(path) = &self.file_path {
             if let Ok(metadata) = fs::metadata(path) {
                 if let Ok(modified) = metadata.modified() {
                     if self.last_modified.is_some() && self.last_modified != Some(modified) {
+                        if watching == settings::FileWatching::Reload {
+                            self.reload_file();
+                            ctx.request_repaint();
+                            return;
+                        }
                         self.status_message = Some((
                             "File modified externally. Click Reload (🔄) to update.".to_string(),
                             current_time() + 10.0,
@@ -163,63 +360,195 @@ impl MarkdownViewerApp {
         }
     }
 
-    fn get_syntect_theme(&self, visuals: &egui::Visuals) -> &'static syntect::highlighting::Theme {
-        let theme_name = if visuals.dark_mode {
-            "base16-ocean.dark"
-        } else {
-            "base16-ocean.light"
+    fn apply_doc_action(&mut self, ctx: &egui::Context, action: DocAction) {
+        match action {
+            DocAction::ToggleTask { offset, checked } => self.toggle_task(offset, checked),
+            DocAction::OpenNote(path) => self.open_file(path),
+            DocAction::OpenUrl(url) => match self.settings.open_links {
+                settings::LinkPolicy::Open => open_url(&url),
+                settings::LinkPolicy::Ask => self.pending_link = Some(url),
+                settings::LinkPolicy::Copy => self.copy_url(ctx, url),
+            },
+        }
+    }
+
+    fn copy_url(&mut self, ctx: &egui::Context, url: String) {
+        self.status_message = Some((format!("Copied {}", url), current_time()));
+        ctx.output_mut(|o| o.copied_text = url);
+    }
+
+    /// Carries out the keyboard commands typed since the last frame.
+    fn run_key_commands(&mut self) {
+        let offset = self.scroll_offset.unwrap_or(0.0);
+        let max = (self.layout.height - self.view_height).max(0.0);
+        // Paging keeps a couple of lines of the previous page in view.
+        let page = (self.view_height - 2.0 * LINE_SCROLL).max(LINE_SCROLL);
+        let mut target = None;
+        for command in std::mem::take(&mut self.key_commands) {
+            let offset = target.unwrap_or(offset).clamp(0.0, max);
+            let mut headings = self.layout.headings.iter().map(|h| h.top);
+            match command {
+                keymap::Command::PageDown => target = Some(offset + page),
+                keymap::Command::PageUp => target = Some(offset - page),
+                keymap::Command::Top => target = Some(0.0),
+                keymap::Command::Bottom => target = Some(max),
+                keymap::Command::LineDown => target = Some(offset + LINE_SCROLL),
+                keymap::Command::LineUp => target = Some(offset - LINE_SCROLL),
+                keymap::Command::NextHeading => {
+                    target = headings.find(|&top| top > offset + 1.0).or(target);
+                }
+                keymap::Command::PreviousHeading => {
+                    target = Some(headings.rfind(|&top| top < offset - 1.0).unwrap_or(0.0));
+                }
+                keymap::Command::NextLink | keymap::Command::PreviousLink => {
+                    let forward = command == keymap::Command::NextLink;
+                    self.link_focus.index = self.layout.step_link(
+                        self.link_focus.index,
+                        forward,
+                        offset..offset + self.view_height,
+                    );
+                    self.link_focus.reveal = true;
+                }
+                keymap::Command::FollowLink => self.link_focus.follow = true,
+            }
+        }
+        if let Some(target) = target {
+            self.scroll_offset = Some(target.clamp(0.0, max));
+        }
+    }
+
+    /// Asks whether to open the link clicked while links need confirming.
+    fn link_confirmation(&mut self, ctx: &egui::Context) {
+        let Some(url) = self.pending_link.clone() else {
+            return;
         };
-        THEME_SET.themes.get(theme_name).unwrap_or_else(|| {
-            log::warn!("Syntax theme '{}' not found, falling back.", theme_name);
-            if visuals.dark_mode {
-                &THEME_SET.themes["base16-eighties.dark"]
-            } else {
-                &THEME_SET.themes["base16-ocean.light"]
+        let mut keep = true;
+        egui::Window::new("Open link?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(&url);
+                ui.horizontal(|ui| {
+                    if ui.button("🌐 Open").clicked() {
+                        open_url(&url);
+                        keep = false;
+                    }
+                    if ui.button("📋 Copy").clicked() {
+                        self.copy_url(ctx, url.clone());
+                        keep = false;
+                    }
+                    if ui.button("Cancel").clicked()
+                        || ui.input(|i| i.key_pressed(egui::Key::Escape))
+                    {
+                        keep = false;
+                    }
+                });
+            });
+        if !keep {
+            self.pending_link = None;
+        }
+    }
+
+    /// Flips the task marker at `offset` and saves the file, unless it was
+    /// changed on disk since it was loaded.
+    fn toggle_task(&mut self, offset: usize, checked: bool) {
+        let marker = self.markdown.as_bytes().get(offset..offset + 3);
+        if !matches!(marker, Some([b'[', b' ' | b'x' | b'X', b']'])) {
+            log::error!("No task marker at byte offset {}.", offset);
+            self.status_message = Some((
+                "Could not update task: marker not found.".to_string(),
+                current_time(),
+            ));
+            return;
+        }
+        let mut updated = self.markdown.clone();
+        updated.replace_range(offset + 1..offset + 2, if checked { "x" } else { " " });
+
+        let Some(path) = self.file_path.clone() else {
+            // Nothing to save for the built-in welcome document.
+            self.markdown = updated;
+            return;
+        };
+        match fs::read_to_string(&path) {
+            Ok(on_disk) if on_disk == self.markdown => {}
+            Ok(_) => {
+                log::warn!(
+                    "Refusing to update task: {} changed on disk.",
+                    path.display()
+                );
+                self.status_message = Some((
+                    "File changed on disk since it was loaded. Reload (🔄) before ticking tasks."
+                        .to_string(),
+                    current_time() + 10.0,
+                ));
+                return;
             }
-        })
+            Err(e) => {
+                log::error!("Failed to re-read file {}: {}", path.display(), e);
+                self.status_message =
+                    Some((format!("Could not update task: {}", e), current_time()));
+                return;
+            }
+        }
+        match write_atomically(&path, &updated) {
+            Ok(()) => {
+                self.markdown = updated;
+                self.last_modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                self.status_message = Some(("Task updated.".to_string(), current_time()));
+            }
+            Err(e) => {
+                log::error!("Failed to save file {}: {}", path.display(), e);
+                self.status_message = Some((format!("Failed to save file: {}", e), current_time()));
+            }
+        }
+    }
+
+    /// The theme's paired code theme, or the one picked for its mode. `None`
+    /// until the themes have loaded.
+    fn get_syntect_theme(
+        &mut self,
+        theme: &theme::Theme,
+    ) -> Option<&'static syntect::highlighting::Theme> {
+        let paired = highlighting::themes().and_then(|set| {
+            theme
+                .code_theme
+                .as_ref()
+                .and_then(|name| set.themes.get(name))
+        });
+        paired.or_else(|| self.code_theme.get(&self.settings.code_themes, theme.dark))
     }
 }
 
 impl App for MarkdownViewerApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         // Check for external file modifications every 60 frames.
         if ctx.frame_nr() % 60 == 0 {
             self.check_file_modified(ctx);
         }
 
-        let visuals = if self.dark_mode {
-            let mut v = egui::Visuals::dark();
-            // Custom dark theme with blue-tinged gray
-            let dark_bg = Color32::from_rgb(40, 40, 55);
-            v.window_fill = dark_bg;
-            v.panel_fill = dark_bg;
-            v.faint_bg_color = Color32::from_rgb(50, 50, 65);
-            v.widgets.noninteractive.bg_fill = dark_bg;
-
-            // Set text to near-white (240,240,245 is a slightly blue-tinged white)
-            v.widgets.noninteractive.fg_stroke.color = Color32::from_rgb(240, 240, 245);
-            v.override_text_color = Some(Color32::from_rgb(240, 240, 245)); // Correct way to override text color
-
-            v
-        } else {
-            let mut v = egui::Visuals::light();
-            // Light theme adjustments
-            v.code_bg_color = Color32::from_rgb(245, 245, 245);
-            v.extreme_bg_color = Color32::from_rgb(255, 255, 255);
-            v.window_fill = Color32::from_rgb(255, 255, 255);
-            v.panel_fill = Color32::from_rgb(255, 255, 255);
-            v.widgets.noninteractive.bg_fill = Color32::from_rgb(255, 255, 255);
-            v.override_text_color = None; // Use default light mode text colors
-            v
-        };
+        if let Some(message) = self.themes.poll(ctx) {
+            self.status_message = Some((message, current_time() + 10.0));
+        }
+        if let Some(message) = self.keymap.poll(ctx) {
+            self.status_message = Some((message, current_time() + 10.0));
+        }
+        self.run_key_commands();
+        self.poll_vault(ctx);
+        let dark_mode = self.settings.appearance.is_dark(frame.info().system_theme);
+        let theme = self.settings.theme.get(&self.themes, dark_mode).clone();
+        let visuals = theme.visuals();
         ctx.set_visuals(visuals.clone());
 
-        // Obtain the syntect theme based on the current visuals.
-        let syntect_theme = self.get_syntect_theme(&visuals);
+        // Obtain the syntect theme based on the current theme.
+        let syntect_theme = self.get_syntect_theme(&theme);
 
         // Process any dropped files.
         handle_dropped_files(ctx, self);
 
+        reading::handle_zoom_input(ctx, &mut self.settings.reading);
+        ctx.set_zoom_factor(self.settings.reading.zoom);
+
         // --- Top Menu Bar ---
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -232,9 +561,32 @@ impl App for MarkdownViewerApp {
                         .add_filter("Markdown", &["md", "markdown"])
                         .pick_file()
                     {
-                        *self = Self::new_from_file(file_path);
+                        self.open_file(file_path);
                     }
                 }
+                if ui
+                    .button("📁 Open Folder")
+                    .on_hover_text("Browse the Markdown files in a folder")
+                    .clicked()
+                {
+                    if let Some(dir) = FileDialog::new().pick_folder() {
+                        self.open_folder(dir);
+                    }
+                }
+                ui.menu_button("🕘 Recent", |ui| {
+                    if let Some(path) = session::recent_files_ui(ui, &mut self.recent_files) {
+                        if path.is_file() {
+                            self.open_file(path);
+                        } else {
+                            log::warn!("Recent file is missing: {}", path.display());
+                            self.recent_files.retain(|recent| *recent != path);
+                            self.status_message = Some((
+                                format!("{} no longer exists.", path.display()),
+                                current_time(),
+                            ));
+                        }
+                    }
+                });
                 ui.add_enabled_ui(self.file_path.is_some(), |ui| {
                     if ui
                         .button("🔄 Reload")
@@ -267,19 +619,31 @@ impl App for MarkdownViewerApp {
                         }
                     }
                 }
-                if ui
-                    .toggle_value(&mut self.dark_mode, "🌙 Dark Mode")
-                    .on_hover_text("Toggle Dark/Light Theme")
-                    .clicked()
-                {
-                    // Mode toggled.
-                }
+                theme::appearance_ui(ui, &mut self.settings.appearance);
+                ui.menu_button("🔍 View", |ui| {
+                    reading::reading_settings_ui(ui, &mut self.settings.reading);
+                });
+                ui.toggle_value(&mut self.show_settings, "⚙ Settings")
+                    .on_hover_text("Appearance, fonts, links, files, images and Markdown syntax");
+                ui.add_enabled_ui(self.file_path.is_some(), |ui| {
+                    ui.toggle_value(
+                        &mut self.show_backlinks,
+                        format!("↩ Backlinks ({})", self.backlinks.len()),
+                    )
+                    .on_hover_text("Notes in this folder that link here");
+                });
+                ui.add_enabled_ui(self.workspace.is_some(), |ui| {
+                    ui.toggle_value(&mut self.show_search, "🔎 Search")
+                        .on_hover_text("Search the files in the open folder (Ctrl+Shift+F)")
+                        .on_disabled_hover_text("Open a folder to search it");
+                });
                 ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
                     if let Some(ref path) = self.file_path {
                         let filename = path
                             .file_name()
                             .map_or_else(|| path.to_string_lossy(), |n| n.to_string_lossy());
-                        ui.label(RichText::new(filename).weak())
+                        let label = self.title.as_deref().unwrap_or(&filename);
+                        ui.label(RichText::new(label).weak())
                             .on_hover_text(path.display().to_string());
                     } else {
                         ui.label(RichText::new("No file loaded").weak());
@@ -314,7 +678,147 @@ impl App for MarkdownViewerApp {
             self.status_message = None;
         }
 
+        // --- Workspace Panel ---
+        let mut open_from_tree = None;
+        let mut close_workspace = false;
+        if let Some(workspace) = &mut self.workspace {
+            if let Some(changes) = workspace.poll(ctx) {
+                if let Some(vault) = &mut self.vault {
+                    Arc::make_mut(vault).update(&changes);
+                }
+                self.search.index.update(changes, ctx);
+            }
+            egui::SidePanel::left("workspace_panel")
+                .resizable(true)
+                .default_width(220.0)
+                .show(ctx, |ui| {
+                    ui.add_space(4.0);
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(&workspace.tree.name).strong())
+                            .on_hover_text(workspace.root.display().to_string());
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            if ui.small_button("✕").on_hover_text("Close folder").clicked() {
+                                close_workspace = true;
+                            }
+                        });
+                    });
+                    ui.separator();
+                    let current = self.file_path.as_deref().map(wiki::absolute);
+                    ScrollArea::vertical().show(ui, |ui| {
+                        open_from_tree = workspace::tree_ui(ui, workspace, current.as_deref());
+                    });
+                });
+        }
+        if close_workspace {
+            self.workspace = None;
+        }
+        if let Some(path) = open_from_tree {
+            self.open_file(path);
+        }
+
+        // --- Search Panel ---
+        let search_shortcut = egui::KeyboardShortcut::new(
+            egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+            egui::Key::F,
+        );
+        if self.workspace.is_some() && ctx.input_mut(|i| i.consume_shortcut(&search_shortcut)) {
+            self.show_search = true;
+            self.search.focus = true;
+        }
+        let mut open_hit = None;
+        if let (true, Some(workspace)) = (self.show_search, &self.workspace) {
+            egui::SidePanel::right("search_panel")
+                .resizable(true)
+                .default_width(280.0)
+                .show(ctx, |ui| {
+                    ui.add_space(4.0);
+                    open_hit = self.search.ui(ui, &workspace.root);
+                });
+        }
+        if let Some((path, highlight)) = open_hit {
+            if self.file_path.as_deref().map(wiki::absolute) != Some(wiki::absolute(&path)) {
+                self.open_file(path);
+            }
+            self.search_highlight = Some(highlight);
+            ctx.data_mut(|d| d.insert_temp(pending_jump_id(), SEARCH_HIT_ANCHOR.to_string()));
+        }
+
+        // --- Backlinks Panel ---
+        let mut open_backlink = None;
+        if self.show_backlinks && self.file_path.is_some() {
+            egui::SidePanel::right("backlinks_panel")
+                .resizable(true)
+                .default_width(240.0)
+                .show(ctx, |ui| {
+                    ui.add_space(4.0);
+                    ui.label(RichText::new("Backlinks").strong());
+                    ui.separator();
+                    if self.pending_vault.is_some() {
+                        ui.label(RichText::new("Looking for links…").weak());
+                    } else if self.backlinks.is_empty() {
+                        ui.label(RichText::new("No notes in this folder link here.").weak());
+                    }
+                    let root = self.vault.as_ref().map(|v| v.root.as_path());
+                    ScrollArea::vertical().show(ui, |ui| {
+                        for backlink in &self.backlinks {
+                            let name = root
+                                .and_then(|root| backlink.path.strip_prefix(root).ok())
+                                .unwrap_or(&backlink.path);
+                            if ui
+                                .link(name.display().to_string())
+                                .on_hover_text(backlink.path.display().to_string())
+                                .clicked()
+                            {
+                                open_backlink = Some(backlink.path.clone());
+                            }
+                            for line in backlink.context.iter().take(3) {
+                                ui.label(RichText::new(line).small().weak());
+                            }
+                            ui.add_space(6.0);
+                        }
+                    });
+                });
+        }
+        if let Some(path) = open_backlink {
+            self.open_file(path);
+        }
+
+        // --- Quick Open (Ctrl+P) ---
+        let quick_open_shortcut =
+            egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::P);
+        if ctx.input_mut(|i| i.consume_shortcut(&quick_open_shortcut)) {
+            self.quick_open = match self.quick_open {
+                Some(_) => None,
+                None => Some(quick_open::QuickOpen::default()),
+            };
+        }
+        if let Some(message) = settings::settings_window(
+            ctx,
+            &mut self.show_settings,
+            &mut self.settings,
+            &self.config,
+            &self.themes,
+            &theme,
+            &self.keymap,
+        ) {
+            self.status_message = Some((message, current_time() + 10.0));
+        }
+        self.link_confirmation(ctx);
+
+        if let Some(quick_open) = &mut self.quick_open {
+            match quick_open.show(ctx, self.workspace.as_ref(), &self.recent_files) {
+                Some(quick_open::Outcome::Open(path)) => {
+                    self.quick_open = None;
+                    self.open_file(path);
+                }
+                Some(quick_open::Outcome::Close) => self.quick_open = None,
+                None => {}
+            }
+        }
+
         // --- Central Panel for Markdown Rendering ---
+        let mut rendered = None;
+        let document_path = self.file_path.as_deref().map(wiki::absolute);
         egui::CentralPanel::default()
             .frame(Frame {
                 inner_margin: Margin::same(12.0),
@@ -322,6 +826,31 @@ impl App for MarkdownViewerApp {
                 ..Default::default()
             })
             .show(ctx, |ui| {
+                if let Some(position) = &self.resume {
+                    let place = match position.title.as_str() {
+                        "" => "the beginning".to_string(),
+                        title => format!("“{}”", title),
+                    };
+                    ui.horizontal(|ui| {
+                        ui.label(format!("📖 You were reading near {}.", place));
+                        if ui.button("↪ Resume").clicked() {
+                            self.pending_resume = self.resume.take();
+                        }
+                        if ui
+                            .small_button("✕")
+                            .on_hover_text("Start from the top")
+                            .clicked()
+                        {
+                            self.resume = None;
+                        }
+                    });
+                    ui.separator();
+                }
+                if self.layout.height > 0.0 {
+                    if let Some(position) = self.pending_resume.take() {
+                        self.scroll_offset = position.offset(&self.layout);
+                    }
+                }
                 let scroll_id = ui.id().with("markdown_scroll");
                 let mut remembered_offset = self.scroll_offset.take();
                 let mut scroll_area = ScrollArea::vertical()
@@ -331,22 +860,102 @@ impl App for MarkdownViewerApp {
                     scroll_area = scroll_area.vertical_scroll_offset(offset);
                 }
                 let scroll_output = scroll_area.show(ui, |ui| {
-                    render_markdown(ui, &self.markdown, &visuals, syntect_theme);
+                    let margin = self.settings.reading.column_margin(ui.available_width());
+                    Frame::none()
+                        .inner_margin(Margin::symmetric(margin, 0.0))
+                        .show(ui, |ui| {
+                            rendered = Some(render_markdown(
+                                ui,
+                                Document {
+                                    markdown: &self.markdown,
+                                    path: document_path.as_deref(),
+                                    vault: self.vault.as_deref(),
+                                    highlight: self.search_highlight.as_ref(),
+                                },
+                                &visuals,
+                                &theme,
+                                syntect_theme,
+                                &self.settings,
+                                self.link_focus,
+                            ));
+                        });
                 });
-                self.scroll_offset = Some(scroll_output.state.offset.y);
+                let offset = scroll_output.state.offset.y;
+                self.scroll_offset = Some(offset);
+                // Scrolling away from the top means reading from there instead.
+                if offset > 1.0 && self.pending_resume.is_none() {
+                    self.resume = None;
+                }
+                let document_top = scroll_output.inner_rect.top() - offset;
+                let headings = rendered.as_mut().map(|r| std::mem::take(&mut r.headings));
+                let links = rendered.as_mut().map(|r| std::mem::take(&mut r.links));
+                self.view_height = scroll_output.inner_rect.height();
+                self.layout = reading::Layout {
+                    headings: headings
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|heading| reading::HeadingPosition {
+                            top: heading.top - document_top,
+                            ..heading
+                        })
+                        .collect(),
+                    links: links
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|top| top - document_top)
+                        .collect(),
+                    height: scroll_output.content_size.y,
+                };
+                reading::progress_bar(
+                    ui,
+                    scroll_output.inner_rect,
+                    offset,
+                    scroll_output.content_size.y,
+                );
             });
+        // Following and revealing are one-off requests.
+        self.link_focus.follow = false;
+        self.link_focus.reveal = false;
+        for action in rendered.map(|r| r.actions).unwrap_or_default() {
+            self.apply_doc_action(ctx, action);
+        }
 
-        // Build a title string (setting window title at runtime is not supported in eframe 0.27.2)
-        let _title = self
-            .file_path
-            .as_ref()
-            .and_then(|p| p.file_name())
-            .map(|name| format!("{} - Markdown Viewer", name.to_string_lossy()))
+        let title = self
+            .title
+            .clone()
+            .or_else(|| {
+                self.file_path
+                    .as_ref()
+                    .and_then(|p| p.file_name())
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .map(|name| format!("{} - Markdown Viewer", name))
             .unwrap_or_else(|| "Markdown Viewer".to_string());
+        if self.window_title.as_ref() != Some(&title) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = Some(title);
+        }
+    }
+
+    fn raw_input_hook(&mut self, ctx: &egui::Context, raw_input: &mut egui::RawInput) {
+        // Keys belong to the focused widget, open menus and dialogs.
+        let busy = ctx.memory(|m| m.focused().is_some() || m.any_popup_open());
+        if busy || self.quick_open.is_some() || self.pending_link.is_some() {
+            return;
+        }
+        let now = raw_input.time.unwrap_or_else(current_time);
+        let commands = self.keymap.take_commands(&mut raw_input.events, now);
+        self.key_commands.extend(commands);
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, "dark_mode", &self.dark_mode);
+        self.config.save(&self.settings);
+        eframe::set_value(storage, "show_backlinks", &self.show_backlinks);
+        eframe::set_value(storage, "recent_files", &self.recent_files);
+        self.remember_position();
+        self.session.folder = self.workspace.as_ref().map(|w| wiki::absolute(&w.root));
+        self.session.active = self.file_path.as_deref().map(wiki::absolute);
+        eframe::set_value(storage, "session", &self.session);
         log::info!("Saving state.");
     }
 }
@@ -356,154 +965,366 @@ struct RenderState<'a, 'b> {
     block_stack: Vec<BlockInfo>,
     current_job: LayoutJob,
     base_format: TextFormat,
-    list_item_number: Option<u64>,
     in_table_header: bool,
-    table_rows: Vec<Vec<LayoutJob>>,
+    table_rows: Vec<Vec<TableCell>>,
     current_cell_job: LayoutJob,
-    highlighter: Option<HighlightLines<'a>>,
-    code_language: Option<String>,
+    cell_hotspots: Vec<(Range<usize>, Hotspot)>, // Section ranges of `current_cell_job`
+    in_code_block: bool,
+    highlighter: Option<HighlightLines<'a>>, // `None` for plain code blocks
+    code_info: CodeInfo,
     code_block_content: String,
-    current_link_url: Option<String>,
-    current_link_text: String,
-    ui: &'b mut egui::Ui,
+    link_start: Option<(usize, String)>, // Section index where the open link began
+    hotspots: Vec<(Range<usize>, Hotspot)>, // Section ranges of `current_job`
+    item_task: Option<(bool, usize)>,    // Task marker of the innermost list item
+    inline_html: Vec<(String, usize)>, // Open inline tags and the style stack depth they started at
+    suppress_text: usize,              // Inside inline <script>/<style>
+    open_details: usize,
+    details_rest: Vec<html::Node>, // Nodes following the `</details>` that ended a block
+    html_link: Option<String>,     // `href` of the enclosing HTML <a>, for linked images
+    inline_math: Vec<(usize, math::MathBox, Color32)>, // Formulas drawn over placeholder sections
+    actions: Vec<DocAction>,
+    headings: Vec<reading::HeadingPosition>, // Tops in screen coordinates
     visuals: &'b egui::Visuals,
-    syntect_theme: &'a syntect::highlighting::Theme,
+    theme: &'b theme::Theme,
+    syntect_theme: Option<&'a syntect::highlighting::Theme>,
+    footnotes: &'b Footnotes<'b>,
+    document: Document<'b>,
+    links: LinkSettings,  // With the front matter's overrides applied
+    pending_text: String, // Adjacent text events, joined so links can span them
+    embed_depth: usize,   // How many `![[Note]]` embeds deep this is
+    line_height: f32,     // Multiple of the natural line height for paragraphs
+    extensions: settings::Extensions,
+    images: settings::ImageSettings,
+    link_focus: LinkFocus,
+    link_tops: Vec<f32>, // Of the links so far, in screen coordinates
+    search_hit_anchored: bool,
+}
+
+/// The link picked with the keyboard, counting links in document order, and
+/// what to do with it this frame.
+#[derive(Clone, Copy, Debug, Default)]
+struct LinkFocus {
+    index: Option<usize>,
+    /// Scroll it into view.
+    reveal: bool,
+    /// Act as if it was clicked.
+    follow: bool,
+}
+
+/// The Markdown being rendered and, for files, where it lives.
+#[derive(Clone, Copy)]
+struct Document<'b> {
+    markdown: &'b str, // The source that event ranges index into
+    path: Option<&'b Path>,
+    vault: Option<&'b wiki::Vault>,
+    highlight: Option<&'b search::Highlight>, // Search hit to scroll to and mark
+}
+
+impl<'a, 'b> RenderState<'a, 'b> {
+    fn new(
+        visuals: &'b egui::Visuals,
+        theme: &'b theme::Theme,
+        syntect_theme: Option<&'a syntect::highlighting::Theme>,
+        footnotes: &'b Footnotes<'b>,
+        document: Document<'b>,
+        links: LinkSettings,
+    ) -> Self {
+        let base_font_id = FontId::new(BODY_FONT_SIZE, egui::FontFamily::Proportional);
+        let base_format = TextFormat {
+            font_id: base_font_id,
+            color: visuals.text_color(),
+            background: Color32::TRANSPARENT, // Remove text backgrounds
+            ..Default::default()
+        };
+        Self {
+            inline_style_stack: vec![base_format.clone()],
+            block_stack: Vec::new(),
+            current_job: LayoutJob::default(),
+            base_format,
+            in_table_header: false,
+            table_rows: Vec::new(),
+            current_cell_job: LayoutJob::default(),
+            cell_hotspots: Vec::new(),
+            in_code_block: false,
+            highlighter: None,
+            code_info: CodeInfo::default(),
+            code_block_content: String::new(),
+            link_start: None,
+            hotspots: Vec::new(),
+            item_task: None,
+            inline_html: Vec::new(),
+            suppress_text: 0,
+            open_details: 0,
+            details_rest: Vec::new(),
+            html_link: None,
+            inline_math: Vec::new(),
+            actions: Vec::new(),
+            headings: Vec::new(),
+            visuals,
+            theme,
+            syntect_theme,
+            footnotes,
+            document,
+            links,
+            pending_text: String::new(),
+            embed_depth: 0,
+            line_height: 1.0,
+            extensions: settings::Extensions::default(),
+            images: settings::ImageSettings::default(),
+            link_focus: LinkFocus::default(),
+            link_tops: Vec::new(),
+            search_hit_anchored: false,
+        }
+    }
+}
+
+/// Interactive spans inside a flushed paragraph.
+#[derive(Clone, Debug)]
+enum Hotspot {
+    Link(String),
+    FootnoteRef(String),
+    FootnoteBackRef(String),
+    /// Text shown on hover, like the reason a formula couldn't be rendered.
+    Tooltip(String),
+    /// A wiki link to another note (or this one, if `path` is `None`).
+    Note {
+        path: Option<PathBuf>,
+        heading: Option<String>,
+    },
+}
+
+/// A table cell's text and the links in it.
+struct TableCell {
+    job: LayoutJob,
+    hotspots: Vec<(Range<usize>, Hotspot)>,
+}
+
+/// Footnote definitions lifted out of the event stream, numbered in order of
+/// first reference like GitHub does. Unreferenced definitions are dropped.
+struct Footnotes<'m> {
+    definitions: HashMap<String, Vec<SourceEvent<'m>>>,
+    order: Vec<String>,
+}
+
+impl<'m> Footnotes<'m> {
+    fn extract(events: impl Iterator<Item = SourceEvent<'m>>) -> (Vec<SourceEvent<'m>>, Self) {
+        let mut body = Vec::new();
+        let mut definitions = HashMap::new();
+        let mut current: Option<(String, Vec<SourceEvent<'m>>)> = None;
+        for event in events {
+            match event {
+                (Event::Start(Tag::FootnoteDefinition(label)), _) => {
+                    current = Some((label.to_string(), Vec::new()));
+                }
+                (Event::End(TagEnd::FootnoteDefinition), _) => {
+                    if let Some((label, events)) = current.take() {
+                        definitions.entry(label).or_insert(events);
+                    }
+                }
+                event => match current.as_mut() {
+                    Some((_, events)) => events.push(event),
+                    None => body.push(event),
+                },
+            }
+        }
+        let mut footnotes = Self {
+            definitions,
+            order: Vec::new(),
+        };
+        footnotes.number_references(&body);
+        // Footnotes may themselves reference later footnotes.
+        let mut index = 0;
+        while index < footnotes.order.len() {
+            let events = footnotes.definitions[&footnotes.order[index]].clone();
+            footnotes.number_references(&events);
+            index += 1;
+        }
+        (body, footnotes)
+    }
+
+    fn number_references(&mut self, events: &[SourceEvent<'m>]) {
+        for (event, _) in events {
+            if let Event::FootnoteReference(label) = event {
+                if self.definitions.contains_key(label.as_ref())
+                    && !self.order.iter().any(|l| l == label.as_ref())
+                {
+                    self.order.push(label.to_string());
+                }
+            }
+        }
+    }
+
+    fn number(&self, label: &str) -> Option<usize> {
+        self.order.iter().position(|l| l == label).map(|i| i + 1)
+    }
+}
+
+/// A parser event with the byte range of the source it came from.
+type SourceEvent<'m> = (Event<'m>, Range<usize>);
+
+/// Changes to the document requested by interacting with the rendered view.
+#[derive(Clone, Debug)]
+enum DocAction {
+    /// Set the task list marker (`[ ]`/`[x]`) starting at `offset` to `checked`.
+    ToggleTask { offset: usize, checked: bool },
+    /// Show another Markdown file, from a wiki link or a relative link.
+    OpenNote(PathBuf),
+    /// Follow a link to anything else, as the link settings say.
+    OpenUrl(String),
+}
+
+/// Why `render_events` stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BlockEnd {
+    /// The end of the enclosing block quote, list or list item.
+    Container,
+    /// A `</details>` closing a `<details>` opened by an earlier HTML block.
+    DetailsClosed,
+    /// No events left.
+    Exhausted,
 }
 
 #[derive(Clone, Debug)]
 enum BlockInfo {
-    List(Option<u64>),
+    List,
     BlockQuote,
     Table,
 }
 
+/// The `title` from the document's front matter, if it has one.
+fn front_matter_title(markdown: &str) -> Option<String> {
+    let mut parser = Parser::new_ext(markdown, settings::Extensions::default().options());
+    let Some(Event::Start(Tag::MetadataBlock(kind))) = parser.next() else {
+        return None;
+    };
+    let mut raw = String::new();
+    for event in parser {
+        match event {
+            Event::Text(text) => raw.push_str(&text),
+            _ => break,
+        }
+    }
+    FrontMatter::parse(&raw, kind).title()
+}
+
+/// What rendering a document produced besides the widgets.
+struct Rendered {
+    actions: Vec<DocAction>,
+    headings: Vec<reading::HeadingPosition>, // Tops in screen coordinates
+    links: Vec<f32>,                         // Tops in screen coordinates
+}
+
 fn render_markdown<'a>(
     ui: &mut egui::Ui,
-    markdown: &str,
+    document: Document<'_>,
     visuals: &egui::Visuals,
-    syntect_theme: &'a syntect::highlighting::Theme,
-) {
-    let options = Options::ENABLE_TABLES
-        | Options::ENABLE_FOOTNOTES
-        | Options::ENABLE_STRIKETHROUGH
-        | Options::ENABLE_TASKLISTS;
-    let parser = Parser::new_ext(markdown, options);
-    let base_font_id = FontId::new(BODY_FONT_SIZE, egui::FontFamily::Proportional);
-    let base_format = TextFormat {
-        font_id: base_font_id.clone(),
-        color: visuals.text_color(),
-        background: Color32::TRANSPARENT, // Remove text backgrounds
-        ..Default::default()
-    };
-    let mut state = RenderState {
-        inline_style_stack: vec![base_format.clone()],
-        block_stack: Vec::new(),
-        current_job: LayoutJob::default(),
-        base_format,
-        list_item_number: None,
-        in_table_header: false,
-        table_rows: Vec::new(),
-        current_cell_job: LayoutJob::default(),
-        highlighter: None,
-        code_language: None,
-        code_block_content: String::new(),
-        current_link_url: None,
-        current_link_text: String::new(),
-        ui,
+    theme: &theme::Theme,
+    syntect_theme: Option<&'a syntect::highlighting::Theme>,
+    settings: &settings::Settings,
+    link_focus: LinkFocus,
+) -> Rendered {
+    let parser =
+        Parser::new_ext(document.markdown, settings.extensions.options()).into_offset_iter();
+    let (body, footnotes) = Footnotes::extract(parser);
+    let mut state = RenderState::new(
         visuals,
+        theme,
         syntect_theme,
-    };
+        &footnotes,
+        document,
+        settings.links.clone(),
+    );
+    state.line_height = settings.reading.line_height;
+    state.extensions = settings.extensions.clone();
+    state.images = settings.images.clone();
+    state.link_focus = link_focus;
+    render_events(&mut state, ui, &mut body.into_iter());
+    flush_block_content(&mut state, ui);
+    render_footnotes_section(&mut state, ui);
+    Rendered {
+        actions: state.actions,
+        headings: state.headings,
+        links: state.link_tops,
+    }
+}
 
-    for event in parser {
+/// Renders block and inline events into `ui`. Containers (block quotes, lists
+/// and list items) render their children into a nested `Ui`, so this returns
+/// as soon as the end of the enclosing container is reached.
+fn render_events<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+) -> BlockEnd {
+    while let Some((event, range)) = events.next() {
+        if !matches!(event, Event::Text(_)) {
+            flush_text(state, ui);
+        }
+        if let Event::Start(
+            Tag::Paragraph
+            | Tag::Heading { .. }
+            | Tag::CodeBlock(_)
+            | Tag::Item
+            | Tag::TableRow
+            | Tag::HtmlBlock,
+        ) = event
+        {
+            anchor_search_hit(state, ui, &range);
+        }
         match event {
             Event::Start(tag) => match tag {
                 Tag::Paragraph => {
-                    flush_block_content(&mut state);
+                    flush_block_content(state, ui);
                 }
                 Tag::Heading { level, .. } => {
-                    flush_block_content(&mut state);
-                    state.ui.add_space(heading_spacing(level, true));
+                    flush_block_content(state, ui);
+                    ui.add_space(state.theme.heading_spacing(level, true));
                     let mut format = state.base_format.clone();
-                    format.font_id = heading_font_id(level);
+                    format.font_id = state.theme.heading_font_id(level);
+                    format.color = state.theme.heading_color(level).unwrap_or(format.color);
                     state.inline_style_stack.push(format);
                 }
-                Tag::BlockQuote => {
-                    flush_block_content(&mut state);
-                    state.ui.add_space(4.0);
-                    state.block_stack.push(BlockInfo::BlockQuote);
-                    state.ui.group(|ui| {
-                        let style = ui.style_mut();
-                        style.visuals.widgets.noninteractive.bg_fill = Color32::TRANSPARENT;
-                        style.visuals.widgets.noninteractive.bg_stroke = Stroke::NONE;
-                        Frame::none()
-                            .inner_margin(Margin {
-                                left: 10.0,
-                                right: 4.0,
-                                top: 2.0,
-                                bottom: 2.0,
-                            })
-                            .show(ui, |ui| {
-                                let rect = ui.available_rect_before_wrap();
-                                let line_rect = egui::Rect::from_min_max(
-                                    rect.left_top() + egui::vec2(2.0, 0.0),
-                                    rect.left_bottom() + egui::vec2(4.0, 0.0),
-                                );
-                                ui.painter().rect_filled(
-                                    line_rect,
-                                    Rounding::ZERO,
-                                    ui.visuals().widgets.noninteractive.fg_stroke.color,
-                                );
-                            });
-                    });
+                Tag::BlockQuote(_) => {
+                    flush_block_content(state, ui);
+                    ui.add_space(4.0);
+                    match Callout::parse(&state.document.markdown[range.clone()]) {
+                        Some(callout) => render_callout(state, ui, events, &callout, range.start),
+                        None => render_blockquote(state, ui, events),
+                    }
+                    ui.add_space(state.theme.spacing.block);
                 }
                 Tag::CodeBlock(kind) => {
-                    flush_block_content(&mut state);
-                    state.ui.add_space(4.0);
-                    state.code_language = match kind {
-                        CodeBlockKind::Fenced(lang) if !lang.is_empty() => Some(lang.into_string()),
-                        _ => None,
+                    flush_block_content(state, ui);
+                    ui.add_space(4.0);
+                    state.code_info = match kind {
+                        CodeBlockKind::Fenced(info) => CodeInfo::parse(&info),
+                        CodeBlockKind::Indented => CodeInfo::default(),
                     };
                     state.code_block_content.clear();
-                    let syntax = state
-                        .code_language
-                        .as_deref()
-                        .and_then(|lang| SYNTAX_SET.find_syntax_by_token(lang))
-                        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
-                    state.highlighter = Some(HighlightLines::new(syntax, state.syntect_theme));
+                    state.in_code_block = true;
+                    // Shown as plain text until syntect has loaded.
+                    state.highlighter = match (highlighting::syntaxes(), state.syntect_theme) {
+                        (Some(syntaxes), Some(theme)) => {
+                            let syntax = state
+                                .code_info
+                                .language
+                                .as_deref()
+                                .and_then(|lang| syntaxes.find_syntax_by_token(lang))
+                                .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+                            Some(HighlightLines::new(syntax, theme))
+                        }
+                        _ => None,
+                    };
                 }
                 Tag::List(start_num) => {
-                    flush_block_content(&mut state);
-                    state.ui.add_space(4.0);
-                    state.block_stack.push(BlockInfo::List(start_num));
-                    state.list_item_number = start_num;
-                }
-                Tag::Item => {
-                    flush_block_content(&mut state);
-                    let indent_level = state
-                        .block_stack
-                        .iter()
-                        .filter(|b| matches!(b, BlockInfo::List(_)))
-                        .count();
-                    let indent_width = 20.0;
-                    state.ui.indent("list_item_indent", |ui| {
-                        ui.add_space((indent_level.saturating_sub(1)) as f32 * indent_width);
-                        ui.horizontal_top(|ui| {
-                            ui.set_width(indent_width);
-                            let marker = match state.list_item_number {
-                                Some(num) => format!("{}. ", num),
-                                None => "• ".to_string(),
-                            };
-                            ui.label(RichText::new(marker).font(state.base_format.font_id.clone()));
-                        });
-                        if let Some(num) = state.list_item_number.as_mut() {
-                            *num += 1;
-                        }
-                    });
-                    state.ui.add_space(2.0);
+                    flush_block_content(state, ui);
+                    ui.add_space(4.0);
+                    render_list(state, ui, events, start_num);
+                    ui.add_space(state.theme.spacing.block);
                 }
                 Tag::Table(_alignments) => {
-                    flush_block_content(&mut state);
-                    state.ui.add_space(6.0);
+                    flush_block_content(state, ui);
+                    ui.add_space(6.0);
                     state.block_stack.push(BlockInfo::Table);
                     state.table_rows.clear();
                 }
@@ -519,6 +1340,7 @@ fn render_markdown<'a>(
                 }
                 Tag::TableCell => {
                     state.current_cell_job = LayoutJob::default();
+                    state.cell_hotspots.clear();
                     if state.in_table_header {
                         let mut format = state
                             .inline_style_stack
@@ -539,6 +1361,8 @@ fn render_markdown<'a>(
                     let mut format = state.inline_style_stack.last().unwrap().clone();
                     format.font_id =
                         FontId::new(format.font_id.size, egui::FontFamily::Proportional);
+                    // There's no bold face, so bold is drawn brighter, as egui does.
+                    format.color = state.visuals.strong_text_color();
                     state.inline_style_stack.push(format);
                 }
                 Tag::Strikethrough => {
@@ -552,13 +1376,16 @@ fn render_markdown<'a>(
                     title: _,
                     id: _,
                 } => {
-                    flush_inline_content(&mut state, false); // Flush text before link
                     let mut format = state.inline_style_stack.last().unwrap().clone();
                     format.color = state.visuals.hyperlink_color;
                     format.underline = Stroke::new(1.0, format.color);
                     state.inline_style_stack.push(format);
-                    state.current_link_url = Some(dest_url.into_string());
-                    state.current_link_text.clear();
+                    let job = if in_table(state) {
+                        &state.current_cell_job
+                    } else {
+                        &state.current_job
+                    };
+                    state.link_start = Some((job.sections.len(), dest_url.into_string()));
                 }
                 Tag::Image {
                     link_type: _,
@@ -566,69 +1393,98 @@ fn render_markdown<'a>(
                     title,
                     id: _,
                 } => {
-                    flush_inline_content(&mut state, false);
-                    render_image(state.ui, dest_url.as_ref(), title.as_ref());
+                    flush_inline_content(state, ui, false);
+                    render_image(
+                        ui,
+                        dest_url.as_ref(),
+                        title.as_ref(),
+                        ImageSize::default(),
+                        &state.images,
+                    );
+                }
+                Tag::MetadataBlock(kind) => {
+                    flush_block_content(state, ui);
+                    let mut raw = String::new();
+                    for (event, _) in events.by_ref() {
+                        match event {
+                            Event::Text(text) => raw.push_str(&text),
+                            Event::End(TagEnd::MetadataBlock(_)) => break,
+                            _ => {}
+                        }
+                    }
+                    let front_matter = FrontMatter::parse(&raw, kind);
+                    state.links = state.links.with_front_matter(&front_matter);
+                    render_front_matter(state, ui, &front_matter);
                 }
-                Tag::FootnoteDefinition(label) => {
-                    flush_block_content(&mut state);
-                    state.ui.add_space(4.0);
-                    state.ui.label(format!("[^{}]:", label));
-                    state.ui.indent("footnote", |ui| {});
+                Tag::HtmlBlock => {
+                    flush_block_content(state, ui);
+                    if let Some(end) = render_html_block(state, ui, events, range.start) {
+                        return end;
+                    }
                 }
                 _ => {}
             },
             Event::End(tag_end) => match tag_end {
                 TagEnd::Paragraph => {
-                    flush_inline_content(&mut state, true);
+                    close_inline_html(state);
+                    flush_inline_content(state, ui, true);
                 }
                 TagEnd::Heading(level) => {
                     // Corrected: tuple variant TagEnd::Heading(level)
+                    close_inline_html(state);
                     if !state.inline_style_stack.is_empty() {
                         state.inline_style_stack.pop();
                     }
-                    flush_inline_content(&mut state, false);
-                    state.ui.add_space(heading_spacing(level, false));
-                }
-                TagEnd::BlockQuote => {
-                    flush_block_content(&mut state);
-                    if !state.block_stack.is_empty() {
-                        state.block_stack.pop();
+                    let anchor = heading_anchor(&state.current_job.text);
+                    let title = state.current_job.text.trim().to_string();
+                    let top = ui.cursor().top();
+                    flush_inline_content(state, ui, false);
+                    if state.embed_depth == 0 {
+                        let rect =
+                            Rect::from_x_y_ranges(ui.max_rect().x_range(), top..=ui.cursor().top());
+                        register_anchor(ui, &anchor, rect);
+                        state
+                            .headings
+                            .push(reading::HeadingPosition { anchor, title, top });
                     }
-                    state.ui.add_space(6.0);
+                    ui.add_space(state.theme.heading_spacing(level, false));
+                }
+                TagEnd::BlockQuote | TagEnd::Item => {
+                    flush_block_content(state, ui);
+                    return BlockEnd::Container;
                 }
                 TagEnd::CodeBlock => {
-                    render_code_block(&mut state);
+                    if state.code_info.language.as_deref() == Some("mermaid") {
+                        render_mermaid(state, ui, range.start);
+                    } else {
+                        render_code_block(state, ui, range.start);
+                    }
+                    state.in_code_block = false;
                     state.highlighter = None;
-                    state.code_language = None;
-                    state.ui.add_space(6.0);
+                    state.code_info = CodeInfo::default();
+                    ui.add_space(state.theme.spacing.block);
                 }
                 TagEnd::List(_) => {
-                    // Keep List(_) as pulldown_cmark uses TagEnd::List(Option<u64>)
-                    flush_block_content(&mut state);
-                    if !state.block_stack.is_empty() {
-                        state.block_stack.pop();
-                    }
-                    state.list_item_number = get_parent_list_num(&state.block_stack);
-                    state.ui.add_space(6.0);
-                }
-                TagEnd::Item => {
-                    // Keep Item as pulldown_cmark uses TagEnd::Item
-                    flush_inline_content(&mut state, true);
+                    flush_block_content(state, ui);
+                    return BlockEnd::Container;
                 }
                 TagEnd::Table => {
                     // Keep Table as pulldown_cmark uses TagEnd::Table
-                    flush_block_content(&mut state);
-                    render_table(&mut state);
+                    flush_block_content(state, ui);
+                    render_table(state, ui);
                     if !state.block_stack.is_empty() {
                         state.block_stack.pop();
                     }
-                    state.ui.add_space(6.0);
+                    ui.add_space(state.theme.spacing.block);
                 }
                 TagEnd::TableHead => {}
                 TagEnd::TableRow => {}
                 TagEnd::TableCell => {
                     if let Some(last_row) = state.table_rows.last_mut() {
-                        last_row.push(state.current_cell_job.clone());
+                        last_row.push(TableCell {
+                            job: state.current_cell_job.clone(),
+                            hotspots: std::mem::take(&mut state.cell_hotspots),
+                        });
                     }
                     state.current_cell_job = LayoutJob::default();
                     if state.in_table_header {
@@ -644,240 +1500,2092 @@ fn render_markdown<'a>(
                 }
                 TagEnd::Link { .. } => {
                     // Keep as struct
-                    if let Some(url) = state.current_link_url.take() {
-                        let text = std::mem::take(&mut state.current_link_text);
-                        if !state.inline_style_stack.is_empty() {
-                            state.inline_style_stack.pop();
-                        }
-                        let response = state.ui.link(text).on_hover_text(&url);
-                        if response.clicked() {
-                            if let Err(e) = open::that(&url) {
-                                log::error!("Failed to open link '{}': {}", url, e);
-                            }
-                        }
-                    } else {
-                        if !state.inline_style_stack.is_empty() {
-                            state.inline_style_stack.pop();
+                    if !state.inline_style_stack.is_empty() {
+                        state.inline_style_stack.pop();
+                    }
+                    if let Some((start, url)) = state.link_start.take() {
+                        if in_table(state) {
+                            let end = state.current_cell_job.sections.len();
+                            state.cell_hotspots.push((start..end, Hotspot::Link(url)));
+                        } else {
+                            let end = state.current_job.sections.len();
+                            state.hotspots.push((start..end, Hotspot::Link(url)));
                         }
                     }
                 }
                 TagEnd::Image { .. } => {} // Keep as struct
-                TagEnd::FootnoteDefinition => {
-                    // Keep as struct
-                    flush_block_content(&mut state);
-                }
+                // Definitions are lifted out by `Footnotes::extract`.
+                TagEnd::FootnoteDefinition => {}
+                // Consumed together with their start tags.
                 TagEnd::HtmlBlock | TagEnd::MetadataBlock(_) => {}
             },
+            Event::Text(_) if state.suppress_text > 0 => {}
             Event::Text(text) => {
-                if state.highlighter.is_some() {
+                if state.in_code_block {
                     state.code_block_content.push_str(&text);
-                } else if state.current_link_url.is_some() {
-                    state.current_link_text.push_str(&text);
                 } else {
-                    let format = current_format(&state).clone();
-                    state.current_job.append(&text, 0.0, format.clone());
-                    if state
-                        .block_stack
-                        .last()
-                        .map_or(false, |b| matches!(b, BlockInfo::Table))
+                    // Emphasis delimiters split text into several events.
+                    state.pending_text.push_str(&text);
+                }
+            }
+            Event::Code(text) => {
+                let mut code_format = current_format(state).clone();
+                code_format.font_id = FontId::monospace(CODE_FONT_SIZE);
+                code_format.background = state.visuals.code_bg_color;
+                state.current_job.append("`", 0.0, code_format.clone());
+                state.current_job.append(&text, 0.0, code_format.clone());
+                state.current_job.append("`", 0.0, code_format.clone());
+                if state
+                    .block_stack
+                    .last()
+                    .map_or(false, |b| matches!(b, BlockInfo::Table))
+                {
+                    state
+                        .current_cell_job
+                        .append(&format!("`{}`", text), 0.0, code_format);
+                }
+            }
+            Event::Html(html) => {
+                // Only seen outside of `Tag::HtmlBlock` in unusual nesting.
+                let nodes = html::parse_fragment(&html);
+                if let Some(end) = render_html_nodes(state, ui, events, &nodes, range.start) {
+                    return end;
+                }
+            }
+            Event::InlineHtml(html) => render_inline_html(state, ui, &html),
+            Event::InlineMath(source) => render_inline_math(state, ui, &source),
+            Event::DisplayMath(source) => {
+                if in_table(state) {
+                    render_inline_math(state, ui, &source);
+                } else {
+                    flush_inline_content(state, ui, false);
+                    render_display_math(state, ui, &source);
+                }
+            }
+            Event::FootnoteReference(label) => {
+                let mut fmt = current_format(state).clone();
+                fmt.font_id.size *= 0.75;
+                fmt.valign = egui::Align::TOP;
+                match state.footnotes.number(&label) {
+                    Some(number) => {
+                        fmt.color = state.visuals.hyperlink_color;
+                        append_hotspot(
+                            state,
+                            &number.to_string(),
+                            fmt,
+                            Hotspot::FootnoteRef(label.to_string()),
+                        );
+                    }
+                    None => {
+                        let text = format!("[^{}]", label);
+                        state.current_job.append(&text, 0.0, fmt);
+                    }
+                }
+            }
+            Event::SoftBreak => {
+                if state.in_code_block {
+                    state.code_block_content.push('\n');
+                } else {
+                    state
+                        .current_job
+                        .append(" ", 0.0, current_format(state).clone());
+                }
+            }
+            Event::HardBreak => {
+                if state.in_code_block {
+                    state.code_block_content.push('\n');
+                } else {
+                    state
+                        .current_job
+                        .append("\n", 0.0, current_format(state).clone());
+                }
+            }
+            Event::Rule => {
+                flush_block_content(state, ui);
+                ui.add_space(8.0);
+                ui.add(Separator::default().horizontal());
+                ui.add_space(8.0);
+            }
+            Event::TaskListMarker(checked) => {
+                // Drawn as a checkbox in place of the item's bullet.
+                state.item_task = Some((checked, range.start));
+            }
+        }
+    }
+    flush_text(state, ui);
+    BlockEnd::Exhausted
+}
+
+/// Renders a block quote's children inside a frame with a bar on the left.
+/// Nested quotes end up with one bar per level.
+fn render_blockquote<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+) {
+    state.block_stack.push(BlockInfo::BlockQuote);
+    let response = Frame::none()
+        .inner_margin(Margin {
+            left: 12.0,
+            right: 4.0,
+            top: 2.0,
+            bottom: 2.0,
+        })
+        .show(ui, |ui| {
+            // A `</details>` inside the quote can't close a section opened outside it.
+            let outer_details = std::mem::take(&mut state.open_details);
+            render_events(state, ui, events);
+            state.open_details = outer_details;
+        })
+        .response;
+    let rect = response.rect;
+    let bar_rect = Rect::from_min_max(
+        rect.left_top() + egui::vec2(2.0, 0.0),
+        rect.left_bottom() + egui::vec2(5.0, 0.0),
+    );
+    ui.painter()
+        .rect_filled(bar_rect, Rounding::ZERO, state.theme.colors.quote_bar.0);
+    state.block_stack.pop();
+}
+
+/// A GitHub alert (`> [!NOTE]`) or Obsidian callout (`> [!tip]- Title`).
+struct Callout {
+    kind: String,
+    title: Option<String>,
+    /// `Some(open)` for foldable callouts: `+` starts open, `-` folded.
+    fold: Option<bool>,
+}
+
+impl Callout {
+    /// Recognizes the `[!kind]` marker on the first line of a block quote.
+    fn parse(source: &str) -> Option<Self> {
+        let first_line = source.lines().next()?;
+        let marker = first_line.trim_start().strip_prefix('>')?.trim_start();
+        let rest = marker.strip_prefix("[!")?;
+        let end = rest.find(']')?;
+        let kind = &rest[..end];
+        if kind.is_empty() || !kind.chars().all(|c| c.is_alphanumeric() || c == '-') {
+            return None;
+        }
+        let mut rest = &rest[end + 1..];
+        let fold = match rest.chars().next() {
+            Some('+') => Some(true),
+            Some('-') => Some(false),
+            _ => None,
+        };
+        if fold.is_some() {
+            rest = &rest[1..];
+        }
+        let title = rest.trim();
+        Some(Self {
+            kind: kind.to_lowercase(),
+            title: (!title.is_empty()).then(|| title.to_string()),
+            fold,
+        })
+    }
+
+    /// Icon, default title and accent colour, accepting Obsidian's aliases.
+    fn style(&self, dark_mode: bool) -> (&'static str, &'static str, Color32) {
+        let pick = |dark: (u8, u8, u8), light: (u8, u8, u8)| {
+            let (r, g, b) = if dark_mode { dark } else { light };
+            Color32::from_rgb(r, g, b)
+        };
+        let blue = pick((68, 147, 248), (9, 105, 218));
+        let green = pick((63, 185, 80), (26, 127, 55));
+        let purple = pick((171, 125, 248), (130, 80, 223));
+        let yellow = pick((210, 153, 34), (154, 103, 0));
+        let red = pick((248, 81, 73), (209, 36, 47));
+        let gray = pick((145, 152, 161), (89, 99, 110));
+        match self.kind.as_str() {
+            "tip" | "hint" => ("💡", "Tip", green),
+            "important" => ("❗", "Important", purple),
+            "warning" | "attention" => ("⚠", "Warning", yellow),
+            "caution" => ("⛔", "Caution", red),
+            "abstract" | "summary" | "tldr" => ("📋", "Summary", blue),
+            "info" => ("ℹ", "Info", blue),
+            "todo" => ("☑", "Todo", blue),
+            "success" | "check" | "done" => ("✔", "Success", green),
+            "question" | "help" | "faq" => ("❓", "Question", yellow),
+            "failure" | "fail" | "missing" => ("✖", "Failure", red),
+            "danger" | "error" => ("⚡", "Danger", red),
+            "bug" => ("🐛", "Bug", red),
+            "example" => ("📝", "Example", purple),
+            "quote" | "cite" => ("💬", "Quote", gray),
+            _ => ("ℹ", "Note", blue),
+        }
+    }
+}
+
+/// Renders a block quote that starts with a callout marker as a tinted box
+/// with an icon and title. Foldable callouts get a collapsible body.
+fn render_callout<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+    callout: &Callout,
+    salt: usize,
+) {
+    // Drop the marker line; the rest of its paragraph is the callout body.
+    for (event, _) in events.by_ref() {
+        if matches!(
+            event,
+            Event::SoftBreak | Event::HardBreak | Event::End(TagEnd::Paragraph)
+        ) {
+            break;
+        }
+    }
+    state.block_stack.push(BlockInfo::BlockQuote);
+    let (icon, default_title, accent) = callout.style(state.visuals.dark_mode);
+    let title = callout
+        .title
+        .clone()
+        .unwrap_or_else(|| default_title.to_string());
+    let header = RichText::new(format!("{}  {}", icon, title))
+        .font(state.base_format.font_id.clone())
+        .color(accent)
+        .strong();
+    // A `</details>` inside the callout can't close a section opened outside it.
+    let outer_details = std::mem::take(&mut state.open_details);
+    let response = Frame::none()
+        .fill(accent.gamma_multiply(0.1))
+        .rounding(Rounding::same(4.0))
+        .inner_margin(Margin {
+            left: 14.0,
+            right: 8.0,
+            top: 6.0,
+            bottom: 6.0,
+        })
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            match callout.fold {
+                None => {
+                    ui.label(header);
+                    render_events(state, ui, events);
+                    flush_block_content(state, ui);
+                }
+                Some(open) => {
+                    let id = ui.make_persistent_id(("callout", salt));
+                    let mut body_rendered = false;
+                    egui::collapsing_header::CollapsingState::load_with_default_open(
+                        ui.ctx(),
+                        id,
+                        open,
+                    )
+                    .show_header(ui, |ui| {
+                        ui.label(header);
+                    })
+                    .body(|ui| {
+                        body_rendered = true;
+                        render_events(state, ui, events);
+                        flush_block_content(state, ui);
+                    });
+                    if !body_rendered {
+                        while let BlockEnd::DetailsClosed = skip_details_body(state, events) {}
+                    }
+                }
+            }
+        })
+        .response;
+    state.open_details = outer_details;
+    let rect = response.rect;
+    let bar_rect = Rect::from_min_max(rect.left_top(), rect.left_bottom() + egui::vec2(3.0, 0.0));
+    ui.painter().rect_filled(
+        bar_rect,
+        Rounding {
+            nw: 4.0,
+            sw: 4.0,
+            ..Rounding::ZERO
+        },
+        accent,
+    );
+    state.block_stack.pop();
+}
+
+fn render_list<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+    start_num: Option<u64>,
+) {
+    state.block_stack.push(BlockInfo::List);
+    let depth = state
+        .block_stack
+        .iter()
+        .filter(|b| matches!(b, BlockInfo::List))
+        .count();
+    let mut item_number = start_num;
+    while let Some((event, _)) = events.next() {
+        match event {
+            Event::Start(Tag::Item) => {
+                let marker = match item_number.as_mut() {
+                    Some(num) => {
+                        *num += 1;
+                        format!("{}.", *num - 1)
+                    }
+                    None => bullet_marker(depth).to_string(),
+                };
+                render_list_item(state, ui, events, &marker);
+                ui.add_space(2.0);
+            }
+            Event::End(TagEnd::List(_)) => break,
+            other => log::debug!("Unexpected event in list: {:?}", other),
+        }
+    }
+    state.block_stack.pop();
+}
+
+/// Lays out the marker in a fixed-width gutter and the item's blocks beside it.
+fn render_list_item<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+    marker: &str,
+) {
+    let indent_width = 20.0;
+    ui.horizontal_top(|ui| {
+        // The gutter is filled in afterwards, once we know if this is a task item.
+        let gutter_size = egui::vec2(indent_width, ui.spacing().interact_size.y);
+        let (gutter_rect, _) = ui.allocate_exact_size(gutter_size, Sense::hover());
+        let outer_task = state.item_task.take();
+        ui.vertical(|ui| {
+            let outer_details = std::mem::take(&mut state.open_details);
+            render_events(state, ui, events);
+            state.open_details = outer_details;
+        });
+        match std::mem::replace(&mut state.item_task, outer_task) {
+            Some((checked, offset)) => {
+                let mut checked_now = checked;
+                let response = ui.put(gutter_rect, egui::Checkbox::without_text(&mut checked_now));
+                if response.changed() {
+                    state.actions.push(DocAction::ToggleTask {
+                        offset,
+                        checked: checked_now,
+                    });
+                }
+            }
+            None => {
+                ui.painter().text(
+                    gutter_rect.right_top(),
+                    egui::Align2::RIGHT_TOP,
+                    marker,
+                    state.base_format.font_id.clone(),
+                    state.base_format.color,
+                );
+            }
+        }
+    });
+}
+
+fn bullet_marker(depth: usize) -> &'static str {
+    match depth {
+        1 => "•",
+        2 => "◦",
+        _ => "▪",
+    }
+}
+
+fn in_table(state: &RenderState<'_, '_>) -> bool {
+    matches!(state.block_stack.last(), Some(BlockInfo::Table))
+}
+
+/// Reserves room for the formula in the current line; it's painted over the
+/// placeholder once the paragraph is laid out. Formulas that don't parse are
+/// shown as source with the error on hover.
+fn render_inline_math(state: &mut RenderState<'_, '_>, ui: &egui::Ui, source: &str) {
+    // Table cells aren't painted over, so formulas stay as source there.
+    if in_table(state) {
+        let mut format = current_format(state).clone();
+        format.font_id = FontId::monospace(CODE_FONT_SIZE);
+        state
+            .current_cell_job
+            .append(&format!("${}$", source), 0.0, format);
+        return;
+    }
+    let font_id = current_format(state).font_id.clone();
+    let color = current_format(state).color;
+    let result = ui.fonts(|f| {
+        math::layout(f, source, font_id.size, false)
+            .map(|formula| (math::placeholder_format(f, &formula, &font_id), formula))
+    });
+    match result {
+        Ok((placeholder, formula)) => {
+            state
+                .inline_math
+                .push((state.current_job.sections.len(), formula, color));
+            state
+                .current_job
+                .append(math::PLACEHOLDER, 0.0, placeholder);
+        }
+        Err(error) => {
+            log::debug!("Failed to lay out formula '{}': {}", source, error);
+            let mut format = current_format(state).clone();
+            format.font_id = FontId::monospace(CODE_FONT_SIZE);
+            format.background = state.visuals.code_bg_color;
+            format.color = state.visuals.warn_fg_color;
+            append_hotspot(
+                state,
+                &format!("${}$", source),
+                format,
+                Hotspot::Tooltip(error),
+            );
+        }
+    }
+}
+
+/// Draws a `$$...$$` formula centered on its own line.
+fn render_display_math(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui, source: &str) {
+    let size = state.base_format.font_id.size * 1.15;
+    match ui.fonts(|f| math::layout(f, source, size, true)) {
+        Ok(formula) => {
+            ui.add_space(6.0);
+            let desired = egui::vec2(ui.available_width().max(formula.width), formula.height());
+            let (rect, _) = ui.allocate_exact_size(desired, Sense::hover());
+            let origin = Pos2::new(
+                rect.center().x - formula.width / 2.0,
+                rect.top() + formula.ascent,
+            );
+            formula.paint(ui.painter(), origin, ui.visuals().text_color());
+            ui.add_space(6.0);
+        }
+        Err(error) => {
+            log::debug!("Failed to lay out formula '{}': {}", source, error);
+            ui.add(egui::Label::new(
+                RichText::new(format!("$${}$$", source))
+                    .monospace()
+                    .color(state.visuals.warn_fg_color),
+            ))
+            .on_hover_text(error);
+        }
+    }
+}
+
+/// Shows front matter as a card with the title, author, date and tags, which
+/// can be switched to a plain table of all keys.
+fn render_front_matter(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    front_matter: &FrontMatter,
+) {
+    let raw_id = ui.make_persistent_id("front_matter_raw");
+    let mut show_raw: bool = ui.data_mut(|d| *d.get_persisted_mut_or_default(raw_id));
+    let visuals = state.visuals;
+    Frame::none()
+        .fill(visuals.faint_bg_color)
+        .stroke(Stroke::new(
+            1.0,
+            visuals.widgets.noninteractive.bg_stroke.color,
+        ))
+        .rounding(Rounding::same(6.0))
+        .inner_margin(Margin::same(10.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                let title = front_matter.title();
+                if show_raw || title.is_none() {
+                    ui.label(RichText::new("Front matter").weak());
+                } else if let Some(title) = title {
+                    ui.label(
+                        RichText::new(title)
+                            .font(state.theme.heading_font_id(HeadingLevel::H1))
+                            .strong(),
+                    );
+                }
+                ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                    let (icon, hint) = if show_raw {
+                        ("📄", "Show as a card")
+                    } else {
+                        ("☰", "Show all keys")
+                    };
+                    if ui.small_button(icon).on_hover_text(hint).clicked() {
+                        show_raw = !show_raw;
+                    }
+                });
+            });
+            if let Some(error) = &front_matter.error {
+                ui.label(
+                    RichText::new(format!("Could not parse front matter: {}", error))
+                        .color(visuals.warn_fg_color),
+                );
+                ui.label(RichText::new(front_matter.raw.trim_end()).monospace());
+            } else if show_raw {
+                egui::Grid::new("front_matter_fields")
+                    .num_columns(2)
+                    .spacing([12.0, 4.0])
+                    .show(ui, |ui| {
+                        for (key, value) in &front_matter.fields {
+                            ui.label(RichText::new(key).strong());
+                            ui.add(egui::Label::new(value.display()).wrap(true));
+                            ui.end_row();
+                        }
+                    });
+            } else {
+                let byline: Vec<String> = [front_matter.author(), front_matter.date()]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                if !byline.is_empty() {
+                    ui.label(RichText::new(byline.join("  ·  ")).weak());
+                }
+                let tags = front_matter.tags();
+                if !tags.is_empty() {
+                    ui.horizontal_wrapped(|ui| {
+                        for tag in tags {
+                            Frame::none()
+                                .fill(visuals.widgets.inactive.bg_fill)
+                                .rounding(Rounding::same(8.0))
+                                .inner_margin(Margin::symmetric(6.0, 1.0))
+                                .show(ui, |ui| {
+                                    ui.label(RichText::new(format!("#{}", tag)).small());
+                                });
+                        }
+                    });
+                }
+            }
+        });
+    ui.data_mut(|d| d.insert_persisted(raw_id, show_raw));
+    ui.add_space(8.0);
+}
+
+/// Collects the raw HTML of a block and renders it. Returns `Some` if
+/// rendering must stop, see [`BlockEnd`].
+fn render_html_block<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+    offset: usize,
+) -> Option<BlockEnd> {
+    let mut raw = String::new();
+    for (event, _) in events.by_ref() {
+        match event {
+            Event::Html(text) | Event::Text(text) => raw.push_str(&text),
+            Event::End(TagEnd::HtmlBlock) => break,
+            _ => {}
+        }
+    }
+    let nodes = html::parse_fragment(&raw);
+    let end = render_html_nodes(state, ui, events, &nodes, offset);
+    flush_block_content(state, ui);
+    end
+}
+
+/// Renders the supported subset of HTML; other elements are transparent and
+/// only their content is shown. `salt` keeps widget ids stable across frames.
+fn render_html_nodes<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+    nodes: &[html::Node],
+    salt: usize,
+) -> Option<BlockEnd> {
+    for (index, node) in nodes.iter().enumerate() {
+        let salt = salt.wrapping_mul(31).wrapping_add(index);
+        match node {
+            html::Node::Text(text) => {
+                let text = html::collapse_whitespace(text);
+                if state.current_job.is_empty() && text.trim().is_empty() {
+                    continue;
+                }
+                let format = current_format(state).clone();
+                state.current_job.append(&text, 0.0, format);
+            }
+            html::Node::StrayEnd(name) if name == "details" && state.open_details > 0 => {
+                state.details_rest = nodes[index + 1..].to_vec();
+                flush_block_content(state, ui);
+                return Some(BlockEnd::DetailsClosed);
+            }
+            html::Node::StrayEnd(_) => {}
+            html::Node::Element(element) => {
+                if let Some(end) = render_html_element(state, ui, events, element, salt) {
+                    return Some(end);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn render_html_element<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+    element: &html::Element,
+    salt: usize,
+) -> Option<BlockEnd> {
+    match element.name.as_str() {
+        "br" => {
+            let format = current_format(state).clone();
+            state.current_job.append("\n", 0.0, format);
+        }
+        "hr" => {
+            flush_block_content(state, ui);
+            ui.add_space(8.0);
+            ui.add(Separator::default().horizontal());
+            ui.add_space(8.0);
+        }
+        "img" => {
+            flush_inline_content(state, ui, false);
+            let size = ImageSize::from_html(element, ui.available_width());
+            let src = element.attr("src").unwrap_or_default();
+            let alt = element.attr("alt").unwrap_or_default();
+            let response = render_image(ui, src, alt, size, &state.images);
+            if let Some(url) = state.html_link.clone() {
+                let response = response.interact(Sense::click());
+                if response.on_hover_cursor(CursorIcon::PointingHand).clicked() {
+                    state.actions.push(DocAction::OpenUrl(url));
+                }
+            }
+        }
+        "a" => {
+            let href = element.attr("href").map(str::to_string);
+            let Some(url) = href else {
+                return render_html_nodes(state, ui, events, &element.children, salt);
+            };
+            let mut format = current_format(state).clone();
+            format.color = state.visuals.hyperlink_color;
+            format.underline = Stroke::new(1.0, format.color);
+            state.inline_style_stack.push(format);
+            let outer_link = state.html_link.replace(url.clone());
+            let start = state.current_job.sections.len();
+            let end = render_html_nodes(state, ui, events, &element.children, salt);
+            // Any text flushed early (e.g. before an image) loses its hotspot.
+            let start = start.min(state.current_job.sections.len());
+            let sections = start..state.current_job.sections.len();
+            state.hotspots.push((sections, Hotspot::Link(url)));
+            state.html_link = outer_link;
+            state.inline_style_stack.pop();
+            return end;
+        }
+        "details" => return render_html_details(state, ui, events, element, salt),
+        "summary" => {} // Drawn by the enclosing <details>.
+        "table" => {
+            flush_block_content(state, ui);
+            ui.add_space(6.0);
+            let mut rows = Vec::new();
+            collect_html_table_rows(state, element, &mut rows);
+            render_table_rows(state, ui, rows);
+            ui.add_space(state.theme.spacing.block);
+        }
+        "pre" => {
+            flush_block_content(state, ui);
+            ui.add_space(4.0);
+            state.code_block_content = element.text();
+            render_code_block(state, ui, salt);
+            ui.add_space(state.theme.spacing.block);
+        }
+        "ul" | "ol" => {
+            flush_block_content(state, ui);
+            let items = element.children.iter().filter_map(|child| match child {
+                html::Node::Element(li) if li.name == "li" => Some(li),
+                _ => None,
+            });
+            for (number, li) in items.enumerate() {
+                let marker = if element.name == "ol" {
+                    format!("{}.", number + 1)
+                } else {
+                    bullet_marker(1).to_string()
+                };
+                ui.horizontal_top(|ui| {
+                    ui.add_sized(
+                        [20.0, ui.spacing().interact_size.y],
+                        egui::Label::new(
+                            RichText::new(marker).font(state.base_format.font_id.clone()),
+                        ),
+                    );
+                    ui.vertical(|ui| {
+                        render_html_nodes(state, ui, events, &li.children, salt);
+                        flush_block_content(state, ui);
+                    });
+                });
+            }
+        }
+        name if html_block_level(name) => {
+            flush_block_content(state, ui);
+            let centered = name == "center"
+                || element
+                    .attr("align")
+                    .is_some_and(|a| a.eq_ignore_ascii_case("center"));
+            let heading = match name {
+                "h1" => Some(HeadingLevel::H1),
+                "h2" => Some(HeadingLevel::H2),
+                "h3" => Some(HeadingLevel::H3),
+                "h4" => Some(HeadingLevel::H4),
+                "h5" => Some(HeadingLevel::H5),
+                "h6" => Some(HeadingLevel::H6),
+                _ => None,
+            };
+            if let Some(level) = heading {
+                ui.add_space(state.theme.heading_spacing(level, true));
+                let mut format = state.base_format.clone();
+                format.font_id = state.theme.heading_font_id(level);
+                format.color = state.theme.heading_color(level).unwrap_or(format.color);
+                state.inline_style_stack.push(format);
+            }
+            let layout = if centered {
+                Layout::top_down(Align::Center)
+            } else {
+                Layout::top_down(Align::Min)
+            };
+            let end = ui
+                .with_layout(layout, |ui| {
+                    let end = if centered
+                        && html_contains(element, "img")
+                        && !html_has_block_children(element)
                     {
-                        state.current_cell_job.append(&text, 0.0, format);
+                        render_centered_html_row(state, ui, events, element, salt)
+                    } else {
+                        render_html_nodes(state, ui, events, &element.children, salt)
+                    };
+                    flush_inline_content(state, ui, heading.is_none());
+                    end
+                })
+                .inner;
+            if let Some(level) = heading {
+                state.inline_style_stack.pop();
+                ui.add_space(state.theme.heading_spacing(level, false));
+            }
+            return end;
+        }
+        _ => {
+            let format = html_inline_format(state, element);
+            let pushed = format.is_some();
+            if let Some(format) = format {
+                state.inline_style_stack.push(format);
+            }
+            let end = render_html_nodes(state, ui, events, &element.children, salt);
+            if pushed {
+                state.inline_style_stack.pop();
+            }
+            return end;
+        }
+    }
+    None
+}
+
+fn html_block_level(name: &str) -> bool {
+    matches!(
+        name,
+        "p" | "div"
+            | "center"
+            | "section"
+            | "article"
+            | "header"
+            | "footer"
+            | "blockquote"
+            | "li"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+    )
+}
+
+fn html_has_block_children(element: &html::Element) -> bool {
+    element.children.iter().any(|child| match child {
+        html::Node::Element(e) => {
+            html_block_level(&e.name)
+                || matches!(
+                    e.name.as_str(),
+                    "table" | "details" | "pre" | "ul" | "ol" | "hr"
+                )
+        }
+        _ => false,
+    })
+}
+
+fn html_contains(element: &html::Element, name: &str) -> bool {
+    element.children.iter().any(|child| match child {
+        html::Node::Element(e) => e.name == name || html_contains(e, name),
+        _ => false,
+    })
+}
+
+/// Lays out inline content (text, badges, images) in a wrapped row centered
+/// using the row width measured on the previous frame.
+fn render_centered_html_row<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+    element: &html::Element,
+    salt: usize,
+) -> Option<BlockEnd> {
+    let width_id = ui.id().with(("centered_html_row", salt));
+    let available = ui.available_width();
+    let last_width: f32 = ui.data(|d| d.get_temp(width_id)).unwrap_or(available);
+    let (end, content_width) = ui
+        .horizontal_wrapped(|ui| {
+            ui.add_space(((available - last_width) / 2.0).max(0.0));
+            let content_start = ui.cursor().left();
+            let end = render_html_nodes(state, ui, events, &element.children, salt);
+            flush_inline_content(state, ui, false);
+            let content_width = ui.min_rect().right() - content_start;
+            (end, content_width.min(available))
+        })
+        .inner;
+    if (content_width - last_width).abs() > 0.5 {
+        ui.data_mut(|d| d.insert_temp(width_id, content_width));
+        ui.ctx().request_repaint();
+    }
+    end
+}
+
+/// `<details>` becomes a collapsible section. The body may continue with
+/// Markdown after the HTML block, up to the block containing `</details>`.
+fn render_html_details<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+    element: &html::Element,
+    salt: usize,
+) -> Option<BlockEnd> {
+    flush_block_content(state, ui);
+    let summary = element
+        .children
+        .iter()
+        .find_map(|child| match child {
+            html::Node::Element(e) if e.name == "summary" => Some(e.text()),
+            _ => None,
+        })
+        .filter(|text| !text.is_empty())
+        .unwrap_or_else(|| "Details".to_string());
+    let id = ui.make_persistent_id(("html_details", salt));
+    let collapsing = egui::collapsing_header::CollapsingState::load_with_default_open(
+        ui.ctx(),
+        id,
+        element.attr("open").is_some(),
+    );
+    let mut end = None;
+    let mut body_rendered = false;
+    collapsing
+        .show_header(ui, |ui| {
+            ui.label(RichText::new(summary).font(state.base_format.font_id.clone()));
+        })
+        .body(|ui| {
+            body_rendered = true;
+            end = render_html_nodes(state, ui, events, &element.children, salt);
+            if end.is_none() && !element.closed {
+                end = Some(render_details_body(state, ui, events));
+            }
+            flush_block_content(state, ui);
+        });
+    if !body_rendered && !element.closed {
+        end = Some(skip_details_body(state, events));
+    }
+    match end {
+        // The `</details>` was found; continue with whatever followed it.
+        Some(BlockEnd::DetailsClosed) if !element.closed => {
+            let rest = std::mem::take(&mut state.details_rest);
+            render_html_nodes(state, ui, events, &rest, salt.wrapping_add(1))
+        }
+        end => end,
+    }
+}
+
+fn render_details_body<'e>(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+) -> BlockEnd {
+    state.open_details += 1;
+    let end = render_events(state, ui, events);
+    state.open_details -= 1;
+    end
+}
+
+/// Consumes the Markdown inside a collapsed `<details>` up to its `</details>`.
+fn skip_details_body<'e>(
+    state: &mut RenderState<'_, '_>,
+    events: &mut impl Iterator<Item = SourceEvent<'e>>,
+) -> BlockEnd {
+    let mut nested_details = 0usize;
+    let mut containers = 0usize;
+    while let Some((event, _)) = events.next() {
+        match event {
+            Event::Start(Tag::HtmlBlock) => {
+                let mut raw = String::new();
+                for (event, _) in events.by_ref() {
+                    match event {
+                        Event::Html(text) | Event::Text(text) => raw.push_str(&text),
+                        Event::End(TagEnd::HtmlBlock) => break,
+                        _ => {}
                     }
                 }
+                let nodes = html::parse_fragment(&raw);
+                for (index, node) in nodes.iter().enumerate() {
+                    match node {
+                        html::Node::Element(e) if e.name == "details" && !e.closed => {
+                            nested_details += 1;
+                        }
+                        html::Node::StrayEnd(name) if name == "details" => {
+                            if nested_details == 0 {
+                                state.details_rest = nodes[index + 1..].to_vec();
+                                return BlockEnd::DetailsClosed;
+                            }
+                            nested_details -= 1;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::Start(Tag::BlockQuote(_) | Tag::List(_) | Tag::Item) => containers += 1,
+            Event::End(TagEnd::BlockQuote | TagEnd::List(_) | TagEnd::Item) => {
+                if containers == 0 {
+                    return BlockEnd::Container;
+                }
+                containers -= 1;
+            }
+            _ => {}
+        }
+    }
+    BlockEnd::Exhausted
+}
+
+fn collect_html_table_rows(
+    state: &mut RenderState<'_, '_>,
+    element: &html::Element,
+    rows: &mut Vec<Vec<TableCell>>,
+) {
+    for child in &element.children {
+        let html::Node::Element(child) = child else {
+            continue;
+        };
+        match child.name.as_str() {
+            "tr" => {
+                let cells = child
+                    .children
+                    .iter()
+                    .filter_map(|cell| match cell {
+                        html::Node::Element(cell) if cell.name == "td" || cell.name == "th" => {
+                            Some(TableCell {
+                                job: html_inline_job(state, cell),
+                                hotspots: Vec::new(),
+                            })
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                rows.push(cells);
+            }
+            "thead" | "tbody" | "tfoot" => collect_html_table_rows(state, child, rows),
+            _ => {}
+        }
+    }
+}
+
+/// Lays out an element's text with inline formatting only, for table cells.
+fn html_inline_job(state: &mut RenderState<'_, '_>, element: &html::Element) -> LayoutJob {
+    fn append(state: &mut RenderState<'_, '_>, job: &mut LayoutJob, nodes: &[html::Node]) {
+        for node in nodes {
+            match node {
+                html::Node::Text(text) => {
+                    let text = html::collapse_whitespace(text);
+                    let text = if job.is_empty() {
+                        text.trim_start()
+                    } else {
+                        &text
+                    };
+                    job.append(text, 0.0, current_format(state).clone());
+                }
+                html::Node::Element(e) if e.name == "br" => {
+                    job.append("\n", 0.0, current_format(state).clone());
+                }
+                html::Node::Element(e) if e.name == "img" => {
+                    let alt = e.attr("alt").unwrap_or("image");
+                    job.append(alt, 0.0, current_format(state).clone());
+                }
+                html::Node::Element(e) => {
+                    let mut format = html_inline_format(state, e);
+                    if e.name == "th" {
+                        format = Some(current_format(state).clone());
+                    } else if e.name == "a" && e.attr("href").is_some() {
+                        let mut link = current_format(state).clone();
+                        link.color = state.visuals.hyperlink_color;
+                        format = Some(link);
+                    }
+                    let pushed = format.is_some();
+                    if let Some(format) = format {
+                        state.inline_style_stack.push(format);
+                    }
+                    append(state, job, &e.children);
+                    if pushed {
+                        state.inline_style_stack.pop();
+                    }
+                }
+                html::Node::StrayEnd(_) => {}
+            }
+        }
+    }
+    let mut job = LayoutJob::default();
+    append(state, &mut job, &element.children);
+    job
+}
+
+/// The text format for inline formatting elements, or `None` for elements
+/// that don't change the text style.
+fn html_inline_format(state: &RenderState<'_, '_>, element: &html::Element) -> Option<TextFormat> {
+    let mut format = current_format(state).clone();
+    match element.name.as_str() {
+        "b" | "strong" => {
+            format.font_id = FontId::new(format.font_id.size, egui::FontFamily::Proportional);
+            format.color = state.visuals.strong_text_color();
+        }
+        "i" | "em" | "cite" | "var" => format.italics = true,
+        "s" | "del" | "strike" => format.strikethrough = Stroke::new(1.0, format.color),
+        "u" | "ins" => format.underline = Stroke::new(1.0, format.color),
+        "code" | "tt" | "samp" => {
+            format.font_id = FontId::monospace(CODE_FONT_SIZE);
+            format.background = state.visuals.code_bg_color;
+        }
+        "kbd" => {
+            format.font_id = FontId::monospace(CODE_FONT_SIZE * 0.95);
+            format.background = state.visuals.widgets.inactive.bg_fill;
+            format.color = state.visuals.strong_text_color();
+        }
+        "sup" => {
+            format.font_id.size *= 0.75;
+            format.valign = Align::TOP;
+        }
+        "sub" => {
+            format.font_id.size *= 0.75;
+            format.valign = Align::BOTTOM;
+        }
+        "small" => format.font_id.size *= 0.85,
+        "mark" => format.background = Color32::from_rgba_unmultiplied(255, 215, 0, 96),
+        _ => return None,
+    }
+    Some(format)
+}
+
+/// Handles a single inline tag inside a paragraph. Formatting is pushed onto
+/// the style stack until the matching end tag; unknown tags are ignored.
+fn render_inline_html(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui, raw: &str) {
+    for token in html::tokenize(raw) {
+        match token {
+            html::Token::Text(text) => {
+                if state.suppress_text == 0 {
+                    let format = current_format(state).clone();
+                    state.current_job.append(&text, 0.0, format);
+                }
+            }
+            html::Token::Start { name, attrs, .. } => {
+                let element = html::Element {
+                    name,
+                    attrs,
+                    children: Vec::new(),
+                    closed: true,
+                };
+                match element.name.as_str() {
+                    "br" => {
+                        let format = current_format(state).clone();
+                        state.current_job.append("\n", 0.0, format);
+                    }
+                    "img" => {
+                        flush_inline_content(state, ui, false);
+                        let size = ImageSize::from_html(&element, ui.available_width());
+                        render_image(
+                            ui,
+                            element.attr("src").unwrap_or_default(),
+                            element.attr("alt").unwrap_or_default(),
+                            size,
+                            &state.images,
+                        );
+                    }
+                    name if html::is_dropped(name) => state.suppress_text += 1,
+                    "a" => {
+                        let Some(url) = element.attr("href") else {
+                            continue;
+                        };
+                        let depth = state.inline_style_stack.len();
+                        let mut format = current_format(state).clone();
+                        format.color = state.visuals.hyperlink_color;
+                        format.underline = Stroke::new(1.0, format.color);
+                        state.inline_style_stack.push(format);
+                        state.link_start =
+                            Some((state.current_job.sections.len(), url.to_string()));
+                        state.inline_html.push((element.name, depth));
+                    }
+                    _ => {
+                        if let Some(format) = html_inline_format(state, &element) {
+                            let depth = state.inline_style_stack.len();
+                            state.inline_style_stack.push(format.clone());
+                            if element.name == "kbd" {
+                                let mut padding = format;
+                                padding.font_id.size *= 0.4;
+                                state.current_job.append(" ", 0.0, padding);
+                            }
+                            state.inline_html.push((element.name, depth));
+                        }
+                    }
+                }
+            }
+            html::Token::End(name) => {
+                if html::is_dropped(&name) {
+                    state.suppress_text = state.suppress_text.saturating_sub(1);
+                    continue;
+                }
+                let Some(index) = state.inline_html.iter().rposition(|(n, _)| *n == name) else {
+                    continue;
+                };
+                if name == "kbd" {
+                    let mut padding = current_format(state).clone();
+                    padding.font_id.size *= 0.4;
+                    state.current_job.append(" ", 0.0, padding);
+                }
+                let (_, depth) = state.inline_html[index];
+                for (open, _) in state.inline_html.drain(index..) {
+                    if open == "a" {
+                        if let Some((start, url)) = state.link_start.take() {
+                            let end = state.current_job.sections.len();
+                            state.hotspots.push((start..end, Hotspot::Link(url)));
+                        }
+                    }
+                }
+                state.inline_style_stack.truncate(depth);
+            }
+        }
+    }
+}
+
+/// Drops formatting left open by unclosed inline HTML at the end of a block.
+fn close_inline_html(state: &mut RenderState<'_, '_>) {
+    if let Some(&(_, depth)) = state.inline_html.first() {
+        state.inline_style_stack.truncate(depth);
+        state.inline_html.clear();
+    }
+    state.suppress_text = 0;
+}
+
+fn current_format<'a>(state: &'a RenderState<'a, '_>) -> &'a TextFormat {
+    state
+        .inline_style_stack
+        .last()
+        .unwrap_or(&state.base_format)
+}
+
+fn flush_inline_content(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    add_paragraph_spacing: bool,
+) {
+    if !state.current_job.is_empty() {
+        let mut job_to_render = std::mem::take(&mut state.current_job);
+        let hotspots = std::mem::take(&mut state.hotspots);
+        let inline_math = std::mem::take(&mut state.inline_math);
+        if hotspots.is_empty() && inline_math.is_empty() && state.line_height == 1.0 {
+            ui.label(job_to_render);
+        } else {
+            // Lay out the galley ourselves so hotspots can be hit-tested per
+            // glyph and rows can be spaced out.
+            job_to_render.wrap.max_width = ui.available_width();
+            job_to_render.halign = ui.layout().horizontal_placement();
+            let galley = ui.fonts(|f| f.layout_job(job_to_render));
+            let galley = space_rows(galley, state.line_height);
+            let mut sense = Sense::click();
+            sense.focusable = false;
+            let response = ui.add(egui::Label::new(galley.clone()).sense(sense));
+            let origin = match galley.job.halign {
+                Align::LEFT => response.rect.left_top(),
+                Align::Center => response.rect.center_top(),
+                Align::RIGHT => response.rect.right_top(),
+            };
+            for (sections, hotspot) in hotspots {
+                let rects = hotspot_rects(&galley, origin, &sections);
+                handle_hotspot(state, ui, &response, &rects, hotspot);
+            }
+            for (section, formula, color) in inline_math {
+                let glyph = galley
+                    .rows
+                    .iter()
+                    .flat_map(|row| &row.glyphs)
+                    .find(|glyph| glyph.section_index as usize == section);
+                if let Some(glyph) = glyph {
+                    formula.paint(ui.painter(), origin + glyph.pos.to_vec2(), color);
+                }
+            }
+        }
+        if add_paragraph_spacing {
+            ui.add_space(state.theme.spacing.paragraph);
+        }
+    }
+    state.current_job = LayoutJob::default();
+    state.hotspots.clear();
+    state.inline_math.clear();
+}
+
+/// Appends the pending document text. Wiki links and embeds are split out
+/// first; the rest goes through `append_plain`.
+fn flush_text(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui) {
+    if state.pending_text.is_empty() {
+        return;
+    }
+    let text = std::mem::take(&mut state.pending_text);
+    if state.link_start.is_some() || state.html_link.is_some() {
+        append_plain(state, &text, false);
+        return;
+    }
+    if !state.extensions.wiki_links {
+        append_plain(state, &text, true);
+        return;
+    }
+    for piece in wiki::split(&text) {
+        match piece {
+            wiki::Piece::Text(text) => append_plain(state, text, true),
+            wiki::Piece::Link(link) if link.embed && !in_table(state) => {
+                render_embed(state, ui, &link)
+            }
+            wiki::Piece::Link(link) => append_wiki_link(state, &link),
+        }
+    }
+}
+
+/// Appends text, expanding emoji shortcodes and, if `autolink` is set,
+/// linking bare URLs, email addresses and issue references.
+fn append_plain(state: &mut RenderState<'_, '_>, text: &str, autolink: bool) {
+    let in_table = in_table(state);
+    let autolink = autolink && state.extensions.autolinks;
+    for span in autolink::expand(text, &state.links, autolink) {
+        let format = current_format(state).clone();
+        // Table cells are laid out from their own job.
+        match span {
+            autolink::Span::Text(text) => {
+                let job = if in_table {
+                    &mut state.current_cell_job
+                } else {
+                    &mut state.current_job
+                };
+                append_marked(job, &text, format, state.document.highlight, state.visuals);
+            }
+            autolink::Span::Link { text, url } => {
+                let mut link_format = format;
+                link_format.color = state.visuals.hyperlink_color;
+                link_format.underline = Stroke::new(1.0, link_format.color);
+                if in_table {
+                    append_cell_hotspot(state, &text, link_format, Hotspot::Link(url));
+                } else {
+                    append_hotspot(state, &text, link_format, Hotspot::Link(url));
+                }
+            }
+        }
+    }
+}
+
+/// Appends `text`, marking the words matched by the search the document was
+/// opened from.
+fn append_marked(
+    job: &mut LayoutJob,
+    text: &str,
+    format: TextFormat,
+    highlight: Option<&search::Highlight>,
+    visuals: &egui::Visuals,
+) {
+    let Some(highlight) = highlight else {
+        job.append(text, 0.0, format);
+        return;
+    };
+    let mut end = 0;
+    for range in highlight.query.highlights(text) {
+        job.append(&text[end..range.start], 0.0, format.clone());
+        let mut marked = format.clone();
+        marked.background = search::highlight_color(visuals);
+        job.append(&text[range.clone()], 0.0, marked);
+        end = range.end;
+    }
+    job.append(&text[end..], 0.0, format);
+}
+
+fn resolve_wiki_link<'v>(state: &RenderState<'_, 'v>, link: &wiki::WikiLink) -> Option<&'v Path> {
+    state
+        .document
+        .vault?
+        .resolve(&link.target, state.document.path)
+}
+
+/// A `[[wiki link]]`. Links to notes that don't exist are dimmed.
+fn append_wiki_link(state: &mut RenderState<'_, '_>, link: &wiki::WikiLink) {
+    let mut format = current_format(state).clone();
+    let resolved = resolve_wiki_link(state, link);
+    let hotspot = if link.target.is_empty() {
+        Hotspot::Note {
+            path: None,
+            heading: link.heading.clone(),
+        }
+    } else if let Some(path) = resolved {
+        Hotspot::Note {
+            path: Some(path.to_path_buf()),
+            heading: link.heading.clone(),
+        }
+    } else {
+        Hotspot::Tooltip(format!("No note named '{}' in this folder", link.target))
+    };
+    format.color = match hotspot {
+        Hotspot::Tooltip(_) => state.visuals.hyperlink_color.gamma_multiply(0.6),
+        _ => state.visuals.hyperlink_color,
+    };
+    format.underline = Stroke::new(1.0, format.color);
+    let label = link.label();
+    if in_table(state) {
+        append_cell_hotspot(state, &label, format, hotspot);
+    } else {
+        append_hotspot(state, &label, format, hotspot);
+    }
+}
+
+/// Deepest level of notes embedded in notes.
+const MAX_EMBED_DEPTH: usize = 3;
+
+/// `![[image.png]]` shows the image; `![[Note]]` or `![[Note#Heading]]` shows
+/// the note, or the section under the heading, in a frame.
+fn render_embed(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui, link: &wiki::WikiLink) {
+    let Some(path) = resolve_wiki_link(state, link) else {
+        append_wiki_link(state, link);
+        return;
+    };
+    if wiki::is_image(path) {
+        flush_inline_content(state, ui, false);
+        // `![[image.png|300]]` or `![[image.png|300x200]]` sets the size.
+        let mut size = ImageSize::default();
+        if let Some(alias) = &link.alias {
+            let (width, height) = alias.split_once('x').unwrap_or((alias, ""));
+            size.width = width.trim().parse().ok();
+            size.height = height.trim().parse().ok();
+        }
+        render_image(
+            ui,
+            &path.to_string_lossy(),
+            &link.target,
+            size,
+            &state.images,
+        );
+        return;
+    }
+    let content = embedded_note(ui, path);
+    let (Some(content), true) = (
+        content.as_deref(),
+        state.embed_depth < MAX_EMBED_DEPTH && Some(path) != state.document.path,
+    ) else {
+        // Not a note, or an embed that would recurse.
+        append_wiki_link(state, link);
+        return;
+    };
+    let content = match &link.heading {
+        Some(heading) => wiki::section(content, heading).unwrap_or(content),
+        None => content,
+    };
+    flush_inline_content(state, ui, false);
+    let visuals = state.visuals;
+    Frame::none()
+        .fill(visuals.faint_bg_color)
+        .stroke(Stroke::new(
+            1.0,
+            visuals.widgets.noninteractive.bg_stroke.color,
+        ))
+        .rounding(Rounding::same(4.0))
+        .inner_margin(Margin::same(8.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            if ui
+                .link(RichText::new(format!("📄 {}", link.label())).small())
+                .on_hover_text(path.display().to_string())
+                .clicked()
+            {
+                state.actions.push(DocAction::OpenNote(path.to_path_buf()));
+            }
+            let parser = Parser::new_ext(content, state.extensions.options()).into_offset_iter();
+            let (body, footnotes) = Footnotes::extract(parser);
+            let document = Document {
+                markdown: content,
+                path: Some(path),
+                vault: state.document.vault,
+                highlight: None,
+            };
+            let mut embedded = RenderState::new(
+                visuals,
+                state.theme,
+                state.syntect_theme,
+                &footnotes,
+                document,
+                state.links.clone(),
+            );
+            embedded.embed_depth = state.embed_depth + 1;
+            embedded.line_height = state.line_height;
+            embedded.extensions = state.extensions.clone();
+            embedded.images = state.images.clone();
+            // Links are counted across the whole document.
+            embedded.link_focus = state.link_focus;
+            embedded.link_tops = std::mem::take(&mut state.link_tops);
+            render_events(&mut embedded, ui, &mut body.into_iter());
+            flush_block_content(&mut embedded, ui);
+            state.link_tops = embedded.link_tops;
+            // Task offsets refer to the embedded note, so only navigation carries over.
+            state.actions.extend(
+                embedded.actions.into_iter().filter(|action| {
+                    matches!(action, DocAction::OpenNote(_) | DocAction::OpenUrl(_))
+                }),
+            );
+        });
+    ui.add_space(4.0);
+}
+
+/// The text of an embedded note, read again only when the file changes.
+fn embedded_note(ui: &egui::Ui, path: &Path) -> Option<Arc<str>> {
+    if !wiki::is_note(path) {
+        return None;
+    }
+    let metadata = fs::metadata(path).ok()?;
+    let stamp = (metadata.modified().ok(), metadata.len());
+    let id = egui::Id::new(("embedded_note", path));
+    let cached: Option<(Option<SystemTime>, u64, Arc<str>)> = ui.data(|d| d.get_temp(id));
+    match cached {
+        Some((modified, len, content)) if (modified, len) == stamp => Some(content),
+        _ => {
+            let content: Arc<str> = wiki::read_note(path)?.into();
+            ui.data_mut(|d| d.insert_temp(id, (stamp.0, stamp.1, Arc::clone(&content))));
+            Some(content)
+        }
+    }
+}
+
+fn append_hotspot(
+    state: &mut RenderState<'_, '_>,
+    text: &str,
+    format: TextFormat,
+    hotspot: Hotspot,
+) {
+    let start = state.current_job.sections.len();
+    state.current_job.append(text, 0.0, format);
+    let end = state.current_job.sections.len();
+    state.hotspots.push((start..end, hotspot));
+}
+
+fn append_cell_hotspot(
+    state: &mut RenderState<'_, '_>,
+    text: &str,
+    format: TextFormat,
+    hotspot: Hotspot,
+) {
+    let start = state.current_cell_job.sections.len();
+    state.current_cell_job.append(text, 0.0, format);
+    let end = state.current_cell_job.sections.len();
+    state.cell_hotspots.push((start..end, hotspot));
+}
+
+/// Moves the rows of `galley` apart so each line is `factor` times its
+/// natural height. The first row stays put so it lines up with list markers.
+fn space_rows(mut galley: Arc<Galley>, factor: f32) -> Arc<Galley> {
+    if factor == 1.0 || galley.rows.len() < 2 {
+        return galley;
+    }
+    let galley_mut = Arc::make_mut(&mut galley);
+    let pixels_per_point = galley_mut.pixels_per_point;
+    let mut shift = 0.0;
+    let mut offset = egui::Vec2::ZERO;
+    for row in &mut galley_mut.rows {
+        offset.y = (shift * pixels_per_point).round() / pixels_per_point;
+        row.rect = row.rect.translate(offset);
+        row.visuals.mesh.translate(offset);
+        row.visuals.mesh_bounds = row.visuals.mesh_bounds.translate(offset);
+        for glyph in &mut row.glyphs {
+            glyph.pos += offset;
+        }
+        shift += row.rect.height() * (factor - 1.0);
+    }
+    galley_mut.rect.max.y += offset.y;
+    galley_mut.mesh_bounds.max.y += offset.y;
+    galley
+}
+
+/// Screen rects covered by the given sections, one per galley row.
+fn hotspot_rects(galley: &Galley, origin: Pos2, sections: &Range<usize>) -> Vec<Rect> {
+    galley
+        .rows
+        .iter()
+        .filter_map(|row| {
+            row.glyphs
+                .iter()
+                .filter(|glyph| sections.contains(&(glyph.section_index as usize)))
+                .map(|glyph| glyph.logical_rect())
+                .reduce(|a, b| a.union(b))
+                .map(|rect| rect.translate(origin.to_vec2()))
+        })
+        .collect()
+}
+
+fn handle_hotspot(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    response: &Response,
+    rects: &[Rect],
+    hotspot: Hotspot,
+) {
+    let Some(&first_rect) = rects.first() else {
+        return;
+    };
+    let hovered = response
+        .hover_pos()
+        .is_some_and(|pos| rects.iter().any(|r| r.contains(pos)));
+    if hovered && !matches!(hotspot, Hotspot::Tooltip(_)) {
+        ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+    }
+    let mut clicked = hovered && response.clicked();
+    if !matches!(hotspot, Hotspot::Tooltip(_)) {
+        let focused = state.link_focus.index == Some(state.link_tops.len());
+        state.link_tops.push(first_rect.top());
+        if focused {
+            let stroke = Stroke::new(2.0, ui.visuals().selection.stroke.color);
+            for rect in rects {
+                ui.painter().rect_stroke(rect.expand(2.0), 2.0, stroke);
             }
-            Event::Code(text) => {
-                let mut code_format = current_format(&state).clone();
-                code_format.font_id = FontId::monospace(CODE_FONT_SIZE);
-                code_format.background = state.visuals.code_bg_color;
-                if state.current_link_url.is_some() {
-                    state.current_link_text.push('`');
-                    state.current_link_text.push_str(&text);
-                    state.current_link_text.push('`');
-                } else {
-                    state.current_job.append("`", 0.0, code_format.clone());
-                    state.current_job.append(&text, 0.0, code_format.clone());
-                    state.current_job.append("`", 0.0, code_format.clone());
-                    if state
-                        .block_stack
-                        .last()
-                        .map_or(false, |b| matches!(b, BlockInfo::Table))
-                    {
-                        state
-                            .current_cell_job
-                            .append(&format!("`{}`", text), 0.0, code_format);
-                    }
-                }
+            if state.link_focus.reveal {
+                let all = rects.iter().fold(first_rect, |all, rect| all.union(*rect));
+                ui.scroll_to_rect(all.expand(8.0), None);
             }
-            Event::Html(html) => {
-                if html.trim() == "<br>" || html.trim() == "<br/>" || html.trim() == "<br />" {
-                    state
-                        .current_job
-                        .append("\n", 0.0, current_format(&state).clone());
-                } else {
-                    log::debug!("Ignoring HTML: {}", html);
-                    let mut fmt = current_format(&state).clone();
-                    fmt.italics = true;
-                    fmt.color = state.visuals.weak_text_color();
-                    state
-                        .current_job
-                        .append(&format!("[HTML: {}]", html.trim()), 0.0, fmt);
-                }
+            clicked |= state.link_focus.follow;
+        }
+    }
+    match hotspot {
+        Hotspot::Link(url) => {
+            if hovered {
+                egui::show_tooltip_at_pointer(ui.ctx(), response.id.with(&url), |ui| {
+                    ui.label(&url);
+                });
             }
-            Event::InlineHtml(html) => {
-                if html.trim() == "<br>" || html.trim() == "<br/>" || html.trim() == "<br />" {
-                    state
-                        .current_job
-                        .append("\n", 0.0, current_format(&state).clone());
+            if clicked {
+                if let Some(anchor) = url.strip_prefix('#') {
+                    request_jump(ui, heading_anchor(anchor));
+                } else if let Some((path, anchor)) = local_note_link(state, &url) {
+                    if let Some(anchor) = anchor {
+                        request_jump(ui, heading_anchor(anchor));
+                    }
+                    state.actions.push(DocAction::OpenNote(path));
                 } else {
-                    log::debug!("Ignoring inline HTML: {}", html);
-                    let mut fmt = current_format(&state).clone();
-                    fmt.italics = true;
-                    fmt.color = state.visuals.weak_text_color();
-                    state
-                        .current_job
-                        .append(&format!("[Inline HTML: {}]", html.trim()), 0.0, fmt);
+                    state.actions.push(DocAction::OpenUrl(url));
                 }
             }
-            Event::FootnoteReference(label) => {
-                let mut fmt = current_format(&state).clone();
-                fmt.font_id.size *= 0.8;
-                fmt.valign = egui::Align::TOP;
-                let text = format!("[^{}]", label);
-                state.current_job.append(&text, 0.0, fmt);
+        }
+        Hotspot::Note { path, heading } => {
+            let other = path.filter(|path| Some(path.as_path()) != state.document.path);
+            if hovered {
+                let hint = match (&other, &heading) {
+                    (Some(path), _) => path.display().to_string(),
+                    (None, Some(heading)) => format!("#{}", heading),
+                    (None, None) => "This note".to_string(),
+                };
+                egui::show_tooltip_at_pointer(ui.ctx(), response.id.with(&hint), |ui| {
+                    ui.label(&hint);
+                });
             }
-            Event::SoftBreak => {
-                if state.highlighter.is_some() {
-                    state.code_block_content.push('\n');
-                } else if state.current_link_url.is_some() {
-                    state.current_link_text.push(' ');
-                } else {
-                    state
-                        .current_job
-                        .append(" ", 0.0, current_format(&state).clone());
+            if clicked {
+                if let Some(heading) = heading {
+                    request_jump(ui, heading_anchor(&heading));
                 }
-            }
-            Event::HardBreak => {
-                if state.highlighter.is_some() {
-                    state.code_block_content.push('\n');
-                } else if state.current_link_url.is_some() {
-                    state.current_link_text.push('\n');
-                } else {
-                    state
-                        .current_job
-                        .append("\n", 0.0, current_format(&state).clone());
+                if let Some(path) = other {
+                    state.actions.push(DocAction::OpenNote(path));
                 }
             }
-            Event::Rule => {
-                flush_block_content(&mut state);
-                state.ui.add_space(8.0);
-                state.ui.add(Separator::default().horizontal());
-                state.ui.add_space(8.0);
+        }
+        Hotspot::FootnoteRef(label) => {
+            register_anchor(ui, &format!("fnref:{}", label), first_rect);
+            if let Some(events) = state.footnotes.definitions.get(&label).filter(|_| hovered) {
+                let (visuals, theme, syntect_theme, footnotes, document) = (
+                    state.visuals,
+                    state.theme,
+                    state.syntect_theme,
+                    state.footnotes,
+                    state.document,
+                );
+                let links = state.links.clone();
+                egui::show_tooltip_at_pointer(ui.ctx(), response.id.with(&label), |ui| {
+                    ui.set_max_width(400.0);
+                    let mut tooltip_state =
+                        RenderState::new(visuals, theme, syntect_theme, footnotes, document, links);
+                    render_events(&mut tooltip_state, ui, &mut events.iter().cloned());
+                    flush_block_content(&mut tooltip_state, ui);
+                });
             }
-            Event::TaskListMarker(checked) => {
-                let marker = if checked { "[x] " } else { "[ ] " };
-                let mut task_job = LayoutJob::default();
-                task_job.append(marker, 0.0, current_format(&state).clone());
-                task_job.append_job(std::mem::take(&mut state.current_job)); // Corrected append
-                state.current_job = task_job;
-                if state
-                    .block_stack
-                    .last()
-                    .map_or(false, |b| matches!(b, BlockInfo::Table))
-                {
-                    let mut cell_task_job = LayoutJob::default();
-                    cell_task_job.append(marker, 0.0, current_format(&state).clone());
-                    cell_task_job.append_job(std::mem::take(&mut state.current_cell_job)); // Corrected append
-                    state.current_cell_job = cell_task_job;
-                }
+            if clicked {
+                request_jump(ui, format!("fn:{}", label));
+            }
+        }
+        Hotspot::FootnoteBackRef(label) => {
+            if clicked {
+                request_jump(ui, format!("fnref:{}", label));
+            }
+        }
+        Hotspot::Tooltip(text) => {
+            if hovered {
+                egui::show_tooltip_at_pointer(ui.ctx(), response.id.with(&text), |ui| {
+                    ui.label(&text);
+                });
             }
         }
     }
-    flush_block_content(&mut state);
 }
 
-fn current_format<'a>(state: &'a RenderState<'a, '_>) -> &'a TextFormat {
-    state
-        .inline_style_stack
-        .last()
-        .unwrap_or(&state.base_format)
+/// Jump target for the heading with the given text or GitHub-style slug.
+fn heading_anchor(heading: &str) -> String {
+    format!("heading:{}", wiki::slug(heading))
 }
 
-fn flush_inline_content(state: &mut RenderState<'_, '_>, add_paragraph_spacing: bool) {
-    if !state.current_job.is_empty() {
-        let job_to_render = std::mem::take(&mut state.current_job);
-        state.ui.label(job_to_render);
-        if add_paragraph_spacing {
-            state.ui.add_space(4.0);
+/// A relative link to a Markdown file next to this one, with its `#fragment`.
+fn local_note_link<'u>(
+    state: &RenderState<'_, '_>,
+    url: &'u str,
+) -> Option<(PathBuf, Option<&'u str>)> {
+    if url.contains("://") || url.starts_with("mailto:") {
+        return None;
+    }
+    let (file, anchor) = match url.split_once('#') {
+        Some((file, anchor)) => (file, Some(anchor).filter(|a| !a.is_empty())),
+        None => (url, None),
+    };
+    let dir = state.document.path?.parent()?;
+    let path = wiki::normalize(&dir.join(file.replace("%20", " ")));
+    (wiki::is_note(&path) && path.is_file()).then_some((path, anchor))
+}
+
+fn pending_jump_id() -> egui::Id {
+    egui::Id::new("markdown_pending_jump")
+}
+
+/// Anchor of the search hit a document was opened at.
+const SEARCH_HIT_ANCHOR: &str = "search-hit";
+
+/// Registers the search hit's anchor at the start of the block holding it.
+fn anchor_search_hit(state: &mut RenderState<'_, '_>, ui: &egui::Ui, range: &Range<usize>) {
+    let Some(highlight) = state.document.highlight else {
+        return;
+    };
+    if state.search_hit_anchored || !range.contains(&highlight.offset) {
+        return;
+    }
+    state.search_hit_anchored = true;
+    let rect = Rect::from_min_size(ui.cursor().min, egui::vec2(ui.available_width(), 1.0));
+    register_anchor(ui, SEARCH_HIT_ANCHOR, rect);
+}
+
+/// Asks the document to scroll to `anchor` once it is laid out.
+fn request_jump(ui: &egui::Ui, anchor: String) {
+    ui.data_mut(|d| d.insert_temp(pending_jump_id(), anchor));
+    ui.ctx().request_repaint();
+}
+
+/// Records where `anchor` was drawn, scrolling to it if a jump is pending.
+fn register_anchor(ui: &egui::Ui, anchor: &str, rect: Rect) {
+    let pending = ui.data(|d| d.get_temp::<String>(pending_jump_id()));
+    if pending.as_deref() == Some(anchor) {
+        ui.scroll_to_rect(rect, Some(Align::TOP));
+        ui.data_mut(|d| d.remove::<String>(pending_jump_id()));
+    }
+}
+
+fn render_footnotes_section(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui) {
+    let footnotes = state.footnotes;
+    if footnotes.order.is_empty() {
+        return;
+    }
+    ui.add_space(12.0);
+    ui.separator();
+    ui.label(RichText::new("Footnotes").weak());
+    ui.add_space(4.0);
+    let (visuals, theme, syntect_theme, document) = (
+        state.visuals,
+        state.theme,
+        state.syntect_theme,
+        state.document,
+    );
+    let links = state.links.clone();
+    for (index, label) in footnotes.order.iter().enumerate() {
+        let mut events = footnotes.definitions[label].clone();
+        // The back-link goes at the end of the last paragraph, like GitHub.
+        if matches!(events.last(), Some((Event::End(TagEnd::Paragraph), _))) {
+            events.pop();
         }
+        let response = ui
+            .horizontal_top(|ui| {
+                ui.label(
+                    RichText::new(format!("{}.", index + 1))
+                        .font(FontId::new(BODY_FONT_SIZE, egui::FontFamily::Proportional)),
+                );
+                ui.vertical(|ui| {
+                    let mut entry = RenderState::new(
+                        visuals,
+                        theme,
+                        syntect_theme,
+                        footnotes,
+                        document,
+                        links.clone(),
+                    );
+                    entry.link_focus = state.link_focus;
+                    entry.link_tops = std::mem::take(&mut state.link_tops);
+                    render_events(&mut entry, ui, &mut events.into_iter());
+                    let mut link_format = entry.base_format.clone();
+                    link_format.color = visuals.hyperlink_color;
+                    if !entry.current_job.is_empty() {
+                        let space_format = entry.base_format.clone();
+                        entry.current_job.append(" ", 0.0, space_format);
+                    }
+                    append_hotspot(
+                        &mut entry,
+                        "↩",
+                        link_format,
+                        Hotspot::FootnoteBackRef(label.clone()),
+                    );
+                    flush_inline_content(&mut entry, ui, true);
+                    state.actions.append(&mut entry.actions);
+                    state.link_tops = entry.link_tops;
+                });
+            })
+            .response;
+        register_anchor(ui, &format!("fn:{}", label), response.rect);
     }
-    state.current_job = LayoutJob::default();
 }
 
-fn flush_block_content(state: &mut RenderState<'_, '_>) {
+fn flush_block_content(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui) {
     let needs_flush = !state.current_job.is_empty();
     if needs_flush {
         let is_paragraph_like = state.block_stack.is_empty()
             || matches!(
                 state.block_stack.last(),
-                Some(BlockInfo::List(_)) | Some(BlockInfo::BlockQuote)
+                Some(BlockInfo::List) | Some(BlockInfo::BlockQuote)
             );
-        flush_inline_content(state, is_paragraph_like);
+        flush_inline_content(state, ui, is_paragraph_like);
+    }
+}
+
+/// What a fence info string like ```` ```rust {3,7-9} title="main.rs" ```` asks for.
+#[derive(Debug, Default)]
+struct CodeInfo {
+    /// Language to highlight with; `diff-rust` highlights as `rust`.
+    language: Option<String>,
+    /// `diff` or `diff-<language>`: added and removed lines are coloured.
+    diff: bool,
+    /// 1-based lines to emphasise.
+    highlights: Vec<std::ops::RangeInclusive<usize>>,
+    title: Option<String>,
+}
+
+impl CodeInfo {
+    fn parse(info: &str) -> Self {
+        let info = info.trim();
+        let token_end = info
+            .find(|c: char| c.is_whitespace() || c == '{')
+            .unwrap_or(info.len());
+        let (token, mut rest) = info.split_at(token_end);
+        let mut code_info = Self::default();
+        if let Some(language) = token.strip_prefix("diff-").filter(|l| !l.is_empty()) {
+            code_info.diff = true;
+            code_info.language = Some(language.to_string());
+        } else if !token.is_empty() {
+            code_info.diff = token == "diff" || token == "patch";
+            code_info.language = Some(token.to_string());
+        }
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(ranges) = rest.strip_prefix('{') {
+                let end = ranges.find('}').unwrap_or(ranges.len());
+                for range in ranges[..end].split([',', ' ']).map(str::trim) {
+                    let (first, last) = range.split_once('-').unwrap_or((range, range));
+                    if let (Ok(first), Ok(last)) = (first.trim().parse(), last.trim().parse()) {
+                        code_info.highlights.push(first..=last);
+                    }
+                }
+                rest = ranges.get(end + 1..).unwrap_or("");
+            } else if let Some(value) = rest.strip_prefix("title=") {
+                let (title, after) = match value.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let value = &value[1..];
+                        let end = value.find(quote).unwrap_or(value.len());
+                        (&value[..end], value.get(end + 1..).unwrap_or(""))
+                    }
+                    _ => value.split_at(value.find(char::is_whitespace).unwrap_or(value.len())),
+                };
+                code_info.title = (!title.is_empty()).then(|| title.to_string());
+                rest = after;
+            } else {
+                // Unknown attribute.
+                rest = &rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..];
+            }
+        }
+        code_info
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.highlights.iter().any(|range| range.contains(&line))
+    }
+
+    /// Name shown in the header, e.g. "Rust" or "Rust diff".
+    fn language_name(&self) -> String {
+        let Some(token) = self.language.as_deref() else {
+            return "Text".to_string();
+        };
+        let name = highlighting::syntaxes()
+            .and_then(|set| set.find_syntax_by_token(token))
+            .map_or(token, |syntax| syntax.name.as_str());
+        if self.diff && token != "diff" && token != "patch" {
+            format!("{} diff", name)
+        } else {
+            name.to_string()
+        }
     }
 }
 
-fn render_code_block(state: &mut RenderState<'_, '_>) {
+/// View options of a code block, toggled from its header.
+#[derive(Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+struct CodeBlockOptions {
+    line_numbers: bool,
+    wrap: bool,
+}
+
+/// Draws a code block with a header showing its title and language, a copy
+/// button and toggles for line numbers and wrapping. `salt`, the block's
+/// source offset, keeps the toggles of identical blocks apart.
+fn render_code_block(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui, salt: usize) {
     let code = std::mem::take(&mut state.code_block_content);
-    let _language_name = state.code_language.as_deref().unwrap_or("text");
+    let info = std::mem::take(&mut state.code_info);
+    let visuals = state.visuals;
+    let plain_format = TextFormat {
+        font_id: FontId::monospace(CODE_FONT_SIZE),
+        color: visuals.text_color(),
+        ..Default::default()
+    };
+    let (added, removed) = if visuals.dark_mode {
+        (
+            Color32::from_rgb(63, 185, 80),
+            Color32::from_rgb(248, 81, 73),
+        )
+    } else {
+        (
+            Color32::from_rgb(26, 127, 55),
+            Color32::from_rgb(209, 36, 47),
+        )
+    };
+    // With `diff-<language>` the +/- marker is kept out of the highlighter.
+    let split_marker = info.diff && info.language.as_deref().is_some_and(|l| l != "diff");
+    let mut job = LayoutJob::default();
+    let mut line_backgrounds = Vec::new();
+    for (index, line) in LinesWithEndings::from(&code).enumerate() {
+        let marker_color = match line.as_bytes().first() {
+            Some(b'+') if info.diff => Some(added),
+            Some(b'-') if info.diff => Some(removed),
+            _ => None,
+        };
+        line_backgrounds.push(if info.is_highlighted(index + 1) {
+            Some(visuals.selection.bg_fill.gamma_multiply(0.35))
+        } else {
+            marker_color.map(|color| color.gamma_multiply(0.15))
+        });
+        let mut content = line;
+        if split_marker && !line.is_empty() {
+            let marker_len = line.chars().next().map_or(0, char::len_utf8);
+            let mut marker_format = plain_format.clone();
+            if let Some(color) = marker_color {
+                marker_format.color = color;
+            }
+            job.append(&line[..marker_len], 0.0, marker_format);
+            content = &line[marker_len..];
+        }
+        let (Some(highlighter), Some(syntaxes)) =
+            (state.highlighter.as_mut(), highlighting::syntaxes())
+        else {
+            job.append(content, 0.0, plain_format.clone());
+            continue;
+        };
+        match highlighter.highlight_line(content, syntaxes) {
+            Ok(ranges) => {
+                for (style, text) in ranges {
+                    job.append(text, 0.0, syntect_style_to_text_format(style));
+                }
+            }
+            Err(e) => {
+                log::error!("Syntect highlighting error: {}", e);
+                job.append(content, 0.0, plain_format.clone());
+                state.highlighter = None;
+            }
+        }
+    }
+    if job.is_empty() {
+        job.append("", 0.0, plain_format);
+    }
+    let options_id = ui.make_persistent_id(("code_block", salt));
+    let mut options: CodeBlockOptions =
+        ui.data_mut(|d| *d.get_persisted_mut_or_default(options_id));
     let frame = Frame::none()
-        .fill(state.visuals.code_bg_color)
+        .fill(visuals.code_bg_color)
         .inner_margin(Margin::symmetric(6.0, 4.0))
         .rounding(Rounding::same(4.0));
-    frame.show(state.ui, |ui| {
-        ScrollArea::horizontal()
-            .id_source(ui.next_auto_id())
-            .show(ui, |ui| {
-                let mut job = LayoutJob::default();
-                if let Some(highlighter) = state.highlighter.as_mut() {
-                    for line in LinesWithEndings::from(&code) {
-                        match highlighter.highlight_line(line, &SYNTAX_SET) {
-                            Ok(ranges) => {
-                                for (style, text) in ranges {
-                                    job.append(text, 0.0, syntect_style_to_text_format(style));
-                                }
-                            }
-                            Err(e) => {
-                                log::error!("Syntect highlighting error: {}", e);
-                                job.append(
-                                    &code,
-                                    0.0,
-                                    TextFormat {
-                                        font_id: FontId::monospace(CODE_FONT_SIZE),
-                                        color: state.visuals.text_color(),
-                                        ..Default::default()
-                                    },
-                                );
-                                break;
-                            }
-                        }
-                    }
+    frame.show(ui, |ui| {
+        ui.set_width(ui.available_width());
+        ui.horizontal(|ui| {
+            if let Some(title) = &info.title {
+                ui.label(RichText::new(title).small().strong());
+                ui.label(RichText::new(info.language_name()).small().weak());
+            } else {
+                ui.label(RichText::new(info.language_name()).small());
+            }
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui
+                    .small_button("🗐")
+                    .on_hover_text("Copy to clipboard")
+                    .clicked()
+                {
+                    ui.output_mut(|o| o.copied_text = code.trim_end_matches('\n').to_string());
+                }
+                ui.toggle_value(&mut options.wrap, RichText::new("↩").small())
+                    .on_hover_text("Wrap long lines");
+                ui.toggle_value(&mut options.line_numbers, RichText::new("#").small())
+                    .on_hover_text("Show line numbers");
+            });
+        });
+        ui.add_space(2.0);
+        if options.wrap {
+            render_code_lines(ui, job, &line_backgrounds, options.line_numbers, true);
+        } else {
+            ScrollArea::horizontal()
+                .id_source(ui.next_auto_id())
+                .show(ui, |ui| {
+                    render_code_lines(ui, job, &line_backgrounds, options.line_numbers, false)
+                });
+        }
+    });
+    ui.data_mut(|d| d.insert_persisted(options_id, options));
+}
+
+/// Lays out highlighted code, with line numbers painted in a gutter so they
+/// aren't part of the selectable text. `line_backgrounds` tints whole lines.
+fn render_code_lines(
+    ui: &mut egui::Ui,
+    mut job: LayoutJob,
+    line_backgrounds: &[Option<Color32>],
+    line_numbers: bool,
+    wrap: bool,
+) {
+    let line_count = job.text.lines().count().max(1);
+    let gutter_width = if line_numbers {
+        let digits = line_count.to_string().len() as f32;
+        let digit_width = ui.fonts(|f| f.glyph_width(&FontId::monospace(CODE_FONT_SIZE), '0'));
+        digits * digit_width + 12.0
+    } else {
+        0.0
+    };
+    job.wrap.max_width = if wrap {
+        (ui.available_width() - gutter_width).max(40.0)
+    } else {
+        f32::INFINITY
+    };
+    let galley = ui.fonts(|f| f.layout_job(job));
+    ui.horizontal_top(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        let background = ui.painter().add(egui::Shape::Noop);
+        let (gutter, _) =
+            ui.allocate_exact_size(egui::vec2(gutter_width, galley.size().y), Sense::hover());
+        let response = ui.add(egui::Label::new(galley.clone()));
+        let right = response.rect.right().max(ui.max_rect().right());
+        let font_id = FontId::monospace(CODE_FONT_SIZE);
+        let number_color = ui.visuals().weak_text_color();
+        let mut line_rects: Vec<Rect> = Vec::new();
+        let mut line_start = true;
+        for row in &galley.rows {
+            let top = response.rect.top() + row.rect.top();
+            let bottom = response.rect.top() + row.rect.bottom();
+            if line_start {
+                line_rects.push(Rect::from_x_y_ranges(gutter.left()..=right, top..=bottom));
+            } else if let Some(rect) = line_rects.last_mut() {
+                rect.max.y = bottom;
+            }
+            line_start = row.ends_with_newline;
+        }
+        let mut tints = Vec::new();
+        for (number, rect) in line_rects.iter().enumerate().take(line_count) {
+            if let Some(Some(color)) = line_backgrounds.get(number) {
+                tints.push(egui::Shape::rect_filled(*rect, Rounding::ZERO, *color));
+            }
+            if line_numbers {
+                ui.painter().text(
+                    Pos2::new(gutter.right() - 8.0, rect.top()),
+                    egui::Align2::RIGHT_TOP,
+                    (number + 1).to_string(),
+                    font_id.clone(),
+                    number_color,
+                );
+            }
+        }
+        ui.painter().set(background, tints);
+    });
+}
+
+/// Draws a ```` ```mermaid ```` block as a diagram, with a toggle to show its
+/// source. Blocks that can't be parsed are shown as code with the error.
+fn render_mermaid(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui, salt: usize) {
+    let source_id = ui.make_persistent_id(("mermaid_source", salt));
+    let mut show_source: bool = ui.data_mut(|d| *d.get_persisted_mut_or_default(source_id));
+    let diagram = mermaid::parse(&state.code_block_content);
+    if let Err(error) = &diagram {
+        log::debug!("Failed to parse Mermaid diagram: {}", error);
+    }
+    ui.horizontal(|ui| {
+        match &diagram {
+            Ok(_) => ui.label(RichText::new("Mermaid diagram").weak()),
+            Err(error) => ui.label(
+                RichText::new(format!("Could not render diagram: {}", error))
+                    .color(state.visuals.warn_fg_color),
+            ),
+        };
+        if diagram.is_ok() {
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                let (icon, hint) = if show_source {
+                    ("🖼", "Show diagram")
                 } else {
-                    job.append(
-                        &code,
-                        0.0,
-                        TextFormat {
-                            font_id: FontId::monospace(CODE_FONT_SIZE),
-                            color: state.visuals.text_color(),
-                            ..Default::default()
-                        },
-                    );
+                    ("</>", "Show source")
+                };
+                if ui.small_button(icon).on_hover_text(hint).clicked() {
+                    show_source = !show_source;
                 }
-                ui.add(egui::Label::new(job).wrap(false));
             });
+        }
     });
+    ui.data_mut(|d| d.insert_persisted(source_id, show_source));
+    match diagram {
+        Ok(diagram) if !show_source => {
+            let visuals = state.visuals;
+            let theme = mermaid::DiagramTheme {
+                text: visuals.text_color(),
+                fill: visuals.faint_bg_color,
+                stroke: visuals.selection.bg_fill,
+                line: visuals.widgets.noninteractive.fg_stroke.color,
+                label_background: visuals.extreme_bg_color,
+                note_fill: visuals.code_bg_color,
+            };
+            let drawing = ui.fonts(|f| diagram.layout(f, &theme));
+            state.code_block_content.clear();
+            ScrollArea::horizontal()
+                .id_source(ui.next_auto_id())
+                .show(ui, |ui| {
+                    let desired =
+                        egui::vec2(ui.available_width().max(drawing.size.x), drawing.size.y);
+                    let (rect, _) = ui.allocate_exact_size(desired, Sense::hover());
+                    let origin = Pos2::new(rect.center().x - drawing.size.x / 2.0, rect.top());
+                    drawing.paint(ui.painter(), origin.max(rect.min));
+                });
+        }
+        _ => render_code_block(state, ui, salt),
+    }
 }
 
-fn render_table(state: &mut RenderState<'_, '_>) {
+fn render_table(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui) {
     let rows = std::mem::take(&mut state.table_rows);
+    render_table_rows(state, ui, rows);
+}
+
+fn render_table_rows(
+    state: &mut RenderState<'_, '_>,
+    ui: &mut egui::Ui,
+    rows: Vec<Vec<TableCell>>,
+) {
+    let visuals = state.visuals;
     if rows.is_empty() {
         return;
     }
@@ -888,21 +3596,22 @@ fn render_table(state: &mut RenderState<'_, '_>) {
     let frame = Frame::none()
         .stroke(Stroke::new(
             1.0,
-            state.visuals.widgets.noninteractive.bg_stroke.color,
+            visuals.widgets.noninteractive.bg_stroke.color,
         ))
         .inner_margin(Margin::same(4.0));
-    frame.show(state.ui, |ui| {
+    frame.show(ui, |ui| {
         egui::Grid::new(ui.next_auto_id())
             .num_columns(num_columns)
             .striped(true)
             .spacing([10.0, 4.0])
             .show(ui, |ui| {
-                for (row_idx, row_data) in rows.iter().enumerate() {
-                    for cell_job in row_data {
-                        ui.add(egui::Label::new(cell_job.clone()).wrap(true));
+                let row_count = rows.len();
+                for (row_idx, row_data) in rows.into_iter().enumerate() {
+                    for cell in row_data {
+                        render_table_cell(state, ui, cell);
                     }
                     ui.end_row();
-                    if row_idx == 0 && rows.len() > 1 {
+                    if row_idx == 0 && row_count > 1 {
                         ui.separator();
                     }
                 }
@@ -910,15 +3619,58 @@ fn render_table(state: &mut RenderState<'_, '_>) {
     });
 }
 
+fn render_table_cell(state: &mut RenderState<'_, '_>, ui: &mut egui::Ui, cell: TableCell) {
+    if cell.hotspots.is_empty() {
+        ui.add(egui::Label::new(cell.job).wrap(true));
+        return;
+    }
+    let mut job = cell.job;
+    job.wrap.max_width = ui.available_width();
+    let galley = ui.fonts(|f| f.layout_job(job));
+    let mut sense = Sense::click();
+    sense.focusable = false;
+    let response = ui.add(egui::Label::new(galley.clone()).sense(sense));
+    for (sections, hotspot) in cell.hotspots {
+        let rects = hotspot_rects(&galley, response.rect.left_top(), &sections);
+        handle_hotspot(state, ui, &response, &rects, hotspot);
+    }
+}
+
 #[allow(deprecated)] // Allow RetainedImage for now
-fn render_image(ui: &mut egui::Ui, url: &str, alt_text: &str) {
+fn render_image(
+    ui: &mut egui::Ui,
+    url: &str,
+    alt_text: &str,
+    size: ImageSize,
+    settings: &settings::ImageSettings,
+) -> Response {
+    if !settings.load {
+        let alt_text = if alt_text.is_empty() { url } else { alt_text };
+        return ui
+            .label(RichText::new(format!("🖼 {}", alt_text)).weak())
+            .on_hover_text(url);
+    }
     let mut cache = IMAGE_CACHE.lock().unwrap();
     let url_string = url.to_string();
     let retained_image = cache.entry(url_string.clone()).or_insert_with(|| {
         log::debug!("Loading image: {}", url);
+        // Local files (like `![[image.png]]` embeds) are decoded; anything else
+        // gets the placeholder.
+        let local = match fs::read(url) {
+            Ok(bytes) => match RetainedImage::from_image_bytes(url, &bytes) {
+                Ok(image) => Some(image),
+                Err(e) => {
+                    log::warn!("Failed to decode image {}: {}", url, e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
         // Ensure you have a `placeholder.png` in the `src` directory!
-        RetainedImage::from_image_bytes("placeholder", include_bytes!("placeholder.png"))
-            .expect("Failed to load placeholder image bytes") // Panic if placeholder fails
+        local.unwrap_or_else(|| {
+            RetainedImage::from_image_bytes("placeholder", include_bytes!("placeholder.png"))
+                .expect("Failed to load placeholder image bytes") // Panic if placeholder fails
+        })
     });
     // Use egui::Image now
     let img_widget = Image::new(egui::ImageSource::Texture(egui::load::SizedTexture::new(
@@ -926,15 +3678,51 @@ fn render_image(ui: &mut egui::Ui, url: &str, alt_text: &str) {
         retained_image.size_vec2(),
     )))
     .fit_to_original_size(1.0)
-    .max_width(ui.available_width() * 0.8);
+    .max_width(ui.available_width() * settings.max_width);
+    let img_widget = match (size.width, size.height) {
+        (None, None) => img_widget,
+        (width, height) => img_widget.fit_to_exact_size(egui::vec2(
+            width.unwrap_or(f32::INFINITY),
+            height.unwrap_or(f32::INFINITY),
+        )),
+    };
     ui.add_space(4.0);
     let response = ui.add(img_widget);
-    if !alt_text.is_empty() {
-        response.on_hover_text(format!("{} ({})", alt_text, url));
+    let response = if !alt_text.is_empty() {
+        response.on_hover_text(format!("{} ({})", alt_text, url))
     } else {
-        response.on_hover_text(url);
-    }
+        response.on_hover_text(url)
+    };
     ui.add_space(4.0);
+    response
+}
+
+/// Display size requested by an HTML `<img>`; unset sides follow the aspect ratio.
+#[derive(Clone, Copy, Debug, Default)]
+struct ImageSize {
+    width: Option<f32>,
+    height: Option<f32>,
+}
+
+impl ImageSize {
+    fn from_html(element: &html::Element, available_width: f32) -> Self {
+        let parse = |value: Option<&str>, relative_to: f32| {
+            let value = value?.trim();
+            match value.strip_suffix('%') {
+                Some(percent) => percent
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .map(|p| p / 100.0 * relative_to),
+                None => value.trim_end_matches("px").parse::<f32>().ok(),
+            }
+            .filter(|v| *v > 0.0)
+        };
+        Self {
+            width: parse(element.attr("width"), available_width).map(|w| w.min(available_width)),
+            height: parse(element.attr("height"), available_width),
+        }
+    }
 }
 
 fn syntect_style_to_text_format(style: SyntectStyle) -> TextFormat {
@@ -952,60 +3740,6 @@ fn syntect_style_to_text_format(style: SyntectStyle) -> TextFormat {
     }
 }
 
-fn heading_font_id(level: HeadingLevel) -> FontId {
-    let size = match level {
-        HeadingLevel::H1 => 30.0,
-        HeadingLevel::H2 => 24.0,
-        HeadingLevel::H3 => 20.0,
-        HeadingLevel::H4 => 18.0,
-        HeadingLevel::H5 => 16.0,
-        HeadingLevel::H6 => 14.0,
-    };
-    FontId::new(size, egui::FontFamily::Proportional)
-}
-
-fn heading_spacing(level: HeadingLevel, before: bool) -> f32 {
-    match level {
-        HeadingLevel::H1 => {
-            if before {
-                16.0
-            } else {
-                8.0
-            }
-        }
-        HeadingLevel::H2 => {
-            if before {
-                12.0
-            } else {
-                6.0
-            }
-        }
-        HeadingLevel::H3 => {
-            if before {
-                10.0
-            } else {
-                5.0
-            }
-        }
-        _ => {
-            if before {
-                8.0
-            } else {
-                4.0
-            }
-        }
-    }
-}
-
-fn get_parent_list_num(stack: &[BlockInfo]) -> Option<u64> {
-    for block in stack.iter().rev().skip(1) {
-        if let BlockInfo::List(num_opt) = block {
-            return *num_opt;
-        }
-    }
-    None
-}
-
 fn handle_dropped_files(ctx: &egui::Context, app_state: &mut MarkdownViewerApp) {
     let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
     if !dropped_files.is_empty() {
@@ -1013,10 +3747,14 @@ fn handle_dropped_files(ctx: &egui::Context, app_state: &mut MarkdownViewerApp)
     }
     for file in dropped_files {
         if let Some(ref path) = file.path {
-            if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
+            if path.is_dir() {
+                log::info!("Accepted dropped folder: {}", path.display());
+                app_state.open_folder(path.clone());
+                break;
+            } else if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
                 if ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown") {
                     log::info!("Accepted dropped file: {}", path.display());
-                    *app_state = MarkdownViewerApp::new_from_file(path.clone());
+                    app_state.open_file(path.clone());
                     break;
                 } else {
                     log::debug!(
@@ -1059,12 +3797,72 @@ fn register_default_viewer() -> Result<(), Box<dyn std::error::Error>> {
     Err("Default viewer registration is only supported on Windows.".into())
 }
 
+/// Writes `contents` to a temporary file next to `path`, then renames it over
+/// `path` so readers never observe a half-written file.
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .map_or_else(|| "document".into(), |n| n.to_string_lossy());
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })
+}
+
 fn current_time() -> f64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map_or(0.0, |d| d.as_secs_f64())
 }
 
+/// Opens a link with the system's default program.
+fn open_url(url: &str) {
+    if let Err(e) = open::that(url) {
+        log::error!("Failed to open link '{}': {}", url, e);
+    }
+}
+
+const USAGE: &str = "\
+Usage: markdown_viewer_improved [OPTIONS] [FILE_OR_FOLDER]
+
+Options:
+  --config FILE      Read settings from FILE instead of the config directory
+  --set KEY=VALUE    Override a setting for this run, e.g. --set images.load=false
+                     or --set appearance=Dark; nested keys are dotted
+  -h, --help         Show this help";
+
+/// The file or folder to open and the config options from the command line.
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<(Option<String>, settings::ConfigArgs), String> {
+    let mut path = None;
+    let mut config = settings::ConfigArgs::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let file = args.next().ok_or("--config needs a file")?;
+                config.path = Some(PathBuf::from(file));
+            }
+            "--set" => {
+                let setting = args.next().ok_or("--set needs KEY=VALUE")?;
+                let (key, value) = setting
+                    .split_once('=')
+                    .ok_or_else(|| format!("Expected KEY=VALUE, got '{}'", setting))?;
+                config
+                    .overrides
+                    .push((key.trim().to_string(), value.trim().to_string()));
+            }
+            option if option.starts_with("--") => {
+                return Err(format!("Unknown option '{}'", option));
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg)),
+        }
+    }
+    Ok((path, config))
+}
+
 const DEFAULT_MARKDOWN: &str = r#"
 # Welcome to Markdown Viewer! 👋
 This viewer renders Markdown files using `egui` and `pulldown-cmark`.
@@ -1116,19 +3914,40 @@ fn main() -> Result<(), eframe::Error> {
 
     hide_console();
 
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let (path_arg, config_args) = match parse_args(args.into_iter()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
 
     let options = NativeOptions {
         viewport: ViewportBuilder::default()
             .with_inner_size([900.0, 700.0])
             .with_drag_and_drop(true),
         persist_window: true,
+        follow_system_theme: true,
         ..Default::default()
     };
 
-    let initial_app = if args.len() > 1 {
-        let file_path = PathBuf::from(&args[1]);
-        if file_path.exists()
+    // The last session's folder and document are only reopened when nothing
+    // was asked for. The argument is opened once the session is loaded, so
+    // that where it was last read is known.
+    let reopen_session = path_arg.is_none();
+    let mut open_arg = None;
+    let initial_app = if let Some(path_arg) = path_arg {
+        let file_path = PathBuf::from(&path_arg);
+        if file_path.is_dir() {
+            log::info!("Opening folder from argument: {}", file_path.display());
+            open_arg = Some(file_path);
+            MarkdownViewerApp::new_default()
+        } else if file_path.exists()
             && (file_path
                 .extension()
                 .map_or(false, |e| e == "md" || e == "markdown"))
@@ -1137,48 +3956,100 @@ fn main() -> Result<(), eframe::Error> {
                 "Loading initial file from argument: {}",
                 file_path.display()
             );
-            MarkdownViewerApp::new_from_file(file_path)
+            open_arg = Some(file_path);
+            MarkdownViewerApp::new_default()
         } else {
             log::warn!(
                 "Invalid file path or extension provided via argument: {}",
-                args[1]
+                path_arg
             );
-            MarkdownViewerApp::error(format!("Invalid file path provided: {}", args[1]))
+            MarkdownViewerApp::error(format!("Invalid file path provided: {}", path_arg))
         }
     } else {
         MarkdownViewerApp::new_default()
     };
 
-    let app_loaded = |cc: &eframe::CreationContext<'_>| -> Box<dyn App> {
+    let app_loaded = move |cc: &eframe::CreationContext<'_>| -> Box<dyn App> {
         let mut app = initial_app;
 
+        let (config, settings, notice) = settings::Config::load(&config_args, cc.storage);
+        app.config = config;
+        app.settings = settings;
+        if let Some(notice) = notice {
+            app.status_message = Some((notice, current_time() + 10.0));
+        }
+
         if let Some(storage) = cc.storage {
-            if let Some(dark_mode) = eframe::get_value::<bool>(storage, "dark_mode") {
-                app.dark_mode = dark_mode;
-                log::info!("Loaded dark_mode state: {}", dark_mode);
+            if let Some(show_backlinks) = eframe::get_value::<bool>(storage, "show_backlinks") {
+                app.show_backlinks = show_backlinks;
+            }
+            if let Some(recent_files) = eframe::get_value::<Vec<PathBuf>>(storage, "recent_files") {
+                app.recent_files = recent_files;
             }
+            if let Some(session) = eframe::get_value::<session::Session>(storage, "session") {
+                app.restore_session(session, reopen_session);
+            }
+        }
+        match open_arg {
+            Some(path) if path.is_dir() => app.open_folder(path),
+            Some(path) => app.open_file(path),
+            None => {}
         }
+        app.remember_recent_file();
+        fonts::apply_fonts(&cc.egui_ctx, &app.settings.fonts);
+        // Zoom is handled by the app so that it can be persisted.
+        cc.egui_ctx.options_mut(|o| o.zoom_with_keyboard = false);
+        cc.egui_ctx.set_zoom_factor(app.settings.reading.zoom);
+        highlighting::load_in_background(cc.egui_ctx.clone());
         Box::new(app)
     };
 
-    eframe::run_native("Markdown Viewer", options, Box::new(app_loaded))
+    eframe::run_native(APP_NAME, options, Box::new(app_loaded))
 }
 
-trait LayoutJobExt {
-    fn append_job(&mut self, other: LayoutJob);
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl LayoutJobExt for LayoutJob {
-    fn append_job(&mut self, other: LayoutJob) {
-        if other.is_empty() {
-            return;
-        }
-        let start_offset = self.text.len();
-        self.text.push_str(&other.text);
-        for mut section in other.sections {
-            section.byte_range.start += start_offset;
-            section.byte_range.end += start_offset;
-            self.sections.push(section);
-        }
+    fn parse(args: &[&str]) -> Result<(Option<String>, settings::ConfigArgs), String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn set_options() {
+        let (path, config) = parse(&[
+            "--set",
+            "images.load = false",
+            "notes.md",
+            "--set",
+            "theme=a=b",
+            "--config",
+            "my.toml",
+        ])
+        .unwrap();
+        assert_eq!(path.as_deref(), Some("notes.md"));
+        assert_eq!(config.path, Some(PathBuf::from("my.toml")));
+        assert_eq!(
+            config.overrides,
+            [
+                ("images.load".to_string(), "false".to_string()),
+                ("theme".to_string(), "a=b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn argument_errors() {
+        assert_eq!(parse(&["--set"]).unwrap_err(), "--set needs KEY=VALUE");
+        assert_eq!(
+            parse(&["--set", "images.load"]).unwrap_err(),
+            "Expected KEY=VALUE, got 'images.load'"
+        );
+        assert_eq!(parse(&["--config"]).unwrap_err(), "--config needs a file");
+        assert_eq!(parse(&["--sett"]).unwrap_err(), "Unknown option '--sett'");
+        assert_eq!(
+            parse(&["a.md", "b.md"]).unwrap_err(),
+            "Unexpected argument 'b.md'"
+        );
     }
 }
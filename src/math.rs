@@ -0,0 +1,1662 @@
+//! A small TeX math engine: parses a formula into a tree and lays it out as
+//! text runs, rules and strokes that are painted with egui.
+//!
+//! It covers what shows up in notes: fractions, sub/superscripts, roots,
+//! matrices, `\left`/`\right` delimiters, accents, Greek letters and the common
+//! operators. Anything else is reported as an error so the caller can fall back
+//! to showing the source.
+
+use eframe::egui::{
+    epaint::{Fonts, QuadraticBezierShape},
+    pos2,
+    text::LayoutJob,
+    vec2, Color32, FontFamily, FontId, Galley, Painter, Pos2, Rect, Shape, Stroke, TextFormat,
+    Vec2,
+};
+use std::sync::Arc;
+
+// --- Parsing ---
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Ord,
+    /// Large operators and function names like `\sum` or `\lim`.
+    Op,
+    Bin,
+    Rel,
+    Open,
+    Close,
+    Punct,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Variant {
+    Italic,
+    Upright,
+    /// `\text{...}`: upright, and spaces are kept.
+    Text,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Atom {
+        text: String,
+        kind: Kind,
+        variant: Variant,
+        /// Scripts go above and below in display style (`\sum`, `\lim`).
+        limits: bool,
+        /// Drawn larger in display style (`\sum`, `\int`).
+        large: bool,
+    },
+    Group(Vec<Node>),
+    Frac {
+        num: Box<Node>,
+        den: Box<Node>,
+        bar: bool,
+    },
+    Sqrt {
+        index: Option<Box<Node>>,
+        body: Box<Node>,
+    },
+    Scripts {
+        base: Box<Node>,
+        sub: Option<Box<Node>>,
+        sup: Option<Box<Node>>,
+    },
+    Delimited {
+        left: char,
+        body: Box<Node>,
+        right: char,
+    },
+    Matrix {
+        rows: Vec<Vec<Node>>,
+        left: char,
+        right: char,
+        align: MatrixAlign,
+    },
+    Accent {
+        accent: Accent,
+        body: Box<Node>,
+    },
+    /// Horizontal space in em.
+    Space(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MatrixAlign {
+    Center,
+    Left,
+    /// `aligned`: columns alternate right and left, as around `&=`.
+    RightLeft,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Accent {
+    Hat,
+    Bar,
+    Vec,
+    Tilde,
+    Dot,
+    DDot,
+    Underline,
+}
+
+/// Where a list of nodes stopped.
+#[derive(Clone, Debug, PartialEq)]
+enum Stop {
+    End,
+    CloseBrace,
+    /// `\right` and its delimiter.
+    Right(char),
+    /// `&` between matrix cells.
+    Cell,
+    /// `\\` between matrix rows.
+    Row,
+    /// `\end{name}`.
+    EndEnv(String),
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+/// Symbols that map to a single character.
+fn symbol(name: &str) -> Option<(&'static str, Kind)> {
+    use Kind::*;
+    let symbol = match name {
+        // Greek, lowercase
+        "alpha" => ("α", Ord),
+        "beta" => ("β", Ord),
+        "gamma" => ("γ", Ord),
+        "delta" => ("δ", Ord),
+        "epsilon" => ("ϵ", Ord),
+        "varepsilon" => ("ε", Ord),
+        "zeta" => ("ζ", Ord),
+        "eta" => ("η", Ord),
+        "theta" => ("θ", Ord),
+        "vartheta" => ("ϑ", Ord),
+        "iota" => ("ι", Ord),
+        "kappa" => ("κ", Ord),
+        "lambda" => ("λ", Ord),
+        "mu" => ("μ", Ord),
+        "nu" => ("ν", Ord),
+        "xi" => ("ξ", Ord),
+        "omicron" => ("ο", Ord),
+        "pi" => ("π", Ord),
+        "varpi" => ("ϖ", Ord),
+        "rho" => ("ρ", Ord),
+        "varrho" => ("ϱ", Ord),
+        "sigma" => ("σ", Ord),
+        "varsigma" => ("ς", Ord),
+        "tau" => ("τ", Ord),
+        "upsilon" => ("υ", Ord),
+        "phi" => ("ϕ", Ord),
+        "varphi" => ("φ", Ord),
+        "chi" => ("χ", Ord),
+        "psi" => ("ψ", Ord),
+        "omega" => ("ω", Ord),
+        // Greek, uppercase
+        "Gamma" => ("Γ", Ord),
+        "Delta" => ("Δ", Ord),
+        "Theta" => ("Θ", Ord),
+        "Lambda" => ("Λ", Ord),
+        "Xi" => ("Ξ", Ord),
+        "Pi" => ("Π", Ord),
+        "Sigma" => ("Σ", Ord),
+        "Upsilon" => ("Υ", Ord),
+        "Phi" => ("Φ", Ord),
+        "Psi" => ("Ψ", Ord),
+        "Omega" => ("Ω", Ord),
+        // Binary operators
+        "pm" => ("±", Bin),
+        "mp" => ("∓", Bin),
+        "times" => ("×", Bin),
+        "div" => ("÷", Bin),
+        "cdot" => ("⋅", Bin),
+        "ast" => ("∗", Bin),
+        "star" => ("⋆", Bin),
+        "circ" => ("∘", Bin),
+        "bullet" => ("∙", Bin),
+        "cap" => ("∩", Bin),
+        "cup" => ("∪", Bin),
+        "wedge" | "land" => ("∧", Bin),
+        "vee" | "lor" => ("∨", Bin),
+        "oplus" => ("⊕", Bin),
+        "ominus" => ("⊖", Bin),
+        "otimes" => ("⊗", Bin),
+        "setminus" => ("∖", Bin),
+        // Relations
+        "leq" | "le" => ("≤", Rel),
+        "geq" | "ge" => ("≥", Rel),
+        "neq" | "ne" => ("≠", Rel),
+        "approx" => ("≈", Rel),
+        "equiv" => ("≡", Rel),
+        "sim" => ("∼", Rel),
+        "simeq" => ("≃", Rel),
+        "cong" => ("≅", Rel),
+        "propto" => ("∝", Rel),
+        "ll" => ("≪", Rel),
+        "gg" => ("≫", Rel),
+        "in" => ("∈", Rel),
+        "notin" => ("∉", Rel),
+        "ni" => ("∋", Rel),
+        "subset" => ("⊂", Rel),
+        "subseteq" => ("⊆", Rel),
+        "supset" => ("⊃", Rel),
+        "supseteq" => ("⊇", Rel),
+        "perp" => ("⊥", Rel),
+        "parallel" => ("∥", Rel),
+        "mid" => ("∣", Rel),
+        "to" | "rightarrow" => ("→", Rel),
+        "leftarrow" | "gets" => ("←", Rel),
+        "leftrightarrow" => ("↔", Rel),
+        "Rightarrow" | "implies" => ("⇒", Rel),
+        "Leftarrow" => ("⇐", Rel),
+        "Leftrightarrow" | "iff" => ("⇔", Rel),
+        "mapsto" => ("↦", Rel),
+        "uparrow" => ("↑", Rel),
+        "downarrow" => ("↓", Rel),
+        "models" => ("⊨", Rel),
+        "vdash" => ("⊢", Rel),
+        // Ordinary symbols
+        "infty" => ("∞", Ord),
+        "partial" => ("∂", Ord),
+        "nabla" => ("∇", Ord),
+        "forall" => ("∀", Ord),
+        "exists" => ("∃", Ord),
+        "nexists" => ("∄", Ord),
+        "emptyset" | "varnothing" => ("∅", Ord),
+        "neg" | "lnot" => ("¬", Ord),
+        "top" => ("⊤", Ord),
+        "bot" => ("⊥", Ord),
+        "angle" => ("∠", Ord),
+        "triangle" => ("△", Ord),
+        "hbar" => ("ℏ", Ord),
+        "ell" => ("ℓ", Ord),
+        "Re" => ("ℜ", Ord),
+        "Im" => ("ℑ", Ord),
+        "aleph" => ("ℵ", Ord),
+        "prime" => ("′", Ord),
+        "degree" => ("°", Ord),
+        "ldots" | "dots" => ("…", Ord),
+        "cdots" => ("⋯", Ord),
+        "vdots" => ("⋮", Ord),
+        "ddots" => ("⋱", Ord),
+        "colon" => (":", Punct),
+        // Delimiters
+        "langle" => ("⟨", Open),
+        "rangle" => ("⟩", Close),
+        "lfloor" => ("⌊", Open),
+        "rfloor" => ("⌋", Close),
+        "lceil" => ("⌈", Open),
+        "rceil" => ("⌉", Close),
+        "lvert" => ("|", Open),
+        "rvert" => ("|", Close),
+        "lVert" => ("‖", Open),
+        "rVert" => ("‖", Close),
+        "vert" => ("|", Ord),
+        "Vert" | "|" => ("‖", Ord),
+        "{" | "lbrace" => ("{", Open),
+        "}" | "rbrace" => ("}", Close),
+        // Escapes
+        "$" => ("$", Ord),
+        "%" => ("%", Ord),
+        "&" => ("&", Ord),
+        "#" => ("#", Ord),
+        "_" => ("_", Ord),
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+/// Large operators: `(symbol, limits)`.
+fn large_operator(name: &str) -> Option<(&'static str, bool)> {
+    let op = match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "bigoplus" => ("⨁", true),
+        "bigotimes" => ("⨂", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Function names set upright: `(limits)`.
+fn function_name(name: &str) -> Option<bool> {
+    match name {
+        "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr"
+        | "argmax" | "argmin" => Some(true),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "deg" | "dim" | "ker"
+        | "hom" | "arg" | "mod" | "bmod" => Some(false),
+        _ => None,
+    }
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'C' => 'ℂ',
+        'H' => 'ℍ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        'A'..='Z' => char::from_u32(0x1D538 + (c as u32 - 'A' as u32)).unwrap_or(c),
+        'a'..='z' => char::from_u32(0x1D552 + (c as u32 - 'a' as u32)).unwrap_or(c),
+        '0'..='9' => char::from_u32(0x1D7D8 + (c as u32 - '0' as u32)).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn atom(text: impl Into<String>, kind: Kind, variant: Variant) -> Node {
+    Node::Atom {
+        text: text.into(),
+        kind,
+        variant,
+        limits: false,
+        large: false,
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    /// Reads a command name after the backslash.
+    fn command_name(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.bump();
+        }
+        if self.pos == start {
+            // A single non-letter, e.g. `\,` or `\{`.
+            return self
+                .bump()
+                .map(String::from)
+                .ok_or_else(|| "Formula ends with '\\'".to_string());
+        }
+        Ok(self.src[start..self.pos].to_string())
+    }
+
+    /// Parses nodes up to the end of input or a terminator.
+    fn parse_list(&mut self) -> Result<(Vec<Node>, Stop), String> {
+        let mut nodes: Vec<Node> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.bump() else {
+                return Ok((nodes, Stop::End));
+            };
+            let node = match c {
+                '}' => return Ok((nodes, Stop::CloseBrace)),
+                '&' => return Ok((nodes, Stop::Cell)),
+                '{' => {
+                    let (group, stop) = self.parse_list()?;
+                    if stop != Stop::CloseBrace {
+                        return Err("Missing '}'".to_string());
+                    }
+                    Node::Group(group)
+                }
+                '^' | '_' => {
+                    let script = self.parse_argument()?;
+                    let base = nodes.pop().unwrap_or(Node::Group(Vec::new()));
+                    nodes.push(attach_script(base, c == '^', script)?);
+                    continue;
+                }
+                '\'' => {
+                    let base = nodes.pop().unwrap_or(Node::Group(Vec::new()));
+                    nodes.push(attach_script(
+                        base,
+                        true,
+                        atom("′", Kind::Ord, Variant::Upright),
+                    )?);
+                    continue;
+                }
+                '\\' => {
+                    let name = self.command_name()?;
+                    match name.as_str() {
+                        "\\" | "cr" => return Ok((nodes, Stop::Row)),
+                        "right" => {
+                            let delim = self.delimiter()?;
+                            return Ok((nodes, Stop::Right(delim)));
+                        }
+                        "end" => {
+                            let env = self.environment_name()?;
+                            return Ok((nodes, Stop::EndEnv(env)));
+                        }
+                        _ => self.command(&name)?,
+                    }
+                }
+                '~' => Node::Space(0.28),
+                '0'..='9' | '.' => {
+                    let mut number = String::from(c);
+                    while let Some(d) = self.peek().filter(|d| d.is_ascii_digit() || *d == '.') {
+                        number.push(d);
+                        self.bump();
+                    }
+                    atom(number, Kind::Ord, Variant::Upright)
+                }
+                '+' => atom("+", Kind::Bin, Variant::Upright),
+                '-' => atom("−", Kind::Bin, Variant::Upright),
+                '*' => atom("∗", Kind::Bin, Variant::Upright),
+                '=' | '<' | '>' | ':' => atom(c, Kind::Rel, Variant::Upright),
+                '(' | '[' => atom(c, Kind::Open, Variant::Upright),
+                ')' | ']' | '!' | '?' => atom(c, Kind::Close, Variant::Upright),
+                ',' | ';' => atom(c, Kind::Punct, Variant::Upright),
+                '#' | '$' | '%' => return Err(format!("Unexpected '{}'", c)),
+                c if c.is_alphabetic() => atom(c, Kind::Ord, Variant::Italic),
+                c => atom(c, Kind::Ord, Variant::Upright),
+            };
+            nodes.push(node);
+        }
+    }
+
+    /// A `{...}` group or a single token.
+    fn parse_argument(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.bump();
+                let (nodes, stop) = self.parse_list()?;
+                if stop != Stop::CloseBrace {
+                    return Err("Missing '}'".to_string());
+                }
+                Ok(Node::Group(nodes))
+            }
+            Some('\\') => {
+                self.bump();
+                let name = self.command_name()?;
+                self.command(&name)
+            }
+            Some(c) => {
+                self.bump();
+                Ok(match c {
+                    '0'..='9' => atom(c, Kind::Ord, Variant::Upright),
+                    c if c.is_alphabetic() => atom(c, Kind::Ord, Variant::Italic),
+                    c => atom(c, Kind::Ord, Variant::Upright),
+                })
+            }
+            None => Err("Missing argument".to_string()),
+        }
+    }
+
+    /// The raw text of a `{...}` argument, for `\text` and friends.
+    fn raw_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.bump() != Some('{') {
+            return Err("Expected '{'".to_string());
+        }
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.bump() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(self.src[start..self.pos - 1].to_string()),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        Err("Missing '}'".to_string())
+    }
+
+    fn optional_argument(&mut self) -> Result<Option<Node>, String> {
+        self.skip_whitespace();
+        if self.peek() != Some('[') {
+            return Ok(None);
+        }
+        self.bump();
+        let start = self.pos;
+        let end = self.src[start..]
+            .find(']')
+            .ok_or_else(|| "Missing ']'".to_string())?;
+        self.pos = start + end + 1;
+        let mut inner = Parser {
+            src: &self.src[start..start + end],
+            pos: 0,
+        };
+        let (nodes, _) = inner.parse_list()?;
+        Ok(Some(Node::Group(nodes)))
+    }
+
+    fn environment_name(&mut self) -> Result<String, String> {
+        Ok(self.raw_argument()?.trim().to_string())
+    }
+
+    /// The delimiter after `\left`, `\right` or `\big`; `.` means none.
+    fn delimiter(&mut self) -> Result<char, String> {
+        self.skip_whitespace();
+        match self.bump() {
+            Some('\\') => {
+                let name = self.command_name()?;
+                match symbol(&name) {
+                    Some((text, Kind::Open | Kind::Close | Kind::Ord)) => {
+                        Ok(text.chars().next().unwrap_or('.'))
+                    }
+                    _ => Err(format!("Unsupported delimiter \\{}", name)),
+                }
+            }
+            Some(c @ ('(' | ')' | '[' | ']' | '|' | '.' | '/' | '<' | '>')) => Ok(match c {
+                '<' => '⟨',
+                '>' => '⟩',
+                c => c,
+            }),
+            Some(c) => Err(format!("Unsupported delimiter '{}'", c)),
+            None => Err("Missing delimiter".to_string()),
+        }
+    }
+
+    fn command(&mut self, name: &str) -> Result<Node, String> {
+        if let Some((text, kind)) = symbol(name) {
+            let variant = if name.chars().next().is_some_and(char::is_lowercase)
+                && kind == Kind::Ord
+                && text.chars().all(|c| ('α'..='ω').contains(&c))
+            {
+                Variant::Italic
+            } else {
+                Variant::Upright
+            };
+            return Ok(atom(text, kind, variant));
+        }
+        if let Some((text, limits)) = large_operator(name) {
+            return Ok(Node::Atom {
+                text: text.to_string(),
+                kind: Kind::Op,
+                variant: Variant::Upright,
+                limits,
+                large: true,
+            });
+        }
+        if let Some(limits) = function_name(name) {
+            return Ok(Node::Atom {
+                text: name.to_string(),
+                kind: Kind::Op,
+                variant: Variant::Upright,
+                limits,
+                large: false,
+            });
+        }
+        let node = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => Node::Frac {
+                num: Box::new(self.parse_argument()?),
+                den: Box::new(self.parse_argument()?),
+                bar: true,
+            },
+            "binom" => Node::Delimited {
+                left: '(',
+                body: Box::new(Node::Frac {
+                    num: Box::new(self.parse_argument()?),
+                    den: Box::new(self.parse_argument()?),
+                    bar: false,
+                }),
+                right: ')',
+            },
+            "sqrt" => {
+                let index = self.optional_argument()?.map(Box::new);
+                Node::Sqrt {
+                    index,
+                    body: Box::new(self.parse_argument()?),
+                }
+            }
+            "left" => {
+                let left = self.delimiter()?;
+                let (body, stop) = self.parse_list()?;
+                let Stop::Right(right) = stop else {
+                    return Err("\\left without matching \\right".to_string());
+                };
+                Node::Delimited {
+                    left,
+                    body: Box::new(Node::Group(body)),
+                    right,
+                }
+            }
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" => {
+                let delim = self.delimiter()?;
+                atom(delim, Kind::Ord, Variant::Upright)
+            }
+            "begin" => self.environment()?,
+            "text" | "textrm" | "mbox" | "textnormal" => {
+                atom(self.raw_argument()?, Kind::Ord, Variant::Text)
+            }
+            "mathrm" | "mathbf" | "textbf" | "boldsymbol" | "mathsf" | "mathtt" => {
+                upright(self.parse_argument()?)
+            }
+            "mathit" | "textit" => self.parse_argument()?,
+            "operatorname" => Node::Atom {
+                text: self.raw_argument()?.trim().to_string(),
+                kind: Kind::Op,
+                variant: Variant::Upright,
+                limits: false,
+                large: false,
+            },
+            "mathbb" => {
+                let text: String = self
+                    .raw_argument()?
+                    .trim()
+                    .chars()
+                    .map(double_struck)
+                    .collect();
+                atom(text, Kind::Ord, Variant::Upright)
+            }
+            "hat" | "widehat" => self.accent(Accent::Hat)?,
+            "bar" | "overline" => self.accent(Accent::Bar)?,
+            "vec" | "overrightarrow" => self.accent(Accent::Vec)?,
+            "tilde" | "widetilde" => self.accent(Accent::Tilde)?,
+            "dot" => self.accent(Accent::Dot)?,
+            "ddot" => self.accent(Accent::DDot)?,
+            "underline" => self.accent(Accent::Underline)?,
+            "," | "thinspace" => Node::Space(0.17),
+            ":" | ">" | "medspace" => Node::Space(0.22),
+            ";" | "thickspace" => Node::Space(0.28),
+            "!" => Node::Space(-0.17),
+            " " => Node::Space(0.25),
+            "quad" => Node::Space(1.0),
+            "qquad" => Node::Space(2.0),
+            "displaystyle" | "textstyle" | "limits" | "nolimits" => Node::Group(Vec::new()),
+            _ => return Err(format!("Unsupported command \\{}", name)),
+        };
+        Ok(node)
+    }
+
+    fn accent(&mut self, accent: Accent) -> Result<Node, String> {
+        Ok(Node::Accent {
+            accent,
+            body: Box::new(self.parse_argument()?),
+        })
+    }
+
+    fn environment(&mut self) -> Result<Node, String> {
+        let name = self.environment_name()?;
+        let (left, right, align) = match name.as_str() {
+            "matrix" | "smallmatrix" => ('.', '.', MatrixAlign::Center),
+            "pmatrix" => ('(', ')', MatrixAlign::Center),
+            "bmatrix" => ('[', ']', MatrixAlign::Center),
+            "Bmatrix" => ('{', '}', MatrixAlign::Center),
+            "vmatrix" => ('|', '|', MatrixAlign::Center),
+            "Vmatrix" => ('‖', '‖', MatrixAlign::Center),
+            "cases" => ('{', '.', MatrixAlign::Left),
+            "array" => {
+                // Column specs aren't supported; cells are centered.
+                self.raw_argument()?;
+                ('.', '.', MatrixAlign::Center)
+            }
+            "aligned" | "align" | "align*" | "split" | "gathered" => {
+                ('.', '.', MatrixAlign::RightLeft)
+            }
+            _ => return Err(format!("Unsupported environment '{}'", name)),
+        };
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        loop {
+            let (mut cell, stop) = self.parse_list()?;
+            if align == MatrixAlign::RightLeft && row.len() % 2 == 1 {
+                // Like `{}` in TeX, so `&=` keeps the space before the `=`.
+                cell.insert(0, Node::Group(Vec::new()));
+            }
+            row.push(Node::Group(cell));
+            match stop {
+                Stop::Cell => {}
+                Stop::Row => rows.push(std::mem::take(&mut row)),
+                Stop::EndEnv(end) if end == name => {
+                    // A trailing `\\` leaves an empty last row.
+                    let empty = row.len() == 1 && matches!(&row[0], Node::Group(g) if g.is_empty());
+                    if !empty {
+                        rows.push(row);
+                    }
+                    break;
+                }
+                Stop::EndEnv(end) => {
+                    return Err(format!("\\begin{{{}}} ended by \\end{{{}}}", name, end))
+                }
+                _ => return Err(format!("Missing \\end{{{}}}", name)),
+            }
+        }
+        Ok(Node::Matrix {
+            rows,
+            left,
+            right,
+            align,
+        })
+    }
+}
+
+fn upright(node: Node) -> Node {
+    match node {
+        Node::Atom {
+            text,
+            kind,
+            variant: Variant::Italic,
+            limits,
+            large,
+        } => Node::Atom {
+            text,
+            kind,
+            variant: Variant::Upright,
+            limits,
+            large,
+        },
+        Node::Group(nodes) => Node::Group(nodes.into_iter().map(upright).collect()),
+        other => other,
+    }
+}
+
+fn attach_script(base: Node, is_sup: bool, script: Node) -> Result<Node, String> {
+    let (base, mut sub, mut sup) = match base {
+        Node::Scripts { base, sub, sup } => (base, sub, sup),
+        base => (Box::new(base), None, None),
+    };
+    let slot = if is_sup { &mut sup } else { &mut sub };
+    if slot.is_some() {
+        return Err("Double subscript or superscript".to_string());
+    }
+    *slot = Some(Box::new(script));
+    Ok(Node::Scripts { base, sub, sup })
+}
+
+fn parse(source: &str) -> Result<Node, String> {
+    let mut parser = Parser {
+        src: source,
+        pos: 0,
+    };
+    let (nodes, stop) = parser.parse_list()?;
+    match stop {
+        Stop::End => Ok(Node::Group(nodes)),
+        Stop::CloseBrace => Err("Unmatched '}'".to_string()),
+        Stop::Right(_) => Err("\\right without matching \\left".to_string()),
+        Stop::Cell | Stop::Row => Err("'&' or '\\\\' outside of a matrix".to_string()),
+        Stop::EndEnv(name) => Err(format!("\\end{{{}}} without \\begin", name)),
+    }
+}
+
+// --- Layout ---
+
+#[derive(Clone)]
+enum Item {
+    /// Text with its top-left corner at the offset.
+    Text(Vec2, Arc<Galley>),
+    Rule(Rect),
+    /// An open polyline, stroked with the given width.
+    Line(Vec<Vec2>, f32),
+    Curve([Vec2; 3], f32),
+    Dot(Vec2, f32),
+}
+
+/// A laid out formula. Offsets are relative to the left end of the baseline,
+/// with y pointing down.
+#[derive(Clone, Default)]
+pub struct MathBox {
+    pub width: f32,
+    /// Height above the baseline.
+    pub ascent: f32,
+    /// Depth below the baseline.
+    pub descent: f32,
+    items: Vec<Item>,
+}
+
+impl MathBox {
+    pub fn height(&self) -> f32 {
+        self.ascent + self.descent
+    }
+
+    fn append(&mut self, other: MathBox, offset: Vec2) {
+        self.items
+            .extend(other.items.into_iter().map(|item| match item {
+                Item::Text(pos, galley) => Item::Text(pos + offset, galley),
+                Item::Rule(rect) => Item::Rule(rect.translate(offset)),
+                Item::Line(points, width) => {
+                    Item::Line(points.into_iter().map(|p| p + offset).collect(), width)
+                }
+                Item::Curve(points, width) => Item::Curve(points.map(|p| p + offset), width),
+                Item::Dot(center, radius) => Item::Dot(center + offset, radius),
+            }));
+    }
+
+    /// Paints the formula with the left end of its baseline at `origin`.
+    pub fn paint(&self, painter: &Painter, origin: Pos2, color: Color32) {
+        let at = |v: Vec2| origin + v;
+        for item in &self.items {
+            match item {
+                Item::Text(pos, galley) => painter.galley(at(*pos), galley.clone(), color),
+                Item::Rule(rect) => {
+                    painter.rect_filled(rect.translate(origin.to_vec2()), 0.0, color);
+                }
+                Item::Line(points, width) => {
+                    let points = points.iter().map(|p| at(*p)).collect();
+                    painter.add(Shape::line(points, Stroke::new(*width, color)));
+                }
+                Item::Curve(points, width) => {
+                    painter.add(QuadraticBezierShape::from_points_stroke(
+                        points.map(at),
+                        false,
+                        Color32::TRANSPARENT,
+                        Stroke::new(*width, color),
+                    ));
+                }
+                Item::Dot(center, radius) => {
+                    painter.circle_filled(at(*center), *radius, color);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Style {
+    size: f32,
+    display: bool,
+}
+
+impl Style {
+    fn script(self) -> Self {
+        Style {
+            size: (self.size * 0.7).max(7.0),
+            display: false,
+        }
+    }
+
+    /// Style of a fraction's numerator and denominator.
+    fn fraction(self) -> Self {
+        if self.display {
+            Style {
+                size: self.size,
+                display: false,
+            }
+        } else {
+            self.script()
+        }
+    }
+
+    /// Height of the math axis (where fraction bars sit) above the baseline.
+    fn axis(self) -> f32 {
+        self.size * 0.3
+    }
+
+    fn rule(self) -> f32 {
+        (self.size * 0.06).max(1.0)
+    }
+}
+
+struct Layouter<'f> {
+    fonts: &'f Fonts,
+}
+
+impl Layouter<'_> {
+    fn text(&self, text: &str, size: f32, variant: Variant) -> MathBox {
+        let job = LayoutJob::single_section(
+            text.to_string(),
+            TextFormat {
+                font_id: FontId::new(size, FontFamily::Proportional),
+                color: Color32::PLACEHOLDER,
+                italics: variant == Variant::Italic,
+                ..Default::default()
+            },
+        );
+        let galley = self.fonts.layout_job(job);
+        let baseline = galley
+            .rows
+            .first()
+            .and_then(|row| row.glyphs.first())
+            .map_or(size * 0.8, |glyph| glyph.pos.y);
+        let mut width = galley.size().x;
+        if variant == Variant::Italic {
+            // Room for the slant.
+            width += size * 0.06;
+        }
+        MathBox {
+            width,
+            ascent: baseline,
+            descent: galley.size().y - baseline,
+            items: vec![Item::Text(vec2(0.0, -baseline), galley)],
+        }
+    }
+
+    fn node(&self, node: &Node, style: Style) -> MathBox {
+        match node {
+            Node::Atom {
+                text,
+                variant,
+                large,
+                ..
+            } => {
+                if *large && style.display {
+                    let mut b = self.text(text, style.size * 1.5, *variant);
+                    // Center the enlarged symbol on the axis.
+                    let shift = (b.ascent - b.descent) / 2.0 - style.axis();
+                    let mut shifted = MathBox {
+                        width: b.width,
+                        ascent: b.ascent - shift,
+                        descent: b.descent + shift,
+                        items: Vec::new(),
+                    };
+                    shifted.append(std::mem::take(&mut b), vec2(0.0, shift));
+                    shifted
+                } else {
+                    self.text(text, style.size, *variant)
+                }
+            }
+            Node::Group(nodes) => self.list(nodes, style),
+            Node::Frac { num, den, bar } => self.fraction(num, den, *bar, style),
+            Node::Sqrt { index, body } => self.sqrt(index.as_deref(), body, style),
+            Node::Scripts { base, sub, sup } => {
+                self.scripts(base, sub.as_deref(), sup.as_deref(), style)
+            }
+            Node::Delimited { left, body, right } => {
+                let body = self.node(body, style);
+                self.delimited(*left, body, *right, style)
+            }
+            Node::Matrix {
+                rows,
+                left,
+                right,
+                align,
+            } => {
+                let body = self.matrix(rows, *align, style);
+                self.delimited(*left, body, *right, style)
+            }
+            Node::Accent { accent, body } => self.accent(*accent, body, style),
+            Node::Space(em) => MathBox {
+                width: em * style.size,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Lays out nodes side by side, with TeX-like spacing between atoms.
+    fn list(&self, nodes: &[Node], style: Style) -> MathBox {
+        let mut result = MathBox::default();
+        let mut previous: Option<Kind> = None;
+        for (index, node) in nodes.iter().enumerate() {
+            let mut kind = node_kind(node);
+            if kind == Kind::Bin {
+                // A binary operator without a left operand is unary, like `-x`.
+                let next = nodes.get(index + 1).map(node_kind);
+                if matches!(
+                    previous,
+                    None | Some(Kind::Bin | Kind::Op | Kind::Rel | Kind::Open | Kind::Punct)
+                ) || matches!(next, None | Some(Kind::Rel | Kind::Close | Kind::Punct))
+                {
+                    kind = Kind::Ord;
+                }
+            }
+            if let Some(previous) = previous {
+                result.width += spacing(previous, kind, style);
+            }
+            let b = self.node(node, style);
+            let x = result.width;
+            result.width += b.width;
+            result.ascent = result.ascent.max(b.ascent);
+            result.descent = result.descent.max(b.descent);
+            result.append(b, vec2(x, 0.0));
+            if !matches!(node, Node::Space(_)) {
+                previous = Some(kind);
+            }
+        }
+        if result.items.is_empty() && result.width == 0.0 {
+            // Keep empty groups, like `{}^2`, from collapsing.
+            result.ascent = style.size * 0.7;
+        }
+        result
+    }
+
+    fn fraction(&self, num: &Node, den: &Node, bar: bool, style: Style) -> MathBox {
+        let inner = style.fraction();
+        let num = self.node(num, inner);
+        let den = self.node(den, inner);
+        let rule = style.rule();
+        let gap = style.size * if style.display { 0.15 } else { 0.08 };
+        let padding = style.size * 0.12;
+        let width = num.width.max(den.width) + 2.0 * padding;
+        let axis = style.axis();
+        let num_baseline = -(axis + rule / 2.0 + gap + num.descent);
+        let den_baseline = -(axis - rule / 2.0 - gap) + den.ascent;
+        let mut result = MathBox {
+            width,
+            ascent: -num_baseline + num.ascent,
+            descent: den_baseline + den.descent,
+            items: Vec::new(),
+        };
+        if bar {
+            result.items.push(Item::Rule(Rect::from_min_size(
+                pos2(padding * 0.5, -axis - rule / 2.0),
+                vec2(width - padding, rule),
+            )));
+        }
+        let num_x = (width - num.width) / 2.0;
+        let den_x = (width - den.width) / 2.0;
+        result.append(num, vec2(num_x, num_baseline));
+        result.append(den, vec2(den_x, den_baseline));
+        result
+    }
+
+    fn sqrt(&self, index: Option<&Node>, body: &Node, style: Style) -> MathBox {
+        let body = self.node(body, style);
+        let rule = style.rule();
+        let gap = style.size * 0.12;
+        let top = -(body.ascent + gap + rule);
+        let bottom = body.descent;
+        let sign_width = style.size * 0.6;
+        let index = index.map(|index| {
+            self.node(
+                index,
+                Style {
+                    size: (style.size * 0.5).max(7.0),
+                    display: false,
+                },
+            )
+        });
+        // The index sits in the crook of the sign; make room if it's wide.
+        let lead = index
+            .as_ref()
+            .map_or(0.0, |index| (index.width - sign_width * 0.45).max(0.0));
+        let mid = bottom - (bottom - top) * 0.45;
+        let mut result = MathBox {
+            width: lead + sign_width + body.width + style.size * 0.1,
+            ascent: -top,
+            descent: bottom,
+            items: vec![Item::Line(
+                vec![
+                    vec2(lead, mid + style.size * 0.05),
+                    vec2(lead + sign_width * 0.2, mid),
+                    vec2(lead + sign_width * 0.45, bottom),
+                    vec2(lead + sign_width, top + rule / 2.0),
+                    vec2(
+                        lead + sign_width + body.width + style.size * 0.1,
+                        top + rule / 2.0,
+                    ),
+                ],
+                rule,
+            )],
+        };
+        if let Some(index) = index {
+            let index_baseline = mid - style.size * 0.1 - index.descent;
+            result.ascent = result.ascent.max(-index_baseline + index.ascent);
+            let index_x = lead + sign_width * 0.45 - index.width;
+            result.append(index, vec2(index_x.max(0.0), index_baseline));
+        }
+        result.append(body, vec2(lead + sign_width, 0.0));
+        result
+    }
+
+    fn scripts(
+        &self,
+        base: &Node,
+        sub: Option<&Node>,
+        sup: Option<&Node>,
+        style: Style,
+    ) -> MathBox {
+        let limits = style.display && matches!(base, Node::Atom { limits: true, .. });
+        let base_box = self.node(base, style);
+        let script_style = style.script();
+        let sub = sub.map(|sub| self.node(sub, script_style));
+        let sup = sup.map(|sup| self.node(sup, script_style));
+        if limits {
+            return self.limits(base_box, sub, sup, style);
+        }
+        let mut result = MathBox {
+            width: base_box.width,
+            ascent: base_box.ascent,
+            descent: base_box.descent,
+            items: Vec::new(),
+        };
+        let is_atom = matches!(base, Node::Atom { .. });
+        let x = base_box.width + style.size * 0.05;
+        result.append(base_box.clone(), Vec2::ZERO);
+        let mut sup_shift = sup.as_ref().map(|sup| {
+            let from_base = if is_atom {
+                0.0
+            } else {
+                base_box.ascent - sup.ascent * 0.5
+            };
+            (style.size * 0.4)
+                .max(from_base)
+                .max(sup.descent + style.size * 0.2)
+        });
+        let mut sub_shift = sub.as_ref().map(|sub| {
+            let from_base = if is_atom {
+                0.0
+            } else {
+                base_box.descent + sub.ascent * 0.2
+            };
+            (style.size * 0.2).max(from_base)
+        });
+        if let (Some(sup), Some(sub), Some(up), Some(down)) =
+            (&sup, &sub, sup_shift.as_mut(), sub_shift.as_mut())
+        {
+            let clearance = (*up - sup.descent) - (sub.ascent - *down);
+            let min_clearance = style.size * 0.2;
+            if clearance < min_clearance {
+                *down += min_clearance - clearance;
+            }
+        }
+        let mut width = result.width;
+        if let (Some(sup), Some(up)) = (sup, sup_shift) {
+            width = width.max(x + sup.width);
+            result.ascent = result.ascent.max(up + sup.ascent);
+            result.descent = result.descent.max(sup.descent - up);
+            result.append(sup, vec2(x, -up));
+        }
+        if let (Some(sub), Some(down)) = (sub, sub_shift) {
+            width = width.max(x + sub.width);
+            result.descent = result.descent.max(down + sub.descent);
+            result.append(sub, vec2(x, down));
+        }
+        result.width = width + style.size * 0.05;
+        result
+    }
+
+    /// Places scripts centered above and below a large operator.
+    fn limits(
+        &self,
+        base: MathBox,
+        sub: Option<MathBox>,
+        sup: Option<MathBox>,
+        style: Style,
+    ) -> MathBox {
+        let gap = style.size * 0.1;
+        let width = [
+            base.width,
+            sub.as_ref().map_or(0.0, |b| b.width),
+            sup.as_ref().map_or(0.0, |b| b.width),
+        ]
+        .into_iter()
+        .fold(0.0, f32::max);
+        let mut result = MathBox {
+            width,
+            ascent: base.ascent,
+            descent: base.descent,
+            items: Vec::new(),
+        };
+        if let Some(sup) = sup {
+            let baseline = -(base.ascent + gap + sup.descent);
+            result.ascent = -baseline + sup.ascent;
+            let x = (width - sup.width) / 2.0;
+            result.append(sup, vec2(x, baseline));
+        }
+        if let Some(sub) = sub {
+            let baseline = base.descent + gap + sub.ascent;
+            result.descent = baseline + sub.descent;
+            let x = (width - sub.width) / 2.0;
+            result.append(sub, vec2(x, baseline));
+        }
+        let x = (width - base.width) / 2.0;
+        result.append(base, vec2(x, 0.0));
+        result
+    }
+
+    fn matrix(&self, rows: &[Vec<Node>], align: MatrixAlign, style: Style) -> MathBox {
+        let cell_style = Style {
+            size: style.size,
+            display: false,
+        };
+        let cells: Vec<Vec<MathBox>> = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| self.node(cell, cell_style)).collect())
+            .collect();
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+        let column_widths: Vec<f32> = (0..columns)
+            .map(|c| {
+                cells
+                    .iter()
+                    .filter_map(|row| row.get(c))
+                    .map(|b| b.width)
+                    .fold(0.0, f32::max)
+            })
+            .collect();
+        let column_gap = match align {
+            MatrixAlign::RightLeft => 0.0,
+            _ => style.size * 0.9,
+        };
+        let row_gap = style.size * 0.3;
+        let mut result = MathBox::default();
+        let mut y = 0.0;
+        for row in cells {
+            let ascent = row
+                .iter()
+                .map(|b| b.ascent)
+                .fold(style.size * 0.7, f32::max);
+            let descent = row
+                .iter()
+                .map(|b| b.descent)
+                .fold(style.size * 0.25, f32::max);
+            let baseline = y + ascent;
+            let mut x = 0.0;
+            for (c, cell) in row.into_iter().enumerate() {
+                let slack = column_widths[c] - cell.width;
+                let cell_x = match align {
+                    MatrixAlign::Center => slack / 2.0,
+                    MatrixAlign::Left => 0.0,
+                    MatrixAlign::RightLeft if c % 2 == 0 => slack,
+                    MatrixAlign::RightLeft => 0.0,
+                };
+                result.append(cell, vec2(x + cell_x, baseline));
+                x += column_widths[c] + column_gap;
+            }
+            y = baseline + descent + row_gap;
+        }
+        let height = (y - row_gap).max(0.0);
+        result.width =
+            column_widths.iter().sum::<f32>() + column_gap * columns.saturating_sub(1) as f32;
+        // Center the block on the axis.
+        let shift = -(height / 2.0 + style.axis());
+        let mut centered = MathBox {
+            width: result.width,
+            ascent: -shift,
+            descent: height + shift,
+            items: Vec::new(),
+        };
+        centered.append(result, vec2(0.0, shift));
+        centered
+    }
+
+    fn delimited(&self, left: char, body: MathBox, right: char, style: Style) -> MathBox {
+        let axis = style.axis();
+        // Delimiters are symmetric around the axis and cover the body.
+        let half = (body.ascent - axis)
+            .max(body.descent + axis)
+            .max(style.size * 0.6);
+        let top = -axis - half;
+        let bottom = -axis + half;
+        let rule = style.rule();
+        // Bodies no taller than a line of text get the font's own glyphs.
+        let plain = self.text("(", style.size, Variant::Upright);
+        let fits_glyph = body.ascent <= plain.ascent && body.descent <= plain.descent;
+        let draw = |c: char| match c {
+            '.' => MathBox::default(),
+            c if fits_glyph => self.text(&c.to_string(), style.size, Variant::Upright),
+            c => delimiter(c, top, bottom, rule, style.size),
+        };
+        let (left_box, right_box) = (draw(left), draw(right));
+        let pad = if left == '.' && right == '.' {
+            0.0
+        } else {
+            style.size * 0.1
+        };
+        let mut result = MathBox {
+            width: left_box.width + pad + body.width + pad + right_box.width,
+            ascent: body.ascent.max(-top),
+            descent: body.descent.max(bottom),
+            items: Vec::new(),
+        };
+        let body_x = left_box.width + pad;
+        let right_x = body_x + body.width + pad;
+        result.append(left_box, Vec2::ZERO);
+        result.append(body, vec2(body_x, 0.0));
+        result.append(right_box, vec2(right_x, 0.0));
+        result
+    }
+
+    fn accent(&self, accent: Accent, body: &Node, style: Style) -> MathBox {
+        let body = self.node(body, style);
+        let rule = style.rule();
+        let size = style.size;
+        let width = body.width;
+        let mut result = MathBox {
+            width,
+            ascent: body.ascent,
+            descent: body.descent,
+            items: Vec::new(),
+        };
+        // The font's ascent leaves room for accents on capitals already.
+        let top = -(body.ascent - size * 0.18);
+        let mid = width / 2.0;
+        let item = match accent {
+            Accent::Hat => Item::Line(
+                vec![
+                    vec2(mid - size * 0.2, top),
+                    vec2(mid, top - size * 0.15),
+                    vec2(mid + size * 0.2, top),
+                ],
+                rule,
+            ),
+            Accent::Bar => Item::Rule(Rect::from_min_size(
+                pos2(size * 0.05, top - rule),
+                vec2((width - size * 0.1).max(size * 0.3), rule),
+            )),
+            Accent::Vec => {
+                let y = top - size * 0.05;
+                let right = width.max(size * 0.5);
+                result.items.push(Item::Line(
+                    vec![
+                        vec2(right - size * 0.15, y - size * 0.1),
+                        vec2(right, y),
+                        vec2(right - size * 0.15, y + size * 0.1),
+                    ],
+                    rule,
+                ));
+                Item::Line(vec![vec2(0.0, y), vec2(right, y)], rule)
+            }
+            Accent::Tilde => {
+                let half = (width / 2.0).clamp(size * 0.2, size * 0.5);
+                Item::Curve(
+                    [
+                        vec2(mid - half, top),
+                        vec2(mid, top - size * 0.3),
+                        vec2(mid + half, top - size * 0.1),
+                    ],
+                    rule,
+                )
+            }
+            Accent::Dot => Item::Dot(vec2(mid, top - size * 0.05), size * 0.06),
+            Accent::DDot => {
+                result.items.push(Item::Dot(
+                    vec2(mid - size * 0.12, top - size * 0.05),
+                    size * 0.06,
+                ));
+                Item::Dot(vec2(mid + size * 0.12, top - size * 0.05), size * 0.06)
+            }
+            Accent::Underline => {
+                let y = body.descent + size * 0.05;
+                result.descent = y + rule;
+                Item::Rule(Rect::from_min_size(pos2(0.0, y), vec2(width, rule)))
+            }
+        };
+        result.items.push(item);
+        if accent != Accent::Underline {
+            result.ascent = result.ascent.max(-top + size * 0.2);
+        }
+        result.append(body, Vec2::ZERO);
+        result
+    }
+}
+
+fn node_kind(node: &Node) -> Kind {
+    match node {
+        Node::Atom { kind, .. } => *kind,
+        Node::Scripts { base, .. } => match node_kind(base) {
+            Kind::Op => Kind::Op,
+            _ => Kind::Ord,
+        },
+        _ => Kind::Ord,
+    }
+}
+
+/// Space between two atoms, in the spirit of TeX's inter-atom spacing table.
+fn spacing(left: Kind, right: Kind, style: Style) -> f32 {
+    use Kind::*;
+    let (thin, medium, thick) = (style.size * 0.17, style.size * 0.22, style.size * 0.28);
+    let script = style.size < 10.0 && !style.display;
+    match (left, right) {
+        (Bin, _) | (_, Bin) if !script => medium,
+        (Rel, Rel) => 0.0,
+        (Rel, _) | (_, Rel) if !script => thick,
+        (Op, Ord | Op) | (Ord, Op) | (Close, Op) => thin,
+        (Punct, _) if !script => thin,
+        _ => 0.0,
+    }
+}
+
+/// Draws a stretchy delimiter spanning `top..bottom`.
+fn delimiter(c: char, top: f32, bottom: f32, rule: f32, size: f32) -> MathBox {
+    let height = bottom - top;
+    let mid = (top + bottom) / 2.0;
+    let width = match c {
+        '.' => 0.0,
+        '|' => size * 0.25,
+        '‖' => size * 0.4,
+        _ => (size * 0.3).max(height * 0.12).min(size * 0.6),
+    };
+    let (l, r) = (rule, width - rule);
+    let items = match c {
+        '(' => vec![Item::Curve(
+            [vec2(r, top), vec2(l - width * 0.4, mid), vec2(r, bottom)],
+            rule,
+        )],
+        ')' => vec![Item::Curve(
+            [vec2(l, top), vec2(r + width * 0.4, mid), vec2(l, bottom)],
+            rule,
+        )],
+        '[' => vec![Item::Line(
+            vec![vec2(r, top), vec2(l, top), vec2(l, bottom), vec2(r, bottom)],
+            rule,
+        )],
+        ']' => vec![Item::Line(
+            vec![vec2(l, top), vec2(r, top), vec2(r, bottom), vec2(l, bottom)],
+            rule,
+        )],
+        '⌊' => vec![Item::Line(
+            vec![vec2(l, top), vec2(l, bottom), vec2(r, bottom)],
+            rule,
+        )],
+        '⌋' => vec![Item::Line(
+            vec![vec2(r, top), vec2(r, bottom), vec2(l, bottom)],
+            rule,
+        )],
+        '⌈' => vec![Item::Line(
+            vec![vec2(r, top), vec2(l, top), vec2(l, bottom)],
+            rule,
+        )],
+        '⌉' => vec![Item::Line(
+            vec![vec2(l, top), vec2(r, top), vec2(r, bottom)],
+            rule,
+        )],
+        '{' => {
+            let x = width / 2.0;
+            vec![
+                Item::Curve(
+                    [vec2(r, top), vec2(x, top), vec2(x, mid - height * 0.2)],
+                    rule,
+                ),
+                Item::Curve(
+                    [vec2(x, mid - height * 0.2), vec2(x, mid), vec2(l, mid)],
+                    rule,
+                ),
+                Item::Curve(
+                    [vec2(l, mid), vec2(x, mid), vec2(x, mid + height * 0.2)],
+                    rule,
+                ),
+                Item::Curve(
+                    [
+                        vec2(x, mid + height * 0.2),
+                        vec2(x, bottom),
+                        vec2(r, bottom),
+                    ],
+                    rule,
+                ),
+            ]
+        }
+        '}' => {
+            let x = width / 2.0;
+            vec![
+                Item::Curve(
+                    [vec2(l, top), vec2(x, top), vec2(x, mid - height * 0.2)],
+                    rule,
+                ),
+                Item::Curve(
+                    [vec2(x, mid - height * 0.2), vec2(x, mid), vec2(r, mid)],
+                    rule,
+                ),
+                Item::Curve(
+                    [vec2(r, mid), vec2(x, mid), vec2(x, mid + height * 0.2)],
+                    rule,
+                ),
+                Item::Curve(
+                    [
+                        vec2(x, mid + height * 0.2),
+                        vec2(x, bottom),
+                        vec2(l, bottom),
+                    ],
+                    rule,
+                ),
+            ]
+        }
+        '⟨' => vec![Item::Line(
+            vec![vec2(r, top), vec2(l, mid), vec2(r, bottom)],
+            rule,
+        )],
+        '⟩' => vec![Item::Line(
+            vec![vec2(l, top), vec2(r, mid), vec2(l, bottom)],
+            rule,
+        )],
+        '|' => vec![Item::Line(
+            vec![vec2(width / 2.0, top), vec2(width / 2.0, bottom)],
+            rule,
+        )],
+        '‖' => vec![
+            Item::Line(
+                vec![vec2(width * 0.3, top), vec2(width * 0.3, bottom)],
+                rule,
+            ),
+            Item::Line(
+                vec![vec2(width * 0.7, top), vec2(width * 0.7, bottom)],
+                rule,
+            ),
+        ],
+        '/' => vec![Item::Line(vec![vec2(r, top), vec2(l, bottom)], rule)],
+        _ => Vec::new(),
+    };
+    MathBox {
+        width,
+        ascent: -top,
+        descent: bottom,
+        items,
+    }
+}
+
+/// Parses and lays out a formula at the given font size. Display formulas get
+/// larger operators and limits above and below.
+pub fn layout(fonts: &Fonts, source: &str, size: f32, display: bool) -> Result<MathBox, String> {
+    let node = parse(source)?;
+    let layouter = Layouter { fonts };
+    Ok(layouter.node(&node, Style { size, display }))
+}
+
+/// The text format of a transparent stand-in glyph that reserves room for
+/// `formula` in a line of text set in `font_id`. Paint the formula with its
+/// baseline at the stand-in's baseline.
+pub fn placeholder_format(fonts: &Fonts, formula: &MathBox, font_id: &FontId) -> TextFormat {
+    let metrics = |size: f32| {
+        let font_id = FontId::new(size, font_id.family.clone());
+        let galley = fonts.layout_no_wrap(PLACEHOLDER.to_string(), font_id, Color32::TRANSPARENT);
+        let ascent = galley
+            .rows
+            .first()
+            .and_then(|row| row.glyphs.first())
+            .map_or(size * 0.8, |glyph| glyph.pos.y);
+        (ascent, galley.size())
+    };
+    let (ascent, _) = metrics(font_id.size);
+    // A larger font raises the row's ascent for tall formulas.
+    let size = font_id.size * (formula.ascent / ascent).max(1.0);
+    let (ascent, glyph_size) = metrics(size);
+    TextFormat {
+        font_id: FontId::new(size, font_id.family.clone()),
+        // Letter spacing only applies between glyphs, hence two of them.
+        extra_letter_spacing: formula.width - glyph_size.x,
+        line_height: Some(ascent + formula.descent.max(glyph_size.y - ascent)),
+        color: Color32::TRANSPARENT,
+        ..Default::default()
+    }
+}
+
+/// Text of the stand-in, see [`placeholder_format`].
+pub const PLACEHOLDER: &str = "00";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::FontDefinitions;
+
+    fn parse_one(source: &str) -> Node {
+        match parse(source) {
+            Ok(Node::Group(mut nodes)) if nodes.len() == 1 => nodes.remove(0),
+            other => panic!("{:?} parsed as {:?}", source, other),
+        }
+    }
+
+    fn text(node: &Node) -> &str {
+        match node {
+            Node::Atom { text, .. } => text,
+            Node::Group(nodes) if nodes.len() == 1 => text(&nodes[0]),
+            other => panic!("expected an atom, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fractions() {
+        let Node::Frac { num, den, bar } = parse_one(r"\frac{a+b}{2}") else {
+            panic!("not a fraction");
+        };
+        assert!(bar);
+        assert!(matches!(*num, Node::Group(ref nodes) if nodes.len() == 3));
+        assert_eq!(text(&den), "2");
+        // Single-token arguments don't need braces.
+        let Node::Frac { num, den, .. } = parse_one(r"\frac12") else {
+            panic!("not a fraction");
+        };
+        assert_eq!((text(&num), text(&den)), ("1", "2"));
+        assert!(matches!(
+            parse_one(r"\binom{n}{k}"),
+            Node::Delimited { left: '(', right: ')', body } if matches!(*body, Node::Frac { bar: false, .. })
+        ));
+        assert_eq!(parse(r"\frac{1}").unwrap_err(), "Missing argument");
+    }
+
+    #[test]
+    fn scripts() {
+        let Node::Scripts { base, sub, sup } = parse_one("x_i^2") else {
+            panic!("no scripts");
+        };
+        assert_eq!(text(&base), "x");
+        assert_eq!(text(sub.as_deref().unwrap()), "i");
+        assert_eq!(text(sup.as_deref().unwrap()), "2");
+        let Node::Scripts { sup, .. } = parse_one("e^{i\\pi}") else {
+            panic!("no scripts");
+        };
+        assert!(matches!(sup.as_deref(), Some(Node::Group(nodes)) if nodes.len() == 2));
+        assert!(matches!(
+            parse_one("f'"),
+            Node::Scripts {
+                sub: None,
+                sup: Some(_),
+                ..
+            }
+        ));
+        assert_eq!(
+            parse("x^2^3").unwrap_err(),
+            "Double subscript or superscript"
+        );
+    }
+
+    #[test]
+    fn environments() {
+        let Node::Matrix {
+            rows,
+            left,
+            right,
+            align,
+        } = parse_one(r"\begin{pmatrix} a & b \\ c & d \\ \end{pmatrix}")
+        else {
+            panic!("not a matrix");
+        };
+        // The trailing `\\` doesn't add an empty row.
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.len() == 2));
+        assert_eq!((left, right, align), ('(', ')', MatrixAlign::Center));
+        let Node::Matrix { left, right, .. } = parse_one(r"\begin{cases} 1 & x > 0 \end{cases}")
+        else {
+            panic!("not cases");
+        };
+        assert_eq!((left, right), ('{', '.'));
+        assert_eq!(
+            parse(r"\begin{matrix} a \end{pmatrix}").unwrap_err(),
+            r"\begin{matrix} ended by \end{pmatrix}"
+        );
+        assert_eq!(
+            parse(r"\begin{matrix} a").unwrap_err(),
+            r"Missing \end{matrix}"
+        );
+        assert_eq!(
+            parse(r"\begin{tabular}").unwrap_err(),
+            "Unsupported environment 'tabular'"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("a}").unwrap_err(), "Unmatched '}'");
+        assert_eq!(parse("{a").unwrap_err(), "Missing '}'");
+        assert_eq!(
+            parse(r"\left( x").unwrap_err(),
+            r"\left without matching \right"
+        );
+        assert_eq!(
+            parse("a & b").unwrap_err(),
+            r"'&' or '\\' outside of a matrix"
+        );
+        assert_eq!(parse(r"\foo").unwrap_err(), r"Unsupported command \foo");
+    }
+
+    #[test]
+    fn layout_stacks_fractions() {
+        let fonts = Fonts::new(1.0, 1024, FontDefinitions::default());
+        let plain = layout(&fonts, "a", 16.0, false).unwrap();
+        let fraction = layout(&fonts, r"\frac{a}{b}", 16.0, false).unwrap();
+        assert!(fraction.ascent > plain.ascent);
+        assert!(fraction.descent > plain.descent);
+        let inline = layout(&fonts, r"\sum_{i=1}^n i", 16.0, false).unwrap();
+        let display = layout(&fonts, r"\sum_{i=1}^n i", 16.0, true).unwrap();
+        // Limits go above and below in display style.
+        assert!(display.height() > inline.height());
+        assert!(display.width < inline.width + 16.0);
+    }
+}
@@ -0,0 +1,1642 @@
+//! Offline rendering of Mermaid diagrams: a parser and layout engine for
+//! flowcharts (`flowchart`/`graph`) and sequence diagrams, painted with egui
+//! shapes.
+
+use eframe::egui::{
+    epaint::{CircleShape, CubicBezierShape, Fonts, RectShape},
+    pos2, vec2, Color32, FontId, Galley, Painter, Pos2, Rect, Rounding, Shape, Stroke, Vec2,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const FONT_SIZE: f32 = 13.0;
+const MARGIN: f32 = 8.0;
+
+/// Colours for a diagram, taken from the current visuals.
+pub struct DiagramTheme {
+    pub text: Color32,
+    pub fill: Color32,
+    pub stroke: Color32,
+    pub line: Color32,
+    pub label_background: Color32,
+    pub note_fill: Color32,
+}
+
+pub enum Diagram {
+    Flowchart(Flowchart),
+    Sequence(Sequence),
+}
+
+/// A laid out diagram; shapes are relative to its top-left corner.
+pub struct Drawing {
+    pub size: Vec2,
+    shapes: Vec<Shape>,
+}
+
+impl Drawing {
+    pub fn paint(&self, painter: &Painter, origin: Pos2) {
+        for shape in &self.shapes {
+            let mut shape = shape.clone();
+            shape.translate(origin.to_vec2());
+            painter.add(shape);
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Result<Diagram, String> {
+    let mut lines = source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("%%"));
+    let header = lines.next().ok_or_else(|| "Empty diagram".to_string())?;
+    // `graph TD; A --> B` has statements on the header line.
+    let (header, statements) = header.split_once(';').unwrap_or((header, ""));
+    let mut words = header.split_whitespace();
+    match words.next() {
+        Some("flowchart" | "graph") => {
+            let direction = match words.next().map(|d| d.trim_end_matches(';')) {
+                None | Some("TD" | "TB") => Direction::TopDown,
+                Some("BT") => Direction::BottomUp,
+                Some("LR") => Direction::LeftRight,
+                Some("RL") => Direction::RightLeft,
+                Some(other) => return Err(format!("Unknown direction '{}'", other)),
+            };
+            parse_flowchart(direction, std::iter::once(statements).chain(lines))
+                .map(Diagram::Flowchart)
+        }
+        Some("sequenceDiagram") => parse_sequence(lines).map(Diagram::Sequence),
+        Some(other) => Err(format!("Unsupported diagram type '{}'", other)),
+        None => Err("Empty diagram".to_string()),
+    }
+}
+
+impl Diagram {
+    pub fn layout(&self, fonts: &Fonts, theme: &DiagramTheme) -> Drawing {
+        let mut drawing = match self {
+            Diagram::Flowchart(chart) => layout_flowchart(chart, fonts, theme),
+            Diagram::Sequence(sequence) => layout_sequence(sequence, fonts, theme),
+        };
+        // Normalize so the drawing starts at the margin.
+        let bounds = drawing
+            .shapes
+            .iter()
+            .map(Shape::visual_bounding_rect)
+            .filter(|r| r.is_positive())
+            .fold(Rect::NOTHING, |a, b| a.union(b));
+        if bounds.is_positive() {
+            let offset = vec2(MARGIN, MARGIN) - bounds.min.to_vec2();
+            for shape in &mut drawing.shapes {
+                shape.translate(offset);
+            }
+            drawing.size = bounds.size() + vec2(2.0 * MARGIN, 2.0 * MARGIN);
+        }
+        drawing
+    }
+}
+
+/// Label text with Mermaid's `<br>` line breaks and optional quotes.
+fn label_text(text: &str) -> String {
+    let text = text.trim();
+    let text = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(text);
+    text.replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .replace("<br>", "\n")
+        .replace("#quot;", "\"")
+}
+
+fn text_galley(fonts: &Fonts, text: &str, color: Color32) -> Arc<Galley> {
+    fonts.layout_no_wrap(text.to_string(), FontId::proportional(FONT_SIZE), color)
+}
+
+/// Text centered on `center`.
+fn centered_text(galley: Arc<Galley>, center: Pos2) -> Shape {
+    let pos = center - galley.size() / 2.0;
+    Shape::galley(pos, galley, Color32::PLACEHOLDER)
+}
+
+/// A label with a background, so it stays readable on top of lines.
+fn boxed_label(shapes: &mut Vec<Shape>, galley: Arc<Galley>, center: Pos2, background: Color32) {
+    let rect = Rect::from_center_size(center, galley.size() + vec2(6.0, 2.0));
+    shapes.push(Shape::rect_filled(rect, 2.0, background));
+    shapes.push(centered_text(galley, center));
+}
+
+fn arrow_head(shapes: &mut Vec<Shape>, tip: Pos2, from: Pos2, color: Color32, filled: bool) {
+    let dir = (tip - from).normalized();
+    if !dir.is_finite() {
+        return;
+    }
+    let normal = vec2(-dir.y, dir.x);
+    let size = 8.0;
+    let a = tip - dir * size + normal * size * 0.45;
+    let b = tip - dir * size - normal * size * 0.45;
+    if filled {
+        shapes.push(Shape::convex_polygon(vec![tip, a, b], color, Stroke::NONE));
+    } else {
+        shapes.push(Shape::line(vec![a, tip, b], Stroke::new(1.3, color)));
+    }
+}
+
+fn end_marker(shapes: &mut Vec<Shape>, marker: Marker, tip: Pos2, from: Pos2, color: Color32) {
+    match marker {
+        Marker::None => {}
+        Marker::Arrow => arrow_head(shapes, tip, from, color, true),
+        Marker::Circle => {
+            let dir = (tip - from).normalized();
+            shapes.push(Shape::circle_stroke(
+                tip - dir * 4.0,
+                4.0,
+                Stroke::new(1.3, color),
+            ));
+        }
+        Marker::Cross => {
+            let dir = (tip - from).normalized();
+            let center = tip - dir * 5.0;
+            let s = 4.0;
+            let stroke = Stroke::new(1.5, color);
+            shapes.push(Shape::line_segment(
+                [center + vec2(-s, -s), center + vec2(s, s)],
+                stroke,
+            ));
+            shapes.push(Shape::line_segment(
+                [center + vec2(-s, s), center + vec2(s, -s)],
+                stroke,
+            ));
+        }
+    }
+}
+
+fn stroke_path(shapes: &mut Vec<Shape>, points: &[Pos2], stroke: Stroke, dashed: bool) {
+    if dashed {
+        shapes.extend(Shape::dashed_line(points, stroke, 4.0, 3.0));
+    } else {
+        shapes.push(Shape::line(points.to_vec(), stroke));
+    }
+}
+
+// --- Flowcharts ---
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    TopDown,
+    BottomUp,
+    LeftRight,
+    RightLeft,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NodeShape {
+    Rect,
+    Round,
+    Stadium,
+    Subroutine,
+    Cylinder,
+    Circle,
+    Diamond,
+    Hexagon,
+    Parallelogram,
+    Asymmetric,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LineStyle {
+    Solid,
+    Dotted,
+    Thick,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Marker {
+    None,
+    Arrow,
+    Circle,
+    Cross,
+}
+
+struct FlowNode {
+    label: String,
+    shape: NodeShape,
+}
+
+struct FlowEdge {
+    from: usize,
+    to: usize,
+    label: Option<String>,
+    line: LineStyle,
+    head: Marker,
+    tail: Marker,
+}
+
+pub struct Flowchart {
+    direction: Direction,
+    nodes: Vec<FlowNode>,
+    edges: Vec<FlowEdge>,
+}
+
+/// Shape delimiters, longest first so `([` wins over `(`.
+const SHAPES: &[(&str, &str, NodeShape)] = &[
+    ("([", "])", NodeShape::Stadium),
+    ("[[", "]]", NodeShape::Subroutine),
+    ("[(", ")]", NodeShape::Cylinder),
+    ("((", "))", NodeShape::Circle),
+    ("{{", "}}", NodeShape::Hexagon),
+    ("[/", "/]", NodeShape::Parallelogram),
+    ("[\\", "\\]", NodeShape::Parallelogram),
+    ("[", "]", NodeShape::Rect),
+    ("(", ")", NodeShape::Round),
+    ("{", "}", NodeShape::Diamond),
+    (">", "]", NodeShape::Asymmetric),
+];
+
+/// A parsed link: line style, head and tail markers, and label.
+type Link = (LineStyle, Marker, Marker, Option<String>);
+
+struct FlowParser<'a> {
+    rest: &'a str,
+    ids: HashMap<String, usize>,
+    nodes: Vec<FlowNode>,
+}
+
+impl<'a> FlowParser<'a> {
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    /// Parses `id`, `id[label]`, `id(label)` etc. and returns the node index.
+    fn node(&mut self) -> Result<usize, String> {
+        self.skip_whitespace();
+        let id_len = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if id_len == 0 {
+            return Err(format!("Expected a node at '{}'", self.rest));
+        }
+        let id = self.rest[..id_len].to_string();
+        self.rest = &self.rest[id_len..];
+        let mut shape_label = None;
+        for &(open, close, shape) in SHAPES {
+            if let Some(after) = self.rest.strip_prefix(open) {
+                // Quoted labels may contain the closing delimiter.
+                let end = if after.trim_start().starts_with('"') {
+                    let quote = after.find('"').unwrap_or(0);
+                    after[quote + 1..].find('"').and_then(|q| {
+                        after[quote + q + 2..]
+                            .find(close)
+                            .map(|c| quote + q + 2 + c)
+                    })
+                } else {
+                    after.find(close)
+                };
+                let end = end.ok_or_else(|| format!("Missing '{}' for node '{}'", close, id))?;
+                shape_label = Some((shape, label_text(&after[..end])));
+                self.rest = &after[end + close.len()..];
+                break;
+            }
+        }
+        // Styling classes aren't supported.
+        if let Some(after) = self.rest.strip_prefix(":::") {
+            let len = after
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(after.len());
+            self.rest = &after[len..];
+        }
+        let index = match self.ids.get(&id) {
+            Some(&index) => index,
+            None => {
+                self.nodes.push(FlowNode {
+                    label: id.clone(),
+                    shape: NodeShape::Rect,
+                });
+                self.ids.insert(id, self.nodes.len() - 1);
+                self.nodes.len() - 1
+            }
+        };
+        if let Some((shape, label)) = shape_label {
+            self.nodes[index] = FlowNode { label, shape };
+        }
+        Ok(index)
+    }
+
+    /// `a & b & c`
+    fn node_group(&mut self) -> Result<Vec<usize>, String> {
+        let mut group = vec![self.node()?];
+        loop {
+            self.skip_whitespace();
+            match self.rest.strip_prefix('&') {
+                Some(after) => {
+                    self.rest = after;
+                    group.push(self.node()?);
+                }
+                None => return Ok(group),
+            }
+        }
+    }
+
+    /// Parses a link such as `-->`, `-.->`, `==>`, `--o`, `<-->`, `-- text -->`
+    /// or `-->|text|`. Returns `None` if there is no link here.
+    fn link(&mut self) -> Result<Option<Link>, String> {
+        self.skip_whitespace();
+        let start = self.rest;
+        let mut rest = start;
+        let tail = match rest.chars().next() {
+            Some('<') => Marker::Arrow,
+            Some('o') if rest[1..].starts_with(['-', '=']) => Marker::Circle,
+            Some('x') if rest[1..].starts_with(['-', '=']) => Marker::Cross,
+            _ => Marker::None,
+        };
+        if tail != Marker::None {
+            rest = &rest[1..];
+        }
+        let run = rest
+            .find(|c: char| !matches!(c, '-' | '=' | '.'))
+            .unwrap_or(rest.len());
+        if run < 2 {
+            return Ok(None);
+        }
+        let mut line_chars = rest[..run].to_string();
+        rest = &rest[run..];
+        let mut label = None;
+        let mut head = marker_at(rest);
+        if head == Marker::None && rest.starts_with(char::is_whitespace) {
+            // `-- text -->`: the label runs up to the rest of the link.
+            let closing = match line_chars.as_str() {
+                "--" => Some("--"),
+                "==" => Some("=="),
+                "-." => Some(".-"),
+                _ => None,
+            };
+            if let Some(closing) = closing {
+                if let Some(end) = rest.find(closing) {
+                    label = Some(label_text(&rest[..end]));
+                    let after = &rest[end..];
+                    let run = after
+                        .find(|c: char| !matches!(c, '-' | '=' | '.'))
+                        .unwrap_or(after.len());
+                    line_chars.push_str(&after[..run]);
+                    rest = &after[run..];
+                    head = marker_at(rest);
+                }
+            }
+        }
+        if head != Marker::None {
+            rest = &rest[1..];
+        }
+        let line = if line_chars.contains('.') {
+            LineStyle::Dotted
+        } else if line_chars.contains('=') {
+            LineStyle::Thick
+        } else {
+            LineStyle::Solid
+        };
+        self.rest = rest;
+        self.skip_whitespace();
+        if let Some(after) = self.rest.strip_prefix('|') {
+            let end = after
+                .find('|')
+                .ok_or_else(|| "Missing '|' after link text".to_string())?;
+            label = Some(label_text(&after[..end]));
+            self.rest = &after[end + 1..];
+        }
+        Ok(Some((line, head, tail, label)))
+    }
+}
+
+fn marker_at(rest: &str) -> Marker {
+    let mut chars = rest.chars();
+    let marker = match chars.next() {
+        Some('>') => return Marker::Arrow,
+        Some('o') => Marker::Circle,
+        Some('x') => Marker::Cross,
+        _ => return Marker::None,
+    };
+    // `--o B` is a circle end, `--oB` would be a node called `oB`.
+    match chars.next() {
+        None => marker,
+        Some(c) if c.is_whitespace() || c == '|' => marker,
+        _ => Marker::None,
+    }
+}
+
+fn parse_flowchart<'a>(
+    direction: Direction,
+    lines: impl Iterator<Item = &'a str>,
+) -> Result<Flowchart, String> {
+    let mut parser = FlowParser {
+        rest: "",
+        ids: HashMap::new(),
+        nodes: Vec::new(),
+    };
+    let mut edges = Vec::new();
+    for line in lines {
+        for statement in line.split(';').map(str::trim) {
+            let keyword = statement.split_whitespace().next().unwrap_or("");
+            if statement.is_empty()
+                || matches!(
+                    keyword,
+                    "classDef"
+                        | "class"
+                        | "style"
+                        | "linkStyle"
+                        | "click"
+                        | "subgraph"
+                        | "end"
+                        | "direction"
+                )
+            {
+                continue;
+            }
+            parser.rest = statement;
+            let mut from = parser.node_group()?;
+            while let Some((line, head, tail, label)) = parser.link()? {
+                let to = parser.node_group()?;
+                for &a in &from {
+                    for &b in &to {
+                        edges.push(FlowEdge {
+                            from: a,
+                            to: b,
+                            label: label.clone(),
+                            line,
+                            head,
+                            tail,
+                        });
+                    }
+                }
+                from = to;
+            }
+            parser.skip_whitespace();
+            if !parser.rest.is_empty() {
+                return Err(format!("Unexpected '{}'", parser.rest));
+            }
+        }
+    }
+    if parser.nodes.is_empty() {
+        return Err("Flowchart has no nodes".to_string());
+    }
+    Ok(Flowchart {
+        direction,
+        nodes: parser.nodes,
+        edges,
+    })
+}
+
+/// A vertex of the layered graph: a node or a bend point of a long edge.
+struct Vertex {
+    layer: usize,
+    /// Size along the layer (cross) and rank (main) axes.
+    cross: f32,
+    main: f32,
+    position: f32,
+}
+
+fn node_size(shape: NodeShape, text: Vec2) -> Vec2 {
+    let padded = text + vec2(24.0, 14.0);
+    match shape {
+        NodeShape::Circle => {
+            let d = text.length() + 12.0;
+            vec2(d, d)
+        }
+        NodeShape::Diamond => {
+            let side = (text.x + text.y) * 1.0 + 24.0;
+            vec2(side.max(padded.x * 1.3), (side * 0.6).max(padded.y * 1.6))
+        }
+        NodeShape::Hexagon | NodeShape::Stadium => padded + vec2(padded.y, 0.0),
+        NodeShape::Parallelogram | NodeShape::Asymmetric => padded + vec2(padded.y * 0.8, 0.0),
+        NodeShape::Cylinder => padded + vec2(0.0, 12.0),
+        NodeShape::Subroutine => padded + vec2(16.0, 0.0),
+        NodeShape::Rect | NodeShape::Round => padded,
+    }
+}
+
+/// Orders the graph's edges so it has no cycles, by reversing edges that point
+/// back to a node on the current depth-first path.
+fn acyclic_edges(node_count: usize, edges: &[(usize, usize)]) -> Vec<bool> {
+    let mut reversed = vec![false; edges.len()];
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); node_count];
+    for (index, &(from, _)) in edges.iter().enumerate() {
+        outgoing[from].push(index);
+    }
+    // 0 = unvisited, 1 = on the path, 2 = done
+    let mut state = vec![0u8; node_count];
+    for root in 0..node_count {
+        if state[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0usize)];
+        state[root] = 1;
+        while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+            if let Some(&edge) = outgoing[node].get(*next) {
+                *next += 1;
+                let target = edges[edge].1;
+                match state[target] {
+                    0 => {
+                        state[target] = 1;
+                        stack.push((target, 0));
+                    }
+                    1 => reversed[edge] = true,
+                    _ => {}
+                }
+            } else {
+                state[node] = 2;
+                stack.pop();
+            }
+        }
+    }
+    reversed
+}
+
+fn layout_flowchart(chart: &Flowchart, fonts: &Fonts, theme: &DiagramTheme) -> Drawing {
+    let horizontal = matches!(chart.direction, Direction::LeftRight | Direction::RightLeft);
+    let galleys: Vec<Arc<Galley>> = chart
+        .nodes
+        .iter()
+        .map(|node| text_galley(fonts, &node.label, theme.text))
+        .collect();
+    let sizes: Vec<Vec2> = chart
+        .nodes
+        .iter()
+        .zip(&galleys)
+        .map(|(node, galley)| node_size(node.shape, galley.size()))
+        .collect();
+
+    // Rank the nodes along the main axis, ignoring self-loops.
+    let links: Vec<(usize, usize)> = chart
+        .edges
+        .iter()
+        .filter(|e| e.from != e.to)
+        .map(|e| (e.from, e.to))
+        .collect();
+    let reversed = acyclic_edges(chart.nodes.len(), &links);
+    let forward: Vec<(usize, usize)> = links
+        .iter()
+        .zip(&reversed)
+        .map(|(&(a, b), &rev)| if rev { (b, a) } else { (a, b) })
+        .collect();
+    let mut layer = vec![0usize; chart.nodes.len()];
+    let mut indegree = vec![0usize; chart.nodes.len()];
+    for &(_, b) in &forward {
+        indegree[b] += 1;
+    }
+    let mut queue: Vec<usize> = (0..chart.nodes.len())
+        .filter(|&n| indegree[n] == 0)
+        .collect();
+    while let Some(node) = queue.pop() {
+        for &(a, b) in forward.iter().filter(|(a, _)| *a == node) {
+            layer[b] = layer[b].max(layer[a] + 1);
+            indegree[b] -= 1;
+            if indegree[b] == 0 {
+                queue.push(b);
+            }
+        }
+    }
+
+    let main_size = |size: Vec2| if horizontal { size.x } else { size.y };
+    let cross_size = |size: Vec2| if horizontal { size.y } else { size.x };
+    let mut vertices: Vec<Vertex> = sizes
+        .iter()
+        .zip(&layer)
+        .map(|(&size, &layer)| Vertex {
+            layer,
+            cross: cross_size(size),
+            main: main_size(size),
+            position: 0.0,
+        })
+        .collect();
+    // Long edges get a bend point on every layer they cross.
+    let mut paths: Vec<Vec<usize>> = Vec::new();
+    for &(a, b) in &forward {
+        let mut path = vec![a];
+        for l in layer[a] + 1..layer[b] {
+            vertices.push(Vertex {
+                layer: l,
+                cross: 12.0,
+                main: 0.0,
+                position: 0.0,
+            });
+            path.push(vertices.len() - 1);
+        }
+        path.push(b);
+        paths.push(path);
+    }
+    let layer_count = vertices.iter().map(|v| v.layer + 1).max().unwrap_or(0);
+    let mut layers: Vec<Vec<usize>> = vec![Vec::new(); layer_count];
+    for (index, vertex) in vertices.iter().enumerate() {
+        layers[vertex.layer].push(index);
+    }
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    let mut successors: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for path in &paths {
+        for pair in path.windows(2) {
+            successors[pair[0]].push(pair[1]);
+            predecessors[pair[1]].push(pair[0]);
+        }
+    }
+
+    // Reduce crossings by sorting each layer on its neighbours' barycenters.
+    let mut order = vec![0.0f32; vertices.len()];
+    let renumber = |layers: &Vec<Vec<usize>>, order: &mut Vec<f32>| {
+        for layer in layers {
+            for (i, &v) in layer.iter().enumerate() {
+                order[v] = i as f32;
+            }
+        }
+    };
+    renumber(&layers, &mut order);
+    for sweep in 0..8 {
+        let down = sweep % 2 == 0;
+        let range: Vec<usize> = if down {
+            (1..layer_count).collect()
+        } else {
+            (0..layer_count.saturating_sub(1)).rev().collect()
+        };
+        for l in range {
+            let neighbours = if down { &predecessors } else { &successors };
+            let mut keyed: Vec<(f32, usize)> = layers[l]
+                .iter()
+                .map(|&v| {
+                    let n = &neighbours[v];
+                    let key = if n.is_empty() {
+                        order[v]
+                    } else {
+                        n.iter().map(|&u| order[u]).sum::<f32>() / n.len() as f32
+                    };
+                    (key, v)
+                })
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            layers[l] = keyed.into_iter().map(|(_, v)| v).collect();
+            for (i, &v) in layers[l].iter().enumerate() {
+                order[v] = i as f32;
+            }
+        }
+    }
+
+    // Cross-axis positions: pack each layer, then pull vertices towards their
+    // neighbours while keeping the layer's order and spacing.
+    let gap = 30.0;
+    for layer in &layers {
+        let mut x = 0.0;
+        for &v in layer {
+            vertices[v].position = x + vertices[v].cross / 2.0;
+            x += vertices[v].cross + gap;
+        }
+    }
+    for sweep in 0..4 {
+        let down = sweep % 2 == 0;
+        let range: Vec<usize> = if down {
+            (1..layer_count).collect()
+        } else {
+            (0..layer_count.saturating_sub(1)).rev().collect()
+        };
+        for l in range {
+            let neighbours = if down { &predecessors } else { &successors };
+            let desired: Vec<f32> = layers[l]
+                .iter()
+                .map(|&v| {
+                    let n = &neighbours[v];
+                    if n.is_empty() {
+                        vertices[v].position
+                    } else {
+                        n.iter().map(|&u| vertices[u].position).sum::<f32>() / n.len() as f32
+                    }
+                })
+                .collect();
+            // Place left to right at the desired spot or the first free one,
+            // then shift the layer so it's centered on what was desired.
+            let mut placed = Vec::with_capacity(desired.len());
+            let mut min_x = f32::NEG_INFINITY;
+            for (i, &v) in layers[l].iter().enumerate() {
+                let half = vertices[v].cross / 2.0;
+                let x = desired[i].max(min_x + half);
+                placed.push(x);
+                min_x = x + half + gap;
+            }
+            let shift = (desired.iter().sum::<f32>() - placed.iter().sum::<f32>())
+                / desired.len().max(1) as f32;
+            let shift = shift.max(0.0);
+            for (i, &v) in layers[l].iter().enumerate() {
+                vertices[v].position = placed[i] + shift;
+            }
+        }
+    }
+
+    // Main-axis positions of the layers.
+    let rank_gap = 50.0;
+    let mut layer_main = Vec::with_capacity(layer_count);
+    let mut y = 0.0;
+    for layer in &layers {
+        let depth = layer.iter().map(|&v| vertices[v].main).fold(0.0, f32::max);
+        layer_main.push(y + depth / 2.0);
+        y += depth + rank_gap;
+    }
+    let flip = matches!(chart.direction, Direction::BottomUp | Direction::RightLeft);
+    let center_of = |v: usize| {
+        let main = layer_main[vertices[v].layer];
+        let main = if flip { -main } else { main };
+        let cross = vertices[v].position;
+        if horizontal {
+            pos2(main, cross)
+        } else {
+            pos2(cross, main)
+        }
+    };
+
+    let mut shapes = Vec::new();
+    let mut label_shapes = Vec::new();
+    let mut path_index = 0;
+    for edge in &chart.edges {
+        let stroke = Stroke::new(
+            if edge.line == LineStyle::Thick {
+                2.5
+            } else {
+                1.3
+            },
+            theme.line,
+        );
+        let dashed = edge.line == LineStyle::Dotted;
+        let points = if edge.from == edge.to {
+            // A small loop on the side of the node.
+            let center = center_of(edge.from);
+            let half = sizes[edge.from] / 2.0;
+            let start = center + vec2(half.x, -half.y * 0.4);
+            let end = center + vec2(half.x, half.y * 0.4);
+            let bezier = CubicBezierShape::from_points_stroke(
+                [
+                    start,
+                    start + vec2(30.0, -10.0),
+                    end + vec2(30.0, 10.0),
+                    end,
+                ],
+                false,
+                Color32::TRANSPARENT,
+                stroke,
+            );
+            bezier.flatten(Some(0.5))
+        } else {
+            let path = &paths[path_index];
+            let is_reversed = reversed[path_index];
+            path_index += 1;
+            let mut points: Vec<Pos2> = path.iter().map(|&v| center_of(v)).collect();
+            if is_reversed {
+                points.reverse();
+            }
+            let last = points.len() - 1;
+            points[0] = boundary_point(
+                chart.nodes[edge.from].shape,
+                points[0],
+                sizes[edge.from] / 2.0,
+                points[1],
+            );
+            points[last] = boundary_point(
+                chart.nodes[edge.to].shape,
+                points[last],
+                sizes[edge.to] / 2.0,
+                points[last - 1],
+            );
+            points
+        };
+        if points.len() < 2 {
+            continue;
+        }
+        stroke_path(&mut shapes, &points, stroke, dashed);
+        let n = points.len();
+        end_marker(
+            &mut shapes,
+            edge.head,
+            points[n - 1],
+            points[n - 2],
+            theme.line,
+        );
+        end_marker(&mut shapes, edge.tail, points[0], points[1], theme.line);
+        if let Some(label) = &edge.label {
+            let galley = text_galley(fonts, label, theme.text);
+            boxed_label(
+                &mut label_shapes,
+                galley,
+                polyline_midpoint(&points),
+                theme.label_background,
+            );
+        }
+    }
+    for (index, node) in chart.nodes.iter().enumerate() {
+        let rect = Rect::from_center_size(center_of(index), sizes[index]);
+        node_shape(&mut shapes, node.shape, rect, theme);
+        shapes.push(centered_text(galleys[index].clone(), rect.center()));
+    }
+    shapes.extend(label_shapes);
+    Drawing {
+        size: Vec2::ZERO,
+        shapes,
+    }
+}
+
+/// Where the line from the node's center towards `toward` leaves the node.
+fn boundary_point(shape: NodeShape, center: Pos2, half: Vec2, toward: Pos2) -> Pos2 {
+    let d = toward - center;
+    if d.length() < 1e-3 {
+        return center;
+    }
+    let t = match shape {
+        NodeShape::Circle => half.x / d.length(),
+        NodeShape::Diamond => 1.0 / (d.x.abs() / half.x + d.y.abs() / half.y),
+        _ => (half.x / d.x.abs()).min(half.y / d.y.abs()),
+    };
+    center + d * t.min(1.0)
+}
+
+fn polyline_midpoint(points: &[Pos2]) -> Pos2 {
+    let total: f32 = points.windows(2).map(|w| w[0].distance(w[1])).sum();
+    let mut remaining = total / 2.0;
+    for w in points.windows(2) {
+        let length = w[0].distance(w[1]);
+        if remaining <= length && length > 0.0 {
+            return w[0] + (w[1] - w[0]) * (remaining / length);
+        }
+        remaining -= length;
+    }
+    points[0]
+}
+
+fn node_shape(shapes: &mut Vec<Shape>, shape: NodeShape, rect: Rect, theme: &DiagramTheme) {
+    let stroke = Stroke::new(1.3, theme.stroke);
+    let fill = theme.fill;
+    let (c, w, h) = (rect.center(), rect.width(), rect.height());
+    let polygon = |points: Vec<Pos2>| Shape::convex_polygon(points, fill, stroke);
+    match shape {
+        NodeShape::Rect => shapes.push(RectShape::new(rect, 0.0, fill, stroke).into()),
+        NodeShape::Round => shapes.push(RectShape::new(rect, 6.0, fill, stroke).into()),
+        NodeShape::Stadium => shapes.push(RectShape::new(rect, h / 2.0, fill, stroke).into()),
+        NodeShape::Subroutine => {
+            shapes.push(RectShape::new(rect, 0.0, fill, stroke).into());
+            for x in [rect.left() + 8.0, rect.right() - 8.0] {
+                shapes.push(Shape::line_segment(
+                    [pos2(x, rect.top()), pos2(x, rect.bottom())],
+                    stroke,
+                ));
+            }
+        }
+        NodeShape::Circle => shapes.push(
+            CircleShape {
+                center: c,
+                radius: w / 2.0,
+                fill,
+                stroke,
+            }
+            .into(),
+        ),
+        NodeShape::Diamond => shapes.push(polygon(vec![
+            pos2(c.x, rect.top()),
+            pos2(rect.right(), c.y),
+            pos2(c.x, rect.bottom()),
+            pos2(rect.left(), c.y),
+        ])),
+        NodeShape::Hexagon => {
+            let inset = h / 2.0;
+            shapes.push(polygon(vec![
+                pos2(rect.left() + inset, rect.top()),
+                pos2(rect.right() - inset, rect.top()),
+                pos2(rect.right(), c.y),
+                pos2(rect.right() - inset, rect.bottom()),
+                pos2(rect.left() + inset, rect.bottom()),
+                pos2(rect.left(), c.y),
+            ]));
+        }
+        NodeShape::Parallelogram => {
+            let skew = h * 0.4;
+            shapes.push(polygon(vec![
+                pos2(rect.left() + skew, rect.top()),
+                rect.right_top(),
+                pos2(rect.right() - skew, rect.bottom()),
+                rect.left_bottom(),
+            ]));
+        }
+        NodeShape::Asymmetric => {
+            let point = h * 0.4;
+            shapes.push(polygon(vec![
+                rect.left_top(),
+                pos2(rect.right() - point, rect.top()),
+                pos2(rect.right(), c.y),
+                pos2(rect.right() - point, rect.bottom()),
+                rect.left_bottom(),
+            ]));
+        }
+        NodeShape::Cylinder => {
+            let ry = 6.0;
+            let ellipse = |cy: f32, from: f32, to: f32| -> Vec<Pos2> {
+                (0..=24)
+                    .map(|i| {
+                        let a = from + (to - from) * i as f32 / 24.0;
+                        pos2(c.x + w / 2.0 * a.cos(), cy + ry * a.sin())
+                    })
+                    .collect()
+            };
+            let body = Rect::from_min_max(
+                pos2(rect.left(), rect.top() + ry),
+                pos2(rect.right(), rect.bottom() - ry),
+            );
+            shapes.push(Shape::rect_filled(body, 0.0, fill));
+            shapes.push(Shape::convex_polygon(
+                ellipse(rect.bottom() - ry, 0.0, std::f32::consts::TAU),
+                fill,
+                Stroke::NONE,
+            ));
+            shapes.push(Shape::line(
+                ellipse(rect.bottom() - ry, 0.0, std::f32::consts::PI),
+                stroke,
+            ));
+            for x in [rect.left(), rect.right()] {
+                shapes.push(Shape::line_segment(
+                    [pos2(x, body.top()), pos2(x, body.bottom())],
+                    stroke,
+                ));
+            }
+            shapes.push(polygon(ellipse(
+                rect.top() + ry,
+                0.0,
+                std::f32::consts::TAU,
+            )));
+        }
+    }
+}
+
+// --- Sequence diagrams ---
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArrowKind {
+    /// `->` and `-->`
+    Line,
+    /// `->>` and `-->>`
+    Arrow,
+    /// `-x` and `--x`
+    Cross,
+    /// `-)` and `--)`
+    Async,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NotePlacement {
+    LeftOf,
+    RightOf,
+    Over,
+}
+
+enum SequenceItem {
+    Message {
+        from: usize,
+        to: usize,
+        text: String,
+        dashed: bool,
+        arrow: ArrowKind,
+    },
+    Note {
+        placement: NotePlacement,
+        first: usize,
+        last: usize,
+        text: String,
+    },
+    /// `loop`, `alt`, `opt`, `par`, ... with its condition.
+    BlockStart {
+        kind: String,
+        text: String,
+    },
+    /// `else` or `and` inside a block.
+    BlockDivider {
+        text: String,
+    },
+    BlockEnd,
+}
+
+struct Participant {
+    id: String,
+    label: String,
+    actor: bool,
+}
+
+pub struct Sequence {
+    participants: Vec<Participant>,
+    items: Vec<SequenceItem>,
+    autonumber: bool,
+}
+
+/// Message arrows, longest first.
+const SEQUENCE_ARROWS: &[(&str, bool, ArrowKind)] = &[
+    ("-->>", true, ArrowKind::Arrow),
+    ("->>", false, ArrowKind::Arrow),
+    ("--x", true, ArrowKind::Cross),
+    ("-x", false, ArrowKind::Cross),
+    ("--)", true, ArrowKind::Async),
+    ("-)", false, ArrowKind::Async),
+    ("-->", true, ArrowKind::Line),
+    ("->", false, ArrowKind::Line),
+];
+
+fn participant_index(participants: &mut Vec<Participant>, id: &str, actor: bool) -> usize {
+    let id = id.trim();
+    match participants.iter().position(|p| p.id == id) {
+        Some(index) => index,
+        None => {
+            participants.push(Participant {
+                id: id.to_string(),
+                label: id.to_string(),
+                actor,
+            });
+            participants.len() - 1
+        }
+    }
+}
+
+fn parse_sequence<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Sequence, String> {
+    let mut participants = Vec::new();
+    let mut items = Vec::new();
+    let mut autonumber = false;
+    let mut depth = 0usize;
+    for line in lines {
+        let (keyword, rest) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(k, r)| (k, r.trim()));
+        match keyword {
+            "participant" | "actor" => {
+                let (id, label) = match rest.split_once(" as ") {
+                    Some((id, label)) => (id.trim(), label_text(label)),
+                    None => (rest, label_text(rest)),
+                };
+                let index = participant_index(&mut participants, id, keyword == "actor");
+                participants[index].label = label;
+                participants[index].actor = keyword == "actor";
+            }
+            "autonumber" => autonumber = true,
+            "activate" | "deactivate" | "title" | "accTitle" | "accDescr" | "box" => {}
+            "loop" | "alt" | "opt" | "par" | "critical" | "break" | "rect" => {
+                depth += 1;
+                items.push(SequenceItem::BlockStart {
+                    kind: keyword.to_string(),
+                    text: label_text(rest),
+                });
+            }
+            "else" | "and" | "option" => items.push(SequenceItem::BlockDivider {
+                text: label_text(rest),
+            }),
+            "end" => {
+                if depth == 0 {
+                    return Err("'end' without a block".to_string());
+                }
+                depth -= 1;
+                items.push(SequenceItem::BlockEnd);
+            }
+            "Note" | "note" => {
+                let (position, text) = rest
+                    .split_once(':')
+                    .ok_or_else(|| format!("Missing ':' in '{}'", line))?;
+                let position = position.trim();
+                let (placement, targets) = if let Some(t) = position.strip_prefix("left of") {
+                    (NotePlacement::LeftOf, t)
+                } else if let Some(t) = position.strip_prefix("right of") {
+                    (NotePlacement::RightOf, t)
+                } else if let Some(t) = position.strip_prefix("over") {
+                    (NotePlacement::Over, t)
+                } else {
+                    return Err(format!("Unknown note position '{}'", position));
+                };
+                let mut targets = targets
+                    .split(',')
+                    .map(|t| participant_index(&mut participants, t, false));
+                let first = targets
+                    .next()
+                    .ok_or_else(|| "Note without participant".to_string())?;
+                let last = targets.next().unwrap_or(first);
+                items.push(SequenceItem::Note {
+                    placement,
+                    first: first.min(last),
+                    last: first.max(last),
+                    text: label_text(text),
+                });
+            }
+            _ => {
+                let (arrow_at, arrow, dashed, kind) = SEQUENCE_ARROWS
+                    .iter()
+                    .filter_map(|&(arrow, dashed, kind)| {
+                        line.find(arrow).map(|at| (at, arrow, dashed, kind))
+                    })
+                    // The earliest match, and the longest arrow at that spot.
+                    .min_by_key(|&(at, arrow, _, _)| (at, usize::MAX - arrow.len()))
+                    .ok_or_else(|| format!("Unrecognized line '{}'", line))?;
+                let from = &line[..arrow_at];
+                let after = &line[arrow_at + arrow.len()..];
+                let (to, text) = after.split_once(':').unwrap_or((after, ""));
+                // Activation shorthands.
+                let to = to.trim().trim_start_matches(['+', '-']);
+                if from.trim().is_empty() || to.is_empty() {
+                    return Err(format!("Missing participant in '{}'", line));
+                }
+                let from = participant_index(&mut participants, from, false);
+                let to = participant_index(&mut participants, to, false);
+                items.push(SequenceItem::Message {
+                    from,
+                    to,
+                    text: label_text(text),
+                    dashed,
+                    arrow: kind,
+                });
+            }
+        }
+    }
+    if participants.is_empty() {
+        return Err("Sequence diagram has no participants".to_string());
+    }
+    Ok(Sequence {
+        participants,
+        items,
+        autonumber,
+    })
+}
+
+fn layout_sequence(sequence: &Sequence, fonts: &Fonts, theme: &DiagramTheme) -> Drawing {
+    let count = sequence.participants.len();
+    let labels: Vec<Arc<Galley>> = sequence
+        .participants
+        .iter()
+        .map(|p| text_galley(fonts, &p.label, theme.text))
+        .collect();
+    let box_size: Vec<Vec2> = labels
+        .iter()
+        .map(|g| vec2((g.size().x + 24.0).max(80.0), g.size().y + 16.0))
+        .collect();
+    let box_height = box_size.iter().map(|s| s.y).fold(0.0, f32::max);
+    let actor_height = box_height + 30.0;
+    let head_height = if sequence.participants.iter().any(|p| p.actor) {
+        actor_height
+    } else {
+        box_height
+    };
+
+    // Message label text, numbered if requested.
+    let mut number = 0;
+    let message_galleys: Vec<Option<Arc<Galley>>> = sequence
+        .items
+        .iter()
+        .map(|item| match item {
+            SequenceItem::Message { text, .. } => {
+                number += 1;
+                let text = if sequence.autonumber {
+                    format!("{}. {}", number, text)
+                } else {
+                    text.clone()
+                };
+                Some(text_galley(fonts, &text, theme.text))
+            }
+            SequenceItem::Note { text, .. } => Some(text_galley(fonts, text, theme.text)),
+            _ => None,
+        })
+        .collect();
+
+    // Spacing between lifelines: wide enough for boxes and the labels of
+    // messages between them.
+    let mut gaps: Vec<f32> = (0..count.saturating_sub(1))
+        .map(|i| (box_size[i].x + box_size[i + 1].x) / 2.0 + 30.0)
+        .collect();
+    let mut trailing = 0.0f32;
+    for (item, galley) in sequence.items.iter().zip(&message_galleys) {
+        let Some(galley) = galley else { continue };
+        let width = galley.size().x;
+        let (first, last, needed) = match item {
+            SequenceItem::Message { from, to, .. } if from == to => (*from, from + 1, width + 50.0),
+            SequenceItem::Message { from, to, .. } => (*from.min(to), *from.max(to), width + 30.0),
+            SequenceItem::Note {
+                placement: NotePlacement::RightOf,
+                first,
+                ..
+            } => (*first, first + 1, width + 40.0),
+            _ => continue,
+        };
+        if last >= count {
+            trailing = trailing.max(needed);
+            continue;
+        }
+        let current: f32 = gaps[first..last].iter().sum();
+        if current < needed {
+            gaps[last - 1] += needed - current;
+        }
+    }
+    let mut xs = vec![box_size[0].x / 2.0];
+    for gap in &gaps {
+        xs.push(xs.last().unwrap() + gap);
+    }
+    let left = 0.0;
+    let right = xs[count - 1] + box_size[count - 1].x / 2.0 + trailing;
+
+    let mut shapes = Vec::new();
+    let mut y = head_height + 20.0;
+    let line_stroke = Stroke::new(1.3, theme.line);
+    // Open blocks: (kind, text, top y)
+    let mut blocks: Vec<(String, String, f32)> = Vec::new();
+    let mut frames = Vec::new();
+    for (item, galley) in sequence.items.iter().zip(&message_galleys) {
+        match item {
+            SequenceItem::Message {
+                from,
+                to,
+                dashed,
+                arrow,
+                ..
+            } => {
+                let galley = galley.clone().unwrap();
+                let label_height = galley.size().y;
+                let (x1, x2) = (xs[*from], xs[*to]);
+                if from == to {
+                    let top = y + label_height + 4.0;
+                    shapes.push(Shape::galley(
+                        pos2(x1 + 8.0, y),
+                        galley,
+                        Color32::PLACEHOLDER,
+                    ));
+                    let points = vec![
+                        pos2(x1, top),
+                        pos2(x1 + 36.0, top),
+                        pos2(x1 + 36.0, top + 22.0),
+                        pos2(x1, top + 22.0),
+                    ];
+                    stroke_path(&mut shapes, &points, line_stroke, *dashed);
+                    sequence_arrow(&mut shapes, *arrow, points[3], points[2], theme.line);
+                    y = top + 22.0 + 16.0;
+                } else {
+                    shapes.push(centered_text(
+                        galley,
+                        pos2((x1 + x2) / 2.0, y + label_height / 2.0),
+                    ));
+                    let line_y = y + label_height + 6.0;
+                    let (start, end) = (pos2(x1, line_y), pos2(x2, line_y));
+                    stroke_path(&mut shapes, &[start, end], line_stroke, *dashed);
+                    sequence_arrow(&mut shapes, *arrow, end, start, theme.line);
+                    y = line_y + 18.0;
+                }
+            }
+            SequenceItem::Note {
+                placement,
+                first,
+                last,
+                ..
+            } => {
+                let galley = galley.clone().unwrap();
+                let size = galley.size() + vec2(16.0, 10.0);
+                let rect = match placement {
+                    NotePlacement::LeftOf => {
+                        Rect::from_min_size(pos2(xs[*first] - 10.0 - size.x, y), size)
+                    }
+                    NotePlacement::RightOf => Rect::from_min_size(pos2(xs[*first] + 10.0, y), size),
+                    NotePlacement::Over => {
+                        let (a, b) = (xs[*first], xs[*last]);
+                        let width = size.x.max(b - a + 40.0);
+                        Rect::from_center_size(
+                            pos2((a + b) / 2.0, y + size.y / 2.0),
+                            vec2(width, size.y),
+                        )
+                    }
+                };
+                shapes.push(
+                    RectShape::new(rect, 2.0, theme.note_fill, Stroke::new(1.0, theme.stroke))
+                        .into(),
+                );
+                shapes.push(centered_text(galley, rect.center()));
+                y = rect.bottom() + 14.0;
+            }
+            SequenceItem::BlockStart { kind, text } => {
+                blocks.push((kind.clone(), text.clone(), y));
+                y += 30.0;
+            }
+            SequenceItem::BlockDivider { text } => {
+                let inset = blocks.len() as f32 * 6.0;
+                let stroke = Stroke::new(1.0, theme.stroke);
+                shapes.extend(Shape::dashed_line(
+                    &[pos2(left - 20.0 + inset, y), pos2(right + 20.0 - inset, y)],
+                    stroke,
+                    4.0,
+                    3.0,
+                ));
+                if !text.is_empty() {
+                    let galley = text_galley(fonts, &format!("[{}]", text), theme.text);
+                    shapes.push(centered_text(galley, pos2((left + right) / 2.0, y + 10.0)));
+                }
+                y += 28.0;
+            }
+            SequenceItem::BlockEnd => {
+                if let Some((kind, text, top)) = blocks.pop() {
+                    let inset = blocks.len() as f32 * 6.0;
+                    let rect = Rect::from_min_max(
+                        pos2(left - 20.0 + inset, top),
+                        pos2(right + 20.0 - inset, y),
+                    );
+                    frames.push((rect, kind, text));
+                }
+                y += 12.0;
+            }
+        }
+    }
+    let bottom = y;
+
+    // Frames go under the messages.
+    let mut background = Vec::new();
+    for (rect, kind, text) in frames {
+        background.push(Shape::rect_stroke(
+            rect,
+            0.0,
+            Stroke::new(1.0, theme.stroke),
+        ));
+        let tag = text_galley(fonts, &kind, theme.text);
+        let tag_rect = Rect::from_min_size(rect.min, tag.size() + vec2(12.0, 6.0));
+        background.push(Shape::convex_polygon(
+            vec![
+                tag_rect.left_top(),
+                tag_rect.right_top(),
+                pos2(tag_rect.right(), tag_rect.bottom() - 5.0),
+                pos2(tag_rect.right() - 5.0, tag_rect.bottom()),
+                tag_rect.left_bottom(),
+            ],
+            theme.fill,
+            Stroke::new(1.0, theme.stroke),
+        ));
+        background.push(centered_text(tag, tag_rect.center()));
+        if !text.is_empty() {
+            let galley = text_galley(fonts, &format!("[{}]", text), theme.text);
+            background.push(Shape::galley(
+                pos2(tag_rect.right() + 8.0, tag_rect.top() + 3.0),
+                galley,
+                Color32::PLACEHOLDER,
+            ));
+        }
+    }
+    for (index, participant) in sequence.participants.iter().enumerate() {
+        let x = xs[index];
+        background.extend(Shape::dashed_line(
+            &[pos2(x, head_height), pos2(x, bottom)],
+            Stroke::new(1.0, theme.stroke),
+            4.0,
+            4.0,
+        ));
+        for top in [0.0, bottom] {
+            let (galley, size) = (labels[index].clone(), box_size[index]);
+            if participant.actor {
+                stick_figure(&mut background, pos2(x, top), theme);
+                background.push(centered_text(
+                    galley,
+                    pos2(x, top + actor_height - box_height / 2.0),
+                ));
+            } else {
+                let rect =
+                    Rect::from_min_size(pos2(x - size.x / 2.0, top), vec2(size.x, box_height));
+                background.push(
+                    RectShape::new(
+                        rect,
+                        Rounding::same(3.0),
+                        theme.fill,
+                        Stroke::new(1.3, theme.stroke),
+                    )
+                    .into(),
+                );
+                background.push(centered_text(galley, rect.center()));
+            }
+        }
+    }
+    background.extend(shapes);
+    Drawing {
+        size: Vec2::ZERO,
+        shapes: background,
+    }
+}
+
+fn sequence_arrow(shapes: &mut Vec<Shape>, kind: ArrowKind, tip: Pos2, from: Pos2, color: Color32) {
+    match kind {
+        ArrowKind::Line => {}
+        ArrowKind::Arrow => arrow_head(shapes, tip, from, color, true),
+        ArrowKind::Async => arrow_head(shapes, tip, from, color, false),
+        ArrowKind::Cross => end_marker(shapes, Marker::Cross, tip, from, color),
+    }
+}
+
+fn stick_figure(shapes: &mut Vec<Shape>, top: Pos2, theme: &DiagramTheme) {
+    let stroke = Stroke::new(1.5, theme.stroke);
+    let head = top + vec2(0.0, 6.0);
+    shapes.push(Shape::circle_stroke(head, 6.0, stroke));
+    let neck = head + vec2(0.0, 6.0);
+    let hip = neck + vec2(0.0, 12.0);
+    shapes.push(Shape::line_segment([neck, hip], stroke));
+    shapes.push(Shape::line_segment(
+        [neck + vec2(-9.0, 5.0), neck + vec2(9.0, 5.0)],
+        stroke,
+    ));
+    shapes.push(Shape::line(
+        vec![hip + vec2(-8.0, 10.0), hip, hip + vec2(8.0, 10.0)],
+        stroke,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::FontDefinitions;
+
+    fn flowchart(source: &str) -> Flowchart {
+        match parse(source) {
+            Ok(Diagram::Flowchart(chart)) => chart,
+            Ok(_) => panic!("not a flowchart"),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn sequence(source: &str) -> Sequence {
+        match parse(source) {
+            Ok(Diagram::Sequence(sequence)) => sequence,
+            Ok(_) => panic!("not a sequence diagram"),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn error(source: &str) -> String {
+        match parse(source) {
+            Ok(_) => panic!("{:?} parsed", source),
+            Err(e) => e,
+        }
+    }
+
+    /// Edges as `(from label, to label, edge label)`.
+    fn edges(chart: &Flowchart) -> Vec<(&str, &str, Option<&str>)> {
+        chart
+            .edges
+            .iter()
+            .map(|edge| {
+                (
+                    chart.nodes[edge.from].label.as_str(),
+                    chart.nodes[edge.to].label.as_str(),
+                    edge.label.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flowchart_nodes_and_shapes() {
+        let chart = flowchart("graph LR\n  A[Start] --> B{Ok?}\n  B --> C((Done))");
+        assert_eq!(chart.direction, Direction::LeftRight);
+        let shapes: Vec<_> = chart
+            .nodes
+            .iter()
+            .map(|n| (n.label.as_str(), n.shape))
+            .collect();
+        assert_eq!(
+            shapes,
+            [
+                ("Start", NodeShape::Rect),
+                ("Ok?", NodeShape::Diamond),
+                ("Done", NodeShape::Circle)
+            ]
+        );
+        // A quoted label may contain the closing delimiter.
+        let chart = flowchart(r#"flowchart TD; A["a [b] c"]"#);
+        assert_eq!(chart.nodes[0].label, "a [b] c");
+    }
+
+    #[test]
+    fn flowchart_link_labels() {
+        let chart = flowchart(
+            "flowchart TD\n A -->|yes| B\n A -- no --> C\n B -. maybe .-> C\n C == sure ==> D",
+        );
+        assert_eq!(
+            edges(&chart),
+            [
+                ("A", "B", Some("yes")),
+                ("A", "C", Some("no")),
+                ("B", "C", Some("maybe")),
+                ("C", "D", Some("sure"))
+            ]
+        );
+        let lines: Vec<_> = chart.edges.iter().map(|e| e.line).collect();
+        assert_eq!(
+            lines,
+            [
+                LineStyle::Solid,
+                LineStyle::Solid,
+                LineStyle::Dotted,
+                LineStyle::Thick
+            ]
+        );
+    }
+
+    #[test]
+    fn flowchart_chains_groups_and_markers() {
+        let chart = flowchart("graph TD\n A & B --> C --o D\n E <--> F\n G --x H");
+        assert_eq!(
+            edges(&chart),
+            [
+                ("A", "C", None),
+                ("B", "C", None),
+                ("C", "D", None),
+                ("E", "F", None),
+                ("G", "H", None)
+            ]
+        );
+        let markers: Vec<_> = chart.edges.iter().map(|e| (e.tail, e.head)).collect();
+        assert_eq!(markers[2], (Marker::None, Marker::Circle));
+        assert_eq!(markers[3], (Marker::Arrow, Marker::Arrow));
+        assert_eq!(markers[4], (Marker::None, Marker::Cross));
+        // `--oB` links to a node called `oB`.
+        let chart = flowchart("graph TD\n A --oB");
+        assert_eq!(edges(&chart), [("A", "oB", None)]);
+    }
+
+    #[test]
+    fn flowchart_errors() {
+        assert_eq!(error(""), "Empty diagram");
+        assert_eq!(error("graph XY\nA"), "Unknown direction 'XY'");
+        assert_eq!(error("pie\n\"a\": 1"), "Unsupported diagram type 'pie'");
+        assert_eq!(error("graph TD\nA[oops"), "Missing ']' for node 'A'");
+        assert_eq!(error("graph TD\nA -->|x B"), "Missing '|' after link text");
+        assert_eq!(error("graph TD\nA --> B )"), "Unexpected ')'");
+        assert_eq!(
+            error("graph TD\nclassDef x fill:#f00"),
+            "Flowchart has no nodes"
+        );
+    }
+
+    #[test]
+    fn sequence_messages() {
+        let sequence = sequence(
+            "sequenceDiagram\n participant A as Alice\n actor B\n A->>+B: Hi\n B-->>-A: Hello\n loop Every day\n A-)B: ping\n end",
+        );
+        let participants: Vec<_> = sequence
+            .participants
+            .iter()
+            .map(|p| (p.label.as_str(), p.actor))
+            .collect();
+        assert_eq!(participants, [("Alice", false), ("B", true)]);
+        let messages: Vec<_> = sequence
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                SequenceItem::Message {
+                    from,
+                    to,
+                    text,
+                    dashed,
+                    arrow,
+                } => Some((*from, *to, text.as_str(), *dashed, *arrow)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (0, 1, "Hi", false, ArrowKind::Arrow),
+                (1, 0, "Hello", true, ArrowKind::Arrow),
+                (0, 1, "ping", false, ArrowKind::Async)
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_errors() {
+        assert_eq!(
+            error("sequenceDiagram\n A->>B: hi\n end"),
+            "'end' without a block"
+        );
+        assert_eq!(
+            error("sequenceDiagram\n Note A hi"),
+            "Missing ':' in 'Note A hi'"
+        );
+        assert_eq!(
+            error("sequenceDiagram\n Note under A: hi"),
+            "Unknown note position 'under A'"
+        );
+        assert_eq!(
+            error("sequenceDiagram\n A hello B"),
+            "Unrecognized line 'A hello B'"
+        );
+        assert_eq!(
+            error("sequenceDiagram\n ->>B: hi"),
+            "Missing participant in '->>B: hi'"
+        );
+        assert_eq!(
+            error("sequenceDiagram\n autonumber"),
+            "Sequence diagram has no participants"
+        );
+    }
+
+    #[test]
+    fn layout_follows_direction() {
+        let fonts = Fonts::new(1.0, 1024, FontDefinitions::default());
+        let theme = DiagramTheme {
+            text: Color32::WHITE,
+            fill: Color32::DARK_GRAY,
+            stroke: Color32::GRAY,
+            line: Color32::GRAY,
+            label_background: Color32::BLACK,
+            note_fill: Color32::DARK_GRAY,
+        };
+        let size = |source: &str| parse(source).ok().unwrap().layout(&fonts, &theme).size;
+        let down = size("graph TD\n A --> B --> C");
+        let right = size("graph LR\n A --> B --> C");
+        assert!(down.y > down.x);
+        assert!(right.x > right.y);
+    }
+}
@@ -0,0 +1,350 @@
+//! The Ctrl+P quick-open overlay: fuzzy-matches the files in the workspace
+//! and recently opened documents.
+
+use crate::workspace::Workspace;
+use eframe::egui;
+use egui::text::LayoutJob;
+use egui::{Align2, FontId, Key, TextFormat};
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+/// Results listed at most; the rest are reachable by typing more.
+const MAX_RESULTS: usize = 50;
+/// Bonus for the most recent document, decreasing with age.
+const RECENT_BONUS: i32 = 60;
+
+struct Match {
+    path: PathBuf,
+    label: String,
+    /// Char indices of `label` that matched the query.
+    positions: Vec<usize>,
+    recent: bool,
+}
+
+#[derive(Default)]
+pub struct QuickOpen {
+    query: String,
+    selected: usize,
+    matches: Vec<Match>,
+    /// Query and candidate count the matches were computed for.
+    matched: Option<(String, usize)>,
+}
+
+pub enum Outcome {
+    Open(PathBuf),
+    Close,
+}
+
+impl QuickOpen {
+    /// Draws the overlay. Returns what the user chose, if anything.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        workspace: Option<&Workspace>,
+        recent: &[PathBuf],
+    ) -> Option<Outcome> {
+        let candidates = recent.len() + workspace.map_or(0, |w| w.files.len());
+        if self.matched.as_ref() != Some(&(self.query.clone(), candidates)) {
+            self.update_matches(workspace, recent);
+            self.matched = Some((self.query.clone(), candidates));
+        }
+        let (up, down, enter, escape) = ctx.input(|i| {
+            (
+                i.key_pressed(Key::ArrowUp),
+                i.key_pressed(Key::ArrowDown),
+                i.key_pressed(Key::Enter),
+                i.key_pressed(Key::Escape),
+            )
+        });
+        if escape {
+            return Some(Outcome::Close);
+        }
+        if down && self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        if enter {
+            return match self.matches.get(self.selected) {
+                Some(found) => Some(Outcome::Open(found.path.clone())),
+                None => Some(Outcome::Close),
+            };
+        }
+        let mut outcome = None;
+        egui::Window::new("Quick Open")
+            .title_bar(false)
+            .anchor(Align2::CENTER_TOP, egui::vec2(0.0, 48.0))
+            .fixed_size(egui::vec2(520.0, 0.0))
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("Go to file…")
+                        .desired_width(f32::INFINITY),
+                );
+                response.request_focus();
+                if response.changed() {
+                    self.selected = 0;
+                }
+                ui.separator();
+                if self.matches.is_empty() {
+                    let hint = if workspace.is_none() && recent.is_empty() {
+                        "Open a folder or some files first."
+                    } else {
+                        "No matching files."
+                    };
+                    ui.label(egui::RichText::new(hint).weak());
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(360.0)
+                    .show(ui, |ui| {
+                        for (index, found) in self.matches.iter().enumerate() {
+                            let selected = index == self.selected;
+                            let job = match_label(ui, found);
+                            let response = ui.selectable_label(selected, job);
+                            if selected && (up || down) {
+                                response.scroll_to_me(None);
+                            }
+                            if response.clicked() {
+                                outcome = Some(Outcome::Open(found.path.clone()));
+                            }
+                        }
+                    });
+            });
+        outcome
+    }
+
+    fn update_matches(&mut self, workspace: Option<&Workspace>, recent: &[PathBuf]) {
+        let query: Vec<char> = self
+            .query
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect();
+        // Only the best results are kept, so collect scores with borrowed
+        // labels first.
+        let mut matcher = Matcher::default();
+        let mut scored: Vec<(i32, &PathBuf, Cow<str>, bool)> = Vec::new();
+        for (age, path) in recent.iter().enumerate() {
+            let label = match workspace.and_then(|w| path.strip_prefix(&w.root).ok()) {
+                Some(relative) => relative.to_string_lossy().replace('\\', "/"),
+                None => path.display().to_string(),
+            };
+            if let Some(score) = matcher.score(&query, &label) {
+                let bonus = (RECENT_BONUS - 10 * age as i32).max(10);
+                scored.push((score + bonus, path, Cow::Owned(label), true));
+            }
+        }
+        let files = workspace.map_or(&[][..], |w| &w.files[..]);
+        for file in files {
+            if recent.contains(&file.path) {
+                continue;
+            }
+            if let Some(score) = matcher.score(&query, &file.relative) {
+                scored.push((score, &file.path, Cow::Borrowed(&file.relative), false));
+            }
+        }
+        // Best first; ties go to shorter paths.
+        let order = |a: &(i32, &PathBuf, Cow<str>, bool), b: &(i32, &PathBuf, Cow<str>, bool)| {
+            b.0.cmp(&a.0).then(a.2.len().cmp(&b.2.len()))
+        };
+        if scored.len() > MAX_RESULTS {
+            scored.select_nth_unstable_by(MAX_RESULTS, order);
+            scored.truncate(MAX_RESULTS);
+        }
+        scored.sort_by(order);
+        self.matches = scored
+            .into_iter()
+            .map(|(_, path, label, recent)| {
+                matcher.score(&query, &label);
+                Match {
+                    path: path.clone(),
+                    positions: matcher.positions.clone(),
+                    label: label.into_owned(),
+                    recent,
+                }
+            })
+            .collect();
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+}
+
+/// Path with its matched characters highlighted and the file name emphasised.
+fn match_label(ui: &egui::Ui, found: &Match) -> LayoutJob {
+    let visuals = ui.visuals();
+    let font_id = FontId::proportional(14.0);
+    let name_start = found.label.rfind('/').map_or(0, |i| i + 1);
+    let mut job = LayoutJob::default();
+    if found.recent {
+        job.append(
+            "🕘 ",
+            0.0,
+            TextFormat::simple(font_id.clone(), visuals.weak_text_color()),
+        );
+    }
+    let mut positions = found.positions.iter().peekable();
+    for (index, (byte, c)) in found.label.char_indices().enumerate() {
+        let matched = positions.next_if_eq(&&index).is_some();
+        let color = if matched {
+            visuals.hyperlink_color
+        } else if byte >= name_start {
+            visuals.strong_text_color()
+        } else {
+            visuals.weak_text_color()
+        };
+        let mut buffer = [0; 4];
+        job.append(
+            c.encode_utf8(&mut buffer),
+            0.0,
+            TextFormat::simple(font_id.clone(), color),
+        );
+    }
+    job
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '/' | '\\' | '_' | '-' | '.' | ' ')
+}
+
+/// Scores candidates against a query, reusing its buffers between them.
+#[derive(Default)]
+struct Matcher {
+    chars: Vec<char>,
+    /// Char indices that matched in the last successful `score`.
+    positions: Vec<usize>,
+}
+
+impl Matcher {
+    /// Scores `candidate` against a lowercase `query` whose characters must
+    /// all appear in order. Matches at word starts, in runs and in the file
+    /// name score higher.
+    fn score(&mut self, query: &[char], candidate: &str) -> Option<i32> {
+        self.positions.clear();
+        if query.is_empty() {
+            return Some(0);
+        }
+        let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+        // Cheap rejection before collecting anything.
+        let mut remaining = query.iter().peekable();
+        for c in candidate.chars() {
+            if remaining.next_if_eq(&&lower(c)).is_some() && remaining.peek().is_none() {
+                break;
+            }
+        }
+        if remaining.peek().is_some() {
+            return None;
+        }
+        self.chars.clear();
+        self.chars.extend(candidate.chars());
+        let chars = &self.chars;
+        // Match right to left so that the file name is preferred over folders.
+        let mut next = query.len();
+        for index in (0..chars.len()).rev() {
+            if next == 0 {
+                break;
+            }
+            if lower(chars[index]) == query[next - 1] {
+                self.positions.push(index);
+                next -= 1;
+            }
+        }
+        self.positions.reverse();
+        let positions = &self.positions;
+        let name_start = chars
+            .iter()
+            .rposition(|&c| c == '/' || c == '\\')
+            .map_or(0, |i| i + 1);
+        let mut score = 0;
+        for (n, &index) in positions.iter().enumerate() {
+            score += 16;
+            if n > 0 && positions[n - 1] + 1 == index {
+                score += 24;
+            }
+            let word_start = index == 0
+                || is_separator(chars[index - 1])
+                || (chars[index - 1].is_lowercase() && chars[index].is_uppercase());
+            if word_start {
+                score += 20;
+            }
+            if index >= name_start {
+                score += 8;
+            }
+        }
+        let span = positions[positions.len() - 1] - positions[0] + 1;
+        score -= (span - positions.len()) as i32;
+        score -= chars.len() as i32 / 8;
+        Some(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, candidate: &str) -> Option<i32> {
+        let query: Vec<char> = query.chars().collect();
+        Matcher::default().score(&query, candidate)
+    }
+
+    /// The candidates matching `query`, best first.
+    fn rank<'a>(query: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+        let mut scored: Vec<(i32, &str)> = candidates
+            .iter()
+            .filter_map(|&c| score(query, c).map(|s| (s, c)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.len().cmp(&b.1.len())));
+        scored.into_iter().map(|(_, c)| c).collect()
+    }
+
+    #[test]
+    fn matches_in_order() {
+        assert!(score("rdm", "README.md").is_some());
+        assert!(score("mdr", "README.md").is_none());
+        assert!(score("readmes", "README.md").is_none());
+        assert_eq!(score("", "anything.md"), Some(0));
+    }
+
+    #[test]
+    fn records_positions() {
+        let query: Vec<char> = "todo".chars().collect();
+        let mut matcher = Matcher::default();
+        matcher.score(&query, "todo/todo.md").unwrap();
+        // The file name is preferred over the folder.
+        assert_eq!(matcher.positions, [5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn ranking() {
+        assert_eq!(
+            rank("notes", &["notes/todo.md", "archive/notes.md"]),
+            ["archive/notes.md", "notes/todo.md"]
+        );
+        assert_eq!(
+            rank("plan", &["pole_alien.md", "planning.md"]),
+            ["planning.md", "pole_alien.md"]
+        );
+        assert_eq!(
+            rank("wn", &["gown.md", "work_notes.md"]),
+            ["work_notes.md", "gown.md"]
+        );
+        assert_eq!(
+            rank("cl", &["cycle.md", "ChangeLog.md"]),
+            ["ChangeLog.md", "cycle.md"]
+        );
+        // Ties go to shorter paths.
+        assert_eq!(rank("a", &["docs/a.md", "a.md"]), ["a.md", "docs/a.md"]);
+    }
+
+    #[test]
+    fn recent_files_first() {
+        let recent = [PathBuf::from("/b/one.md"), PathBuf::from("/a/one.md")];
+        let mut quick_open = QuickOpen {
+            query: "one".to_string(),
+            ..Default::default()
+        };
+        quick_open.update_matches(None, &recent);
+        let paths: Vec<&PathBuf> = quick_open.matches.iter().map(|m| &m.path).collect();
+        assert_eq!(paths, [&recent[0], &recent[1]]);
+        assert!(quick_open.matches.iter().all(|m| m.recent));
+    }
+}
@@ -0,0 +1,262 @@
+//! Reading positions that survive font and window size changes, the
+//! reading-progress bar, and zoom and column settings.
+
+use eframe::egui;
+use egui::gui_zoom::kb_shortcuts;
+use egui::{Rect, Stroke};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Where a heading was laid out.
+#[derive(Clone, Debug)]
+pub struct HeadingPosition {
+    pub anchor: String,
+    pub title: String,
+    /// Top of the heading, from the top of the document.
+    pub top: f32,
+}
+
+/// The open document's headings, links and height as of the last frame.
+#[derive(Clone, Debug, Default)]
+pub struct Layout {
+    pub headings: Vec<HeadingPosition>,
+    /// Tops of the links, in document order.
+    pub links: Vec<f32>,
+    pub height: f32,
+}
+
+impl Layout {
+    /// Start and end of the section under the heading at `index`, or of the
+    /// text before the first heading.
+    fn section(&self, index: Option<usize>) -> (f32, f32) {
+        let start = index.map_or(0.0, |i| self.headings[i].top);
+        let next = index.map_or(0, |i| i + 1);
+        let end = self.headings.get(next).map_or(self.height, |h| h.top);
+        (start, end)
+    }
+
+    /// The link after (or before) link `current`, wrapping around. With no
+    /// current link, it's the first (or last) one in `view`, the part of the
+    /// document on screen.
+    pub fn step_link(
+        &self,
+        current: Option<usize>,
+        forward: bool,
+        view: Range<f32>,
+    ) -> Option<usize> {
+        let count = self.links.len();
+        if count == 0 {
+            return None;
+        }
+        Some(match current {
+            Some(index) if forward => (index + 1) % count,
+            Some(index) => (index % count + count - 1) % count,
+            None if forward => self
+                .links
+                .iter()
+                .position(|&top| top >= view.start)
+                .unwrap_or(0),
+            None => self
+                .links
+                .iter()
+                .rposition(|&top| top < view.end)
+                .unwrap_or(count - 1),
+        })
+    }
+}
+
+/// A scroll position relative to the section it's in, rather than in pixels.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadingPosition {
+    /// Anchor of the last heading above the top of the view; `None` before
+    /// the first heading.
+    pub heading: Option<String>,
+    /// Which of the headings with that anchor, for documents that repeat one.
+    pub occurrence: usize,
+    pub title: String,
+    /// How far through the section, from its heading to the next one.
+    pub fraction: f32,
+}
+
+impl ReadingPosition {
+    /// The position of a view scrolled down by `offset`.
+    pub fn at(layout: &Layout, offset: f32) -> Self {
+        // A heading scrolled to sits exactly at the top; allow for rounding.
+        let index = layout
+            .headings
+            .iter()
+            .rposition(|heading| heading.top <= offset + 1.0);
+        let (start, end) = layout.section(index);
+        let fraction = if end > start {
+            ((offset - start) / (end - start)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        match index {
+            Some(index) => {
+                let heading = &layout.headings[index];
+                Self {
+                    occurrence: layout.headings[..index]
+                        .iter()
+                        .filter(|h| h.anchor == heading.anchor)
+                        .count(),
+                    heading: Some(heading.anchor.clone()),
+                    title: heading.title.clone(),
+                    fraction,
+                }
+            }
+            None => Self {
+                fraction,
+                ..Self::default()
+            },
+        }
+    }
+
+    /// The scroll offset for this position, if its heading still exists.
+    pub fn offset(&self, layout: &Layout) -> Option<f32> {
+        let index = match &self.heading {
+            Some(anchor) => Some(
+                layout
+                    .headings
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, h)| h.anchor == *anchor)
+                    .nth(self.occurrence)?
+                    .0,
+            ),
+            None => None,
+        };
+        let (start, end) = layout.section(index);
+        Some(start + self.fraction * (end - start))
+    }
+
+    /// Whether there is anything to resume, i.e. it isn't the very top.
+    pub fn is_start(&self) -> bool {
+        self.heading.is_none() && self.fraction < 0.01
+    }
+}
+
+/// Draws a thin bar along the top of `rect` showing how far `offset` is
+/// through a document `height` tall, seen through a view `rect` tall.
+pub fn progress_bar(ui: &egui::Ui, rect: Rect, offset: f32, height: f32) {
+    let scrollable = height - rect.height();
+    if scrollable <= 0.0 {
+        return;
+    }
+    let progress = (offset / scrollable).clamp(0.0, 1.0);
+    let y = rect.top() + 1.0;
+    let stroke = Stroke::new(2.0, ui.visuals().hyperlink_color.gamma_multiply(0.7));
+    let end = rect.left() + rect.width() * progress;
+    ui.painter()
+        .line_segment([egui::pos2(rect.left(), y), egui::pos2(end, y)], stroke);
+}
+
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 3.0;
+const ZOOM_STEP: f32 = 0.1;
+/// Column width used when a maximum is first switched on.
+const DEFAULT_MAX_WIDTH: f32 = 760.0;
+
+/// How the document is laid out for reading.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadingSettings {
+    /// Scales all text, images and spacing, like zooming in a browser.
+    pub zoom: f32,
+    /// Widest the text column gets, centered in the window; `None` fills it.
+    pub max_width: Option<f32>,
+    /// Multiple of the fonts' natural line height, for paragraphs and lists.
+    pub line_height: f32,
+}
+
+impl Default for ReadingSettings {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            max_width: None,
+            line_height: 1.0,
+        }
+    }
+}
+
+impl ReadingSettings {
+    fn set_zoom(&mut self, zoom: f32) {
+        // Round so repeated steps land on whole percentages.
+        self.zoom = (zoom.clamp(MIN_ZOOM, MAX_ZOOM) * 100.0).round() / 100.0;
+    }
+
+    /// Horizontal margin that centers the column in `available` width.
+    pub fn column_margin(&self, available: f32) -> f32 {
+        self.max_width
+            .map_or(0.0, |max| ((available - max) / 2.0).max(0.0))
+    }
+}
+
+/// Applies Ctrl+plus/minus/0 and Ctrl+wheel (or pinch) zooming.
+pub fn handle_zoom_input(ctx: &egui::Context, settings: &mut ReadingSettings) {
+    let (reset, zoom_in, zoom_out, delta) = ctx.input_mut(|i| {
+        (
+            i.consume_shortcut(&kb_shortcuts::ZOOM_RESET),
+            i.consume_shortcut(&kb_shortcuts::ZOOM_IN)
+                || i.consume_shortcut(&kb_shortcuts::ZOOM_IN_SECONDARY),
+            i.consume_shortcut(&kb_shortcuts::ZOOM_OUT),
+            i.zoom_delta(),
+        )
+    });
+    if reset {
+        settings.zoom = 1.0;
+    }
+    if zoom_in {
+        settings.set_zoom(settings.zoom + ZOOM_STEP);
+    }
+    if zoom_out {
+        settings.set_zoom(settings.zoom - ZOOM_STEP);
+    }
+    if delta != 1.0 {
+        settings.set_zoom(settings.zoom * delta);
+    }
+}
+
+/// Menu contents for zoom, column width and line height. Returns true if
+/// anything changed.
+pub fn reading_settings_ui(ui: &mut egui::Ui, settings: &mut ReadingSettings) -> bool {
+    let before = settings.clone();
+    egui::Grid::new("reading_settings_grid")
+        .num_columns(2)
+        .spacing([8.0, 4.0])
+        .show(ui, |ui| {
+            ui.label("Zoom");
+            ui.horizontal(|ui| {
+                if ui.button("➖").on_hover_text("Ctrl+minus").clicked() {
+                    settings.set_zoom(settings.zoom - ZOOM_STEP);
+                }
+                ui.label(format!("{:.0}%", settings.zoom * 100.0));
+                if ui.button("➕").on_hover_text("Ctrl+plus").clicked() {
+                    settings.set_zoom(settings.zoom + ZOOM_STEP);
+                }
+                if ui.button("Reset").on_hover_text("Ctrl+0").clicked() {
+                    settings.zoom = 1.0;
+                }
+            });
+            ui.end_row();
+            let mut limited = settings.max_width.is_some();
+            ui.checkbox(&mut limited, "Max width");
+            let mut width = settings.max_width.unwrap_or(DEFAULT_MAX_WIDTH);
+            ui.add_enabled(
+                limited,
+                egui::Slider::new(&mut width, 400.0..=1600.0)
+                    .step_by(20.0)
+                    .suffix(" pt"),
+            );
+            settings.max_width = limited.then_some(width);
+            ui.end_row();
+            ui.label("Line height");
+            ui.add(egui::Slider::new(&mut settings.line_height, 1.0..=2.0).step_by(0.05));
+            ui.end_row();
+        });
+    if ui.button("Reset to defaults").clicked() {
+        *settings = ReadingSettings::default();
+    }
+    *settings != before
+}
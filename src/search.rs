@@ -0,0 +1,691 @@
+//! Full-text search over the workspace's Markdown files. Each file gets its
+//! own inverted index, so re-indexing a changed file only touches that file.
+//! Queries match words, `prefix*` terms and `"quoted phrases"`.
+
+use crate::workspace::Changes;
+use eframe::egui;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Files bigger than this aren't indexed.
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+/// Snippets listed per file.
+const MAX_SNIPPETS: usize = 3;
+/// Files listed at most; a more specific query finds the rest.
+const MAX_FILES_LISTED: usize = 100;
+/// Characters of context shown around a hit.
+const SNIPPET_CONTEXT: usize = 60;
+
+/// Lowercase words and where they are, by byte offset.
+fn words(text: &str) -> impl Iterator<Item = (usize, String)> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        while chars.next_if(|(_, c)| !c.is_alphanumeric()).is_some() {}
+        let (start, _) = *chars.peek()?;
+        let mut word = String::new();
+        while let Some((_, c)) = chars.next_if(|(_, c)| c.is_alphanumeric()) {
+            word.extend(c.to_lowercase());
+        }
+        Some((start, word))
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Word(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+/// A parsed search query; every part has to match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    parts: Vec<Part>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut rest = query;
+        while let Some(start) = rest.find('"') {
+            parts.extend(Self::parse_words(&rest[..start]));
+            let phrase = &rest[start + 1..];
+            let end = phrase.find('"').unwrap_or(phrase.len());
+            let words: Vec<String> = words(&phrase[..end]).map(|(_, w)| w).collect();
+            match words.len() {
+                0 => {}
+                1 => parts.push(Part::Word(words[0].clone())),
+                _ => parts.push(Part::Phrase(words)),
+            }
+            rest = phrase.get(end + 1..).unwrap_or("");
+        }
+        parts.extend(Self::parse_words(rest));
+        Self { parts }
+    }
+
+    fn parse_words(text: &str) -> Vec<Part> {
+        text.split_whitespace()
+            .flat_map(|token| {
+                let prefix = token.ends_with('*');
+                let mut words: Vec<String> = words(token).map(|(_, w)| w).collect();
+                let last = words.pop();
+                let mut parts: Vec<Part> = words.into_iter().map(Part::Word).collect();
+                parts.extend(last.map(|w| {
+                    if prefix {
+                        Part::Prefix(w)
+                    } else {
+                        Part::Word(w)
+                    }
+                }));
+                parts
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Byte ranges in `text` that the query matches: single words, and whole
+    /// runs of words for phrases.
+    pub fn highlights(&self, text: &str) -> Vec<Range<usize>> {
+        let words: Vec<(Range<usize>, String)> = words(text)
+            .map(|(start, word)| {
+                let len = text[start..]
+                    .find(|c: char| !c.is_alphanumeric())
+                    .unwrap_or(text.len() - start);
+                (start..start + len, word)
+            })
+            .collect();
+        let mut ranges = Vec::new();
+        for part in &self.parts {
+            match part {
+                Part::Word(w) => ranges.extend(
+                    words
+                        .iter()
+                        .filter(|(_, word)| word == w)
+                        .map(|(range, _)| range.clone()),
+                ),
+                Part::Prefix(p) => ranges.extend(
+                    words
+                        .iter()
+                        .filter(|(_, word)| word.starts_with(p.as_str()))
+                        .map(|(range, _)| range.clone()),
+                ),
+                Part::Phrase(phrase) => ranges.extend(
+                    words
+                        .windows(phrase.len())
+                        .filter(|window| window.iter().map(|(_, w)| w).eq(phrase.iter()))
+                        .map(|window| window[0].0.start..window[window.len() - 1].0.end),
+                ),
+            }
+        }
+        // Overlapping matches from different parts are marked once.
+        ranges.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+}
+
+/// A word in an indexed file.
+#[derive(Clone, Copy)]
+struct Occurrence {
+    block: u32,
+    /// Index among the file's words, for phrase matching.
+    position: u32,
+    offset: u32,
+}
+
+struct Doc {
+    modified: Option<SystemTime>,
+    len: u64,
+    source: String,
+    /// Byte ranges of paragraphs, headings and code blocks.
+    blocks: Vec<Range<usize>>,
+    terms: BTreeMap<String, Vec<Occurrence>>,
+}
+
+impl Doc {
+    fn parse(source: String, modified: Option<SystemTime>, len: u64) -> Self {
+        let blocks = split_blocks(&source);
+        let mut terms: BTreeMap<String, Vec<Occurrence>> = BTreeMap::new();
+        let mut block = 0;
+        for (position, (offset, word)) in words(&source).enumerate() {
+            while block + 1 < blocks.len() && blocks[block].end <= offset {
+                block += 1;
+            }
+            terms.entry(word).or_default().push(Occurrence {
+                block: block as u32,
+                position: position as u32,
+                offset: offset as u32,
+            });
+        }
+        Self {
+            modified,
+            len,
+            source,
+            blocks,
+            terms,
+        }
+    }
+
+    /// Occurrences of a part; for phrases, those of the first word.
+    fn find(&self, part: &Part) -> Vec<Occurrence> {
+        match part {
+            Part::Word(word) => self.terms.get(word).cloned().unwrap_or_default(),
+            Part::Prefix(prefix) => self
+                .terms
+                .range(prefix.clone()..)
+                .take_while(|(term, _)| term.starts_with(prefix.as_str()))
+                .flat_map(|(_, occurrences)| occurrences.iter().copied())
+                .collect(),
+            Part::Phrase(words) => {
+                let rest: Vec<&Vec<Occurrence>> = match words[1..]
+                    .iter()
+                    .map(|word| self.terms.get(word))
+                    .collect::<Option<_>>()
+                {
+                    Some(rest) => rest,
+                    None => return Vec::new(),
+                };
+                let Some(first) = self.terms.get(&words[0]) else {
+                    return Vec::new();
+                };
+                first
+                    .iter()
+                    .filter(|start| {
+                        rest.iter().enumerate().all(|(i, occurrences)| {
+                            let position = start.position + i as u32 + 1;
+                            occurrences
+                                .binary_search_by_key(&position, |o| o.position)
+                                .is_ok_and(|found| occurrences[found].block == start.block)
+                        })
+                    })
+                    .copied()
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Splits Markdown source into blocks at blank lines, keeping fenced code
+/// together and headings on their own.
+fn split_blocks(source: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut start = None;
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let line_range = offset..offset + line.len();
+        offset += line.len();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            start.get_or_insert(line_range.start);
+            continue;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            if let Some(start) = start.take() {
+                blocks.push(start..line_range.start);
+            }
+            if !trimmed.is_empty() {
+                blocks.push(line_range);
+            }
+            continue;
+        }
+        start.get_or_insert(line_range.start);
+    }
+    if let Some(start) = start {
+        blocks.push(start..source.len());
+    }
+    blocks
+}
+
+/// A snippet of a matching block.
+pub struct Snippet {
+    pub text: String,
+    /// Byte ranges of `text` to highlight.
+    pub highlights: Vec<Range<usize>>,
+    /// Where the hit is in the file.
+    pub offset: usize,
+}
+
+pub struct FileHit {
+    pub path: PathBuf,
+    pub snippets: Vec<Snippet>,
+    /// Blocks in the file matching the query.
+    pub blocks: usize,
+}
+
+#[derive(Default)]
+struct Index {
+    docs: HashMap<PathBuf, Arc<Doc>>,
+}
+
+/// Files waiting to be indexed.
+#[derive(Default)]
+struct Queue {
+    /// Whether the indexing thread is running. It empties the queue before
+    /// it stops.
+    running: bool,
+    changed: HashSet<PathBuf>,
+    removed: HashSet<PathBuf>,
+}
+
+/// The index of a workspace, updated on a background thread. Updates build a
+/// new index and swap it in, so searches never wait for one.
+#[derive(Default)]
+pub struct SearchIndex {
+    index: Arc<Mutex<Arc<Index>>>,
+    queue: Arc<Mutex<Queue>>,
+    /// Bumped whenever the index changes.
+    generation: Arc<AtomicUsize>,
+}
+
+impl SearchIndex {
+    /// Re-indexes files that were added or changed and drops removed ones,
+    /// in the background.
+    pub fn update(&self, changes: Changes, ctx: &egui::Context) {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        for path in changes.removed {
+            queue.changed.remove(&path);
+            queue.removed.insert(path);
+        }
+        for path in changes.changed {
+            queue.removed.remove(&path);
+            queue.changed.insert(path);
+        }
+        if queue.running {
+            return;
+        }
+        queue.running = true;
+        drop(queue);
+        let index = Arc::clone(&self.index);
+        let queue = Arc::clone(&self.queue);
+        let generation = Arc::clone(&self.generation);
+        let ctx = ctx.clone();
+        let spawned = std::thread::Builder::new()
+            .name("search index".to_string())
+            .spawn(move || loop {
+                let (changed, removed) = {
+                    let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+                    if queue.changed.is_empty() && queue.removed.is_empty() {
+                        queue.running = false;
+                        return;
+                    }
+                    (
+                        std::mem::take(&mut queue.changed),
+                        std::mem::take(&mut queue.removed),
+                    )
+                };
+                let mut docs = index.lock().unwrap_or_else(|e| e.into_inner()).docs.clone();
+                let mut removed = removed
+                    .iter()
+                    .filter(|path| docs.remove(*path).is_some())
+                    .count();
+                let mut updated = 0;
+                for path in changed {
+                    let Ok(metadata) = fs::metadata(&path) else {
+                        removed += usize::from(docs.remove(&path).is_some());
+                        continue;
+                    };
+                    let stamp = (metadata.modified().ok(), metadata.len());
+                    let unchanged = docs
+                        .get(&path)
+                        .is_some_and(|doc| (doc.modified, doc.len) == stamp);
+                    if unchanged || stamp.1 > MAX_FILE_SIZE {
+                        continue;
+                    }
+                    if let Ok(source) = fs::read_to_string(&path) {
+                        docs.insert(path, Arc::new(Doc::parse(source, stamp.0, stamp.1)));
+                        updated += 1;
+                    }
+                }
+                if updated > 0 || removed > 0 {
+                    log::debug!(
+                        "Search index: {} files updated, {} removed.",
+                        updated,
+                        removed
+                    );
+                    *index.lock().unwrap_or_else(|e| e.into_inner()) = Arc::new(Index { docs });
+                    generation.fetch_add(1, Ordering::SeqCst);
+                    ctx.request_repaint();
+                }
+            });
+        if let Err(e) = spawned {
+            log::error!("Failed to start the search indexer: {}", e);
+            self.queue.lock().unwrap_or_else(|e| e.into_inner()).running = false;
+        }
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn is_indexing(&self) -> bool {
+        self.queue.lock().is_ok_and(|queue| queue.running)
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.index.lock().map_or(0, |index| index.docs.len())
+    }
+
+    fn snapshot(&self) -> Arc<Index> {
+        Arc::clone(&self.index.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Files matching every part of the query, best first.
+    pub fn search(&self, query: &Query) -> Vec<FileHit> {
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let index = self.snapshot();
+        let mut hits = Vec::new();
+        for (path, doc) in &index.docs {
+            let found: Vec<Vec<Occurrence>> = query.parts.iter().map(|p| doc.find(p)).collect();
+            if found.iter().any(Vec::is_empty) {
+                continue;
+            }
+            // Blocks ranked by how many parts they contain, then position.
+            let mut blocks: BTreeMap<u32, (usize, usize)> = BTreeMap::new();
+            for occurrences in &found {
+                let mut seen = Vec::new();
+                for occurrence in occurrences {
+                    let entry = blocks
+                        .entry(occurrence.block)
+                        .or_insert((0, occurrence.offset as usize));
+                    if !seen.contains(&occurrence.block) {
+                        seen.push(occurrence.block);
+                        entry.0 += 1;
+                    }
+                    entry.1 = entry.1.min(occurrence.offset as usize);
+                }
+            }
+            let mut ranked: Vec<(u32, usize, usize)> = blocks
+                .into_iter()
+                .map(|(block, (parts, offset))| (block, parts, offset))
+                .collect();
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            let full = ranked.iter().filter(|b| b.1 == query.parts.len()).count();
+            let snippets = ranked
+                .iter()
+                .take(MAX_SNIPPETS)
+                .map(|&(block, _, offset)| {
+                    snippet(&doc.source, &doc.blocks[block as usize], offset, query)
+                })
+                .collect();
+            hits.push(FileHit {
+                path: path.clone(),
+                snippets,
+                blocks: full.max(1),
+            });
+        }
+        hits.sort_by(|a, b| b.blocks.cmp(&a.blocks).then(a.path.cmp(&b.path)));
+        hits
+    }
+}
+
+/// Up to `SNIPPET_CONTEXT` characters either side of the hit at `offset`,
+/// on one line.
+fn snippet(source: &str, block: &Range<usize>, offset: usize, query: &Query) -> Snippet {
+    let text = &source[block.clone()];
+    let hit = offset.saturating_sub(block.start).min(text.len());
+    let start = text[..hit]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(0, |(i, _)| i);
+    let end = text[hit..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT * 2)
+        .map_or(text.len(), |(i, _)| hit + i);
+    let mut snippet: String = text[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < text.len() {
+        snippet.push('…');
+    }
+    Snippet {
+        highlights: query.highlights(&snippet),
+        text: snippet,
+        offset,
+    }
+}
+
+/// Search terms to highlight in an opened document, and the hit to scroll to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Highlight {
+    pub query: Query,
+    /// Byte offset of the hit in the document.
+    pub offset: usize,
+}
+
+/// `path` relative to `root`, for display.
+pub fn display_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// The search side panel: a query box and results grouped by file.
+#[derive(Default)]
+pub struct SearchPanel {
+    pub index: SearchIndex,
+    /// Set to focus the query box on the next frame.
+    pub focus: bool,
+    query: String,
+    results: Vec<FileHit>,
+    /// Query and index generation the results are for.
+    searched: Option<(String, usize)>,
+}
+
+impl SearchPanel {
+    /// Draws the panel. Returns the file and hit that was clicked, if any.
+    pub fn ui(&mut self, ui: &mut egui::Ui, root: &Path) -> Option<(PathBuf, Highlight)> {
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut self.query)
+                .hint_text("Search files…  \"phrase\"  prefix*")
+                .desired_width(f32::INFINITY),
+        );
+        if std::mem::take(&mut self.focus) {
+            response.request_focus();
+        }
+        let generation = self.index.generation();
+        let query = Query::parse(&self.query);
+        if self.searched.as_ref() != Some(&(self.query.clone(), generation)) {
+            self.results = self.index.search(&query);
+            self.searched = Some((self.query.clone(), generation));
+        }
+        ui.horizontal(|ui| {
+            if self.index.is_indexing() {
+                ui.spinner();
+            }
+            let status = match (query.is_empty(), self.results.len()) {
+                (true, _) => format!("{} files indexed", self.index.file_count()),
+                (false, 1) => "1 file".to_string(),
+                (false, n) => format!("{} files", n),
+            };
+            ui.label(egui::RichText::new(status).small().weak());
+        });
+        ui.separator();
+        let mut clicked = None;
+        let visuals = ui.visuals().clone();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for hit in self.results.iter().take(MAX_FILES_LISTED) {
+                let name = display_path(root, &hit.path);
+                let first = hit.snippets.first().map_or(0, |s| s.offset);
+                if ui
+                    .link(egui::RichText::new(name).strong())
+                    .on_hover_text(hit.path.display().to_string())
+                    .clicked()
+                {
+                    clicked = Some((hit.path.clone(), first));
+                }
+                for snippet in &hit.snippets {
+                    let job = snippet_job(&visuals, snippet);
+                    let label = egui::Label::new(job).sense(egui::Sense::click());
+                    if ui
+                        .add(label)
+                        .on_hover_cursor(egui::CursorIcon::PointingHand)
+                        .clicked()
+                    {
+                        clicked = Some((hit.path.clone(), snippet.offset));
+                    }
+                }
+                ui.add_space(6.0);
+            }
+        });
+        clicked.map(|(path, offset)| (path, Highlight { query, offset }))
+    }
+}
+
+fn snippet_job(visuals: &egui::Visuals, snippet: &Snippet) -> egui::text::LayoutJob {
+    let format = egui::TextFormat {
+        font_id: egui::FontId::proportional(12.0),
+        color: visuals.weak_text_color(),
+        ..Default::default()
+    };
+    let mut job = egui::text::LayoutJob::default();
+    let mut end = 0;
+    for range in &snippet.highlights {
+        job.append(&snippet.text[end..range.start], 0.0, format.clone());
+        let mut marked = format.clone();
+        marked.color = visuals.strong_text_color();
+        marked.background = highlight_color(visuals);
+        job.append(&snippet.text[range.clone()], 0.0, marked);
+        end = range.end;
+    }
+    job.append(&snippet.text[end..], 0.0, format);
+    job
+}
+
+/// Background of highlighted search terms.
+pub fn highlight_color(visuals: &egui::Visuals) -> egui::Color32 {
+    visuals.warn_fg_color.gamma_multiply(0.35)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(source: &str) -> Doc {
+        Doc::parse(source.to_string(), None, source.len() as u64)
+    }
+
+    fn phrase(words: &[&str]) -> Part {
+        Part::Phrase(words.iter().map(|w| w.to_string()).collect())
+    }
+
+    fn blocks(source: &str) -> Vec<&str> {
+        split_blocks(source)
+            .into_iter()
+            .map(|range| &source[range])
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Query::parse("\"Hello, World\" Rust* fast").parts,
+            [
+                phrase(&["hello", "world"]),
+                Part::Prefix("rust".to_string()),
+                Part::Word("fast".to_string()),
+            ]
+        );
+        assert_eq!(
+            Query::parse("\"one\" two-three").parts,
+            [
+                Part::Word("one".to_string()),
+                Part::Word("two".to_string()),
+                Part::Word("three".to_string()),
+            ]
+        );
+        // An unterminated quote runs to the end.
+        assert_eq!(
+            Query::parse("a \"b c").parts,
+            [Part::Word("a".to_string()), phrase(&["b", "c"])]
+        );
+        for empty in ["", "   ", "\"\"", "\" \" *", "!?"] {
+            assert!(Query::parse(empty).is_empty(), "{:?}", empty);
+        }
+    }
+
+    #[test]
+    fn highlights() {
+        let text = "Rust lang, rusty lang; lang rust";
+        assert_eq!(Query::parse("\"rust lang\"").highlights(text), vec![0..9]);
+        assert_eq!(
+            Query::parse("rust*").highlights(text),
+            [0..4, 11..16, 28..32]
+        );
+        // The phrase and the word overlap, so they're marked as one range.
+        assert_eq!(
+            Query::parse("\"rust lang\" lang").highlights(text),
+            [0..9, 17..21, 23..27]
+        );
+        assert!(Query::parse("").highlights(text).is_empty());
+        assert!(Query::parse("missing").highlights(text).is_empty());
+    }
+
+    #[test]
+    fn find() {
+        let doc = doc("# Rust lang\n\nUsing rust\nlang here.\n\nRust\n\nlang alone, rusty.\n");
+        let offsets =
+            |part: &Part| -> Vec<u32> { doc.find(part).iter().map(|o| o.offset).collect() };
+        // Phrases match across lines in a block, but not across blocks.
+        assert_eq!(offsets(&phrase(&["rust", "lang"])), [2, 19]);
+        assert_eq!(offsets(&phrase(&["lang", "here"])), [24]);
+        assert!(offsets(&phrase(&["here", "rust"])).is_empty());
+        assert!(offsets(&phrase(&["rust", "missing"])).is_empty());
+        assert_eq!(offsets(&Part::Word("rust".to_string())), [2, 19, 36]);
+        assert_eq!(offsets(&Part::Prefix("rus".to_string())), [2, 19, 36, 54]);
+        assert!(offsets(&Part::Prefix("z".to_string())).is_empty());
+        let blocks: Vec<u32> = doc
+            .find(&Part::Word("lang".to_string()))
+            .iter()
+            .map(|o| o.block)
+            .collect();
+        assert_eq!(blocks, [0, 1, 3]);
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            blocks("# Title\nFirst\nparagraph\n\n\nSecond\n## Sub\n"),
+            ["# Title\n", "First\nparagraph\n", "Second\n", "## Sub\n"]
+        );
+        // Blank lines and headings inside fenced code don't split it.
+        assert_eq!(
+            blocks("Intro\n```\nfn a() {}\n\n# not a heading\n```\nAfter\n\n~~~\nopen"),
+            [
+                "Intro\n```\nfn a() {}\n\n# not a heading\n```\nAfter\n",
+                "~~~\nopen"
+            ]
+        );
+        assert!(blocks("").is_empty());
+        assert!(blocks("\n  \n").is_empty());
+    }
+}
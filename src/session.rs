@@ -0,0 +1,117 @@
+//! Recently opened files and the session that is restored on the next start.
+
+use crate::reading::ReadingPosition;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Documents whose reading position is remembered; the least recently
+/// opened are forgotten first.
+const MAX_DOCUMENTS: usize = 200;
+
+/// An opened document and where it was last read.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionDocument {
+    pub path: PathBuf,
+    pub position: ReadingPosition,
+}
+
+/// What was open when the viewer last closed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub folder: Option<PathBuf>,
+    /// Most recently opened last.
+    pub documents: Vec<SessionDocument>,
+    pub active: Option<PathBuf>,
+}
+
+impl Session {
+    /// Records where `path` was read up to, making it the most recent.
+    pub fn remember(&mut self, path: PathBuf, position: ReadingPosition) {
+        self.documents.retain(|document| document.path != path);
+        self.documents.push(SessionDocument { path, position });
+        if self.documents.len() > MAX_DOCUMENTS {
+            self.documents.remove(0);
+        }
+    }
+
+    /// Where `path` was last read up to.
+    pub fn position(&self, path: &Path) -> Option<&ReadingPosition> {
+        self.documents
+            .iter()
+            .find(|document| document.path == path)
+            .map(|document| &document.position)
+    }
+
+    /// Drops the folder and active document if they no longer exist,
+    /// returning their paths. Remembered positions are kept, since their
+    /// files may come back, e.g. once a drive is mounted again.
+    pub fn remove_missing(&mut self) -> Vec<PathBuf> {
+        let mut missing = Vec::new();
+        if self.folder.as_ref().is_some_and(|folder| !folder.is_dir()) {
+            missing.extend(self.folder.take());
+        }
+        if self.active.as_ref().is_some_and(|active| !active.is_file()) {
+            missing.extend(self.active.take());
+        }
+        missing
+    }
+}
+
+/// Notice listing the files of the last session that could not be restored.
+pub fn missing_notice(missing: &[PathBuf]) -> String {
+    const LISTED: usize = 3;
+    let mut names: Vec<String> = missing
+        .iter()
+        .take(LISTED)
+        .map(|path| {
+            path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            )
+        })
+        .collect();
+    if missing.len() > LISTED {
+        names.push(format!("{} more", missing.len() - LISTED));
+    }
+    format!(
+        "Skipped from the last session (moved or deleted): {}",
+        names.join(", ")
+    )
+}
+
+/// Menu contents listing the recent files. Returns the one picked, if any.
+/// "Clear" empties the list.
+pub fn recent_files_ui(ui: &mut egui::Ui, recent_files: &mut Vec<PathBuf>) -> Option<PathBuf> {
+    let mut picked = None;
+    if recent_files.is_empty() {
+        ui.label(egui::RichText::new("No recent files.").weak());
+    }
+    for path in recent_files.iter() {
+        let name = path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy());
+        if ui
+            .button(name)
+            .on_hover_text(path.display().to_string())
+            .clicked()
+        {
+            picked = Some(path.clone());
+            ui.close_menu();
+        }
+    }
+    ui.separator();
+    if ui
+        .add_enabled(
+            !recent_files.is_empty(),
+            egui::Button::new("🗑 Clear Recent Files"),
+        )
+        .clicked()
+    {
+        recent_files.clear();
+        ui.close_menu();
+    }
+    picked
+}
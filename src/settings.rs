@@ -0,0 +1,663 @@
+//! User preferences, kept in a versioned `config.toml` in the config
+//! directory, and the settings window that edits them.
+
+use crate::autolink::{self, LinkSettings};
+use crate::fonts::{self, FontSettings};
+use crate::highlighting::{self, CodeThemes};
+use crate::keymap::{self, Keymap};
+use crate::reading::{self, ReadingSettings};
+use crate::theme::{self, Appearance, Theme, ThemeChoice, Themes};
+use eframe::egui;
+use pulldown_cmark::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Version of the config file layout. Bump it and add a step to
+/// `MIGRATIONS` whenever a key is renamed or changes meaning.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Steps that each upgrade a config table by one version; the one at index
+/// `n` upgrades version `n`.
+const MIGRATIONS: &[fn(&mut Table)] = &[migrate_v0];
+
+const _: () = assert!(MIGRATIONS.len() == CONFIG_VERSION as usize);
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// Everything the settings window edits.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub appearance: Appearance,
+    pub theme: ThemeChoice,
+    pub code_themes: CodeThemes,
+    pub fonts: FontSettings,
+    pub reading: ReadingSettings,
+    pub links: LinkSettings,
+    pub open_links: LinkPolicy,
+    pub file_watching: FileWatching,
+    pub images: ImageSettings,
+    pub extensions: Extensions,
+}
+
+/// What clicking a link to a web page (or anything that isn't a note) does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkPolicy {
+    /// Open it with the system's default program.
+    #[default]
+    Open,
+    /// Ask before opening it.
+    Ask,
+    /// Only copy the address to the clipboard.
+    Copy,
+}
+
+/// What happens when the open file is changed by another program.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileWatching {
+    Off,
+    /// Say so in the status bar and leave reloading to the user.
+    #[default]
+    Notify,
+    Reload,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageSettings {
+    /// Decode and show images; when off, their alt text is shown instead.
+    pub load: bool,
+    /// Widest an image is drawn, as a fraction of the text column.
+    pub max_width: f32,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        Self {
+            load: true,
+            max_width: 0.8,
+        }
+    }
+}
+
+/// Syntax beyond CommonMark. Front matter is always recognised.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Extensions {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub task_lists: bool,
+    pub math: bool,
+    /// Curly quotes, en and em dashes and ellipses.
+    pub smart_punctuation: bool,
+    /// `[[Note]]` links and `![[Note]]` embeds.
+    pub wiki_links: bool,
+    /// Bare URLs, email addresses and issue references.
+    pub autolinks: bool,
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            task_lists: true,
+            math: true,
+            smart_punctuation: false,
+            wiki_links: true,
+            autolinks: true,
+        }
+    }
+}
+
+impl Extensions {
+    /// Parser options for the enabled extensions.
+    pub fn options(&self) -> Options {
+        let mut options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+            | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+        for (enabled, option) in [
+            (self.tables, Options::ENABLE_TABLES),
+            (self.footnotes, Options::ENABLE_FOOTNOTES),
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.task_lists, Options::ENABLE_TASKLISTS),
+            (self.math, Options::ENABLE_MATH),
+            (self.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+        ] {
+            options.set(option, enabled);
+        }
+        options
+    }
+}
+
+/// Config options given on the command line.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigArgs {
+    /// A config file to use instead of the one in the config directory.
+    pub path: Option<PathBuf>,
+    /// `key=value` pairs from `--set`, with dotted keys for nested tables.
+    pub overrides: Vec<(String, String)>,
+}
+
+/// The config file the settings came from, and whether they may be saved
+/// back to it.
+#[derive(Debug, Default)]
+pub struct Config {
+    path: Option<PathBuf>,
+    /// The settings as last read or written, so unchanged ones aren't
+    /// rewritten.
+    saved: Option<Settings>,
+    /// Why the settings aren't saved, if they aren't.
+    read_only: Option<String>,
+}
+
+impl Config {
+    /// Reads the settings from the config file, upgrading older versions,
+    /// and applies the command-line overrides. Without a config file the
+    /// settings saved by earlier releases, if any, are taken from `storage`.
+    /// Also returns a notice if the file couldn't be used as is.
+    pub fn load(
+        args: &ConfigArgs,
+        storage: Option<&dyn eframe::Storage>,
+    ) -> (Self, Settings, Option<String>) {
+        let mut config = Self {
+            path: args
+                .path
+                .clone()
+                .or_else(|| crate::config_dir().map(|dir| dir.join(CONFIG_FILE))),
+            ..Self::default()
+        };
+        let mut notice = None;
+        // Whether the file is already up to date with the settings read.
+        let mut current = false;
+        let mut table = match &config.path {
+            Some(path) if path.exists() => match read_table(path) {
+                Ok(table) => {
+                    current = true;
+                    table
+                }
+                Err(e) => {
+                    log::error!("{}", e);
+                    notice = Some(format!("{} (using the default settings)", e));
+                    config.read_only = Some("the file has errors".to_string());
+                    Table::new()
+                }
+            },
+            _ => {
+                log::info!("No config file yet; taking settings from the saved state.");
+                let mut table = storage.map(legacy_table).unwrap_or_default();
+                migrate(&mut table, 0);
+                table
+            }
+        };
+        if let Some(version) = table.remove("version") {
+            let version = version.as_integer().unwrap_or_default();
+            if version > i64::from(CONFIG_VERSION) {
+                log::warn!("Config file version {} is newer than this viewer.", version);
+                notice = Some(
+                    "The config file is from a newer version of the viewer; settings it doesn't \
+                     know are ignored and changes won't be saved."
+                        .to_string(),
+                );
+                config.read_only = Some("the file is from a newer version".to_string());
+            } else if version < i64::from(CONFIG_VERSION) {
+                migrate(&mut table, version.max(0) as u32);
+                current = false;
+            }
+        }
+        for (key, value) in &args.overrides {
+            set_key(&mut table, key, parse_value(value));
+        }
+        if !args.overrides.is_empty() {
+            config.read_only = Some("settings are overridden on the command line".to_string());
+        }
+        let settings = match table.try_into::<Settings>() {
+            Ok(settings) => {
+                // Otherwise the file is written on the next save.
+                if current {
+                    config.saved = Some(settings.clone());
+                }
+                settings
+            }
+            Err(e) => {
+                log::error!("Invalid settings: {}", e);
+                notice = Some(format!(
+                    "Invalid settings ({}); using the defaults.",
+                    e.message().trim()
+                ));
+                config.read_only = Some("the settings are invalid".to_string());
+                Settings::default()
+            }
+        };
+        (config, settings, notice)
+    }
+
+    /// Writes `settings` to the config file if they changed.
+    pub fn save(&mut self, settings: &Settings) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if self.read_only.is_some() || self.saved.as_ref() == Some(settings) {
+            return;
+        }
+        let result = Table::try_from(settings)
+            .map_err(|e| e.to_string())
+            .and_then(|table| {
+                let mut file = Table::new();
+                file.insert("version".to_string(), Value::Integer(CONFIG_VERSION.into()));
+                file.extend(table);
+                file.iter_mut().for_each(|(_, value)| tidy_floats(value));
+                toml::to_string_pretty(&file).map_err(|e| e.to_string())
+            })
+            .and_then(|text| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                fs::write(path, text).map_err(|e| e.to_string())
+            });
+        match result {
+            Ok(()) => {
+                log::info!("Saved settings to {}", path.display());
+                self.saved = Some(settings.clone());
+            }
+            Err(e) => log::error!("Failed to save settings to {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Writes the settings' `f32`s as they'd be typed, like `1.1` rather than
+/// `1.100000023841858`.
+fn tidy_floats(value: &mut Value) {
+    match value {
+        Value::Float(float) => *float = (*float as f32).to_string().parse().unwrap_or(*float),
+        Value::Table(table) => table.iter_mut().for_each(|(_, value)| tidy_floats(value)),
+        Value::Array(array) => array.iter_mut().for_each(tidy_floats),
+        _ => {}
+    }
+}
+
+fn read_table(path: &Path) -> Result<Table, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    text.parse::<Table>().map_err(|e| {
+        let message = e.message().trim().replace('\n', ", ");
+        format!("Config error in {}: {}", path.display(), message)
+    })
+}
+
+/// Upgrades `table` from `version` to the current one.
+fn migrate(table: &mut Table, version: u32) {
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::info!("Upgrading settings from version {}.", from);
+        step(table);
+    }
+}
+
+/// Version 0 is the settings as earlier releases kept them with the window
+/// state, gathered by `legacy_table` under their old keys.
+fn migrate_v0(table: &mut Table) {
+    for (old, new) in [
+        ("font_settings", "fonts"),
+        ("link_settings", "links"),
+        ("reading_settings", "reading"),
+    ] {
+        if let Some(value) = table.remove(old) {
+            table.insert(new.to_string(), value);
+        }
+    }
+    // Before the System appearance there was only a dark mode switch.
+    if let Some(dark_mode) = table.remove("dark_mode") {
+        if !table.contains_key("appearance") {
+            let appearance = match dark_mode.as_bool() {
+                Some(false) => Appearance::Light,
+                _ => Appearance::Dark,
+            };
+            if let Ok(value) = Value::try_from(appearance) {
+                table.insert("appearance".to_string(), value);
+            }
+        }
+    }
+}
+
+/// The settings earlier releases saved with the window state, as a
+/// version 0 table.
+fn legacy_table(storage: &dyn eframe::Storage) -> Table {
+    fn copy<T: Serialize + DeserializeOwned>(
+        storage: &dyn eframe::Storage,
+        table: &mut Table,
+        key: &str,
+    ) {
+        let Some(value) = eframe::get_value::<T>(storage, key) else {
+            return;
+        };
+        match Value::try_from(value) {
+            Ok(value) => {
+                table.insert(key.to_string(), value);
+            }
+            Err(e) => log::warn!("Could not carry over the {} setting: {}", key, e),
+        }
+    }
+    let mut table = Table::new();
+    copy::<bool>(storage, &mut table, "dark_mode");
+    copy::<Appearance>(storage, &mut table, "appearance");
+    copy::<ThemeChoice>(storage, &mut table, "theme");
+    copy::<FontSettings>(storage, &mut table, "font_settings");
+    copy::<LinkSettings>(storage, &mut table, "link_settings");
+    copy::<CodeThemes>(storage, &mut table, "code_themes");
+    copy::<ReadingSettings>(storage, &mut table, "reading_settings");
+    table
+}
+
+/// A TOML value from the command line; anything that doesn't parse as one,
+/// like an unquoted word, is taken as a string.
+fn parse_value(text: &str) -> Value {
+    format!("value = {}", text)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(text.to_string()))
+}
+
+/// Sets a dotted `key` like `images.load`, creating tables on the way.
+fn set_key(table: &mut Table, key: &str, value: Value) {
+    let mut parts: Vec<&str> = key.split('.').map(str::trim).collect();
+    let last = parts.pop().unwrap_or_default();
+    let mut table = table;
+    for part in parts {
+        let entry = table
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+        table = entry.as_table_mut().expect("just made a table");
+    }
+    table.insert(last.to_string(), value);
+}
+
+/// The settings window. `theme` is the one in use, to show its paired code
+/// theme. Returns a status message, e.g. after creating a theme file.
+pub fn settings_window(
+    ctx: &egui::Context,
+    open: &mut bool,
+    settings: &mut Settings,
+    config: &Config,
+    themes: &Themes,
+    theme: &Theme,
+    keymap: &Keymap,
+) -> Option<String> {
+    let mut message = None;
+    egui::Window::new("⚙ Settings")
+        .open(open)
+        .default_width(440.0)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::CollapsingHeader::new("Appearance")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Mode");
+                            theme::appearance_ui(ui, &mut settings.appearance);
+                        });
+                        message = theme::theme_ui(ui, &mut settings.theme, themes);
+                        ui.separator();
+                        ui.label("Code theme");
+                        match highlighting::themes() {
+                            Some(set) => {
+                                if let Some(paired) = &theme.code_theme {
+                                    ui.label(format!("The {} theme uses {}.", theme.name, paired));
+                                }
+                                highlighting::code_theme_ui(ui, &mut settings.code_themes, set);
+                            }
+                            None => {
+                                ui.label("Loading themes…");
+                            }
+                        }
+                        ui.separator();
+                        reading::reading_settings_ui(ui, &mut settings.reading);
+                    });
+                egui::CollapsingHeader::new("Fonts").show(ui, |ui| {
+                    if fonts::font_settings_ui(ui, &mut settings.fonts) {
+                        fonts::apply_fonts(ctx, &settings.fonts);
+                    }
+                });
+                egui::CollapsingHeader::new("Links").show(ui, |ui| {
+                    ui.label("Clicking a web link");
+                    for (policy, label) in [
+                        (LinkPolicy::Open, "Opens it in the default browser"),
+                        (LinkPolicy::Ask, "Asks first"),
+                        (LinkPolicy::Copy, "Copies the address"),
+                    ] {
+                        ui.radio_value(&mut settings.open_links, policy, label);
+                    }
+                    ui.separator();
+                    autolink::link_settings_ui(ui, &mut settings.links);
+                });
+                egui::CollapsingHeader::new("Files").show(ui, |ui| {
+                    ui.label("When the open file is changed elsewhere");
+                    for (watching, label) in [
+                        (FileWatching::Notify, "Say so in the status bar"),
+                        (FileWatching::Reload, "Reload it"),
+                        (FileWatching::Off, "Don't check"),
+                    ] {
+                        ui.radio_value(&mut settings.file_watching, watching, label);
+                    }
+                });
+                egui::CollapsingHeader::new("Images").show(ui, |ui| {
+                    ui.checkbox(&mut settings.images.load, "Show images")
+                        .on_hover_text("When off, images are replaced by their description");
+                    ui.add_enabled(
+                        settings.images.load,
+                        egui::Slider::new(&mut settings.images.max_width, 0.2..=1.0)
+                            .text("Max width")
+                            .custom_formatter(|v, _| format!("{:.0}%", v * 100.0)),
+                    );
+                });
+                egui::CollapsingHeader::new("Markdown").show(ui, |ui| {
+                    let extensions = &mut settings.extensions;
+                    for (enabled, label) in [
+                        (&mut extensions.tables, "Tables"),
+                        (&mut extensions.footnotes, "Footnotes"),
+                        (&mut extensions.strikethrough, "Strikethrough"),
+                        (&mut extensions.task_lists, "Task lists"),
+                        (&mut extensions.math, "Math between $ signs"),
+                        (&mut extensions.smart_punctuation, "Smart quotes and dashes"),
+                        (&mut extensions.wiki_links, "Wiki links and embeds"),
+                        (&mut extensions.autolinks, "Links for bare URLs and #123"),
+                    ] {
+                        ui.checkbox(enabled, label);
+                    }
+                });
+                egui::CollapsingHeader::new("Keyboard").show(ui, |ui| {
+                    if let Some(edited) = keymap::keymap_ui(ui, keymap) {
+                        message = Some(edited);
+                    }
+                });
+                ui.separator();
+                if let Some(path) = &config.path {
+                    ui.label(
+                        egui::RichText::new(format!("Saved to {}", path.display()))
+                            .weak()
+                            .small(),
+                    );
+                }
+                if let Some(reason) = &config.read_only {
+                    ui.label(
+                        egui::RichText::new(format!("Changes aren't saved: {}.", reason))
+                            .color(ui.visuals().warn_fg_color)
+                            .small(),
+                    );
+                }
+                if ui.button("Reset all to defaults").clicked() {
+                    *settings = Settings::default();
+                    fonts::apply_fonts(ctx, &settings.fonts);
+                }
+            });
+        });
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Window state as earlier releases saved it.
+    #[derive(Default)]
+    struct Storage(HashMap<String, String>);
+
+    impl eframe::Storage for Storage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_string(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    /// A config file path of its own for each test.
+    fn config_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("settings-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.toml", name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn args(path: &Path, overrides: &[(&str, &str)]) -> ConfigArgs {
+        ConfigArgs {
+            path: Some(path.to_path_buf()),
+            overrides: overrides
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn migrates_legacy_keys() {
+        let mut table: Table = r#"
+            dark_mode = false
+            font_settings = { size = 16.0 }
+            link_settings = { repository = "rust-lang/rust" }
+            reading_settings = { zoom = 1.5 }
+            code_themes = { dark = "x" }
+        "#
+        .parse()
+        .unwrap();
+        migrate_v0(&mut table);
+        let mut keys: Vec<&str> = table.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            ["appearance", "code_themes", "fonts", "links", "reading"]
+        );
+        assert_eq!(table["appearance"].as_str(), Some("Light"));
+        assert_eq!(table["reading"]["zoom"].as_float(), Some(1.5));
+
+        let mut table: Table = "dark_mode = true".parse().unwrap();
+        migrate_v0(&mut table);
+        assert_eq!(table["appearance"].as_str(), Some("Dark"));
+        // An appearance saved by a later release wins over the old switch.
+        let mut table: Table = "dark_mode = true\nappearance = \"System\"".parse().unwrap();
+        migrate_v0(&mut table);
+        assert_eq!(table["appearance"].as_str(), Some("System"));
+    }
+
+    #[test]
+    fn loads_legacy_state_without_a_file() {
+        let mut storage = Storage::default();
+        eframe::set_value(&mut storage, "dark_mode", &false);
+        let reading = ReadingSettings {
+            zoom: 1.25,
+            ..Default::default()
+        };
+        eframe::set_value(&mut storage, "reading_settings", &reading);
+        let path = config_path("legacy");
+        let (mut config, settings, notice) = Config::load(&args(&path, &[]), Some(&storage));
+        assert_eq!(notice, None);
+        assert_eq!(settings.appearance, Appearance::Light);
+        assert_eq!(settings.reading, reading);
+        // Carried over settings go to a new file on the next save.
+        config.save(&settings);
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(&format!("version = {}", CONFIG_VERSION)));
+        assert!(text.contains("zoom = 1.25"));
+    }
+
+    #[test]
+    fn upgrades_old_files() {
+        let path = config_path("old");
+        fs::write(&path, "version = 0\nreading_settings = { zoom = 2.0 }\n").unwrap();
+        let (_, settings, notice) = Config::load(&args(&path, &[]), None);
+        assert_eq!(notice, None);
+        assert_eq!(settings.reading.zoom, 2.0);
+
+        fs::write(&path, "version = 99\n").unwrap();
+        let (mut config, settings, notice) = Config::load(&args(&path, &[]), None);
+        assert!(notice.unwrap().contains("newer version"));
+        // Files from newer versions are left alone.
+        config.save(&settings);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "version = 99\n");
+    }
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(parse_value("true"), Value::Boolean(true));
+        assert_eq!(parse_value("1.5"), Value::Float(1.5));
+        assert_eq!(parse_value("3"), Value::Integer(3));
+        assert_eq!(parse_value("\"a b\""), Value::String("a b".to_string()));
+        // Anything else is a string, so quotes can be left out.
+        assert_eq!(parse_value("Light"), Value::String("Light".to_string()));
+        assert_eq!(parse_value("a = b"), Value::String("a = b".to_string()));
+        assert_eq!(
+            parse_value("[1, 2]"),
+            Value::Array(vec![Value::Integer(1), Value::Integer(2)])
+        );
+    }
+
+    #[test]
+    fn sets_dotted_keys() {
+        let mut table: Table = "images = { max_width = 0.5 }\nreading = 1".parse().unwrap();
+        set_key(&mut table, "images.load", Value::Boolean(false));
+        set_key(&mut table, " reading . zoom ", Value::Float(2.0));
+        set_key(&mut table, "appearance", Value::String("Dark".to_string()));
+        let expected: Table = r#"
+            appearance = "Dark"
+            images = { max_width = 0.5, load = false }
+            reading = { zoom = 2.0 }
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn overrides() {
+        let path = config_path("overrides");
+        fs::write(&path, "version = 1\nappearance = \"Dark\"\n").unwrap();
+        let overrides = [("images.load", "false"), ("appearance", "Light")];
+        let (mut config, settings, notice) = Config::load(&args(&path, &overrides), None);
+        assert_eq!(notice, None);
+        assert!(!settings.images.load);
+        assert_eq!(settings.appearance, Appearance::Light);
+        // Overridden settings aren't saved.
+        config.save(&Settings::default());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "version = 1\nappearance = \"Dark\"\n"
+        );
+
+        let overrides = [("appearance", "Sepia")];
+        let (_, settings, notice) = Config::load(&args(&path, &overrides), None);
+        assert!(notice.unwrap().starts_with("Invalid settings"));
+        assert_eq!(settings, Settings::default());
+    }
+}
@@ -0,0 +1,582 @@
+//! App themes: colours, heading styles and spacing for the UI and the
+//! document, loaded from built-in definitions and `.toml` files in the
+//! config directory, which are reloaded when they change.
+
+use eframe::egui::{self, Color32, FontId};
+use pulldown_cmark::HeadingLevel;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::SystemTime;
+
+/// The built-in themes. The first doubles as the documented template for
+/// theme files.
+const BUILT_IN: &[&str] = &[
+    r##"# Theme files are TOML. Any key left out is taken from the theme named by
+# `extends`, or from "Dark" or "Light" depending on `dark`.
+name = "Dark"
+dark = true
+# extends = "Nord"
+# Syntax theme to use for code blocks with this theme, overriding the one
+# picked in the Code Theme menu. See that menu for the names.
+# code_theme = "base16-ocean.dark"
+
+[colors]
+background = "#282837"
+# Callouts, front matter and other boxes.
+surface = "#323241"
+text = "#f0f0f5"
+link = "#5aaaff"
+quote_bar = "#f0f0f5"
+code_background = "#404040"
+table_stroke = "#3c3c3c"
+
+[headings]
+# Font sizes for levels 1 to 6.
+sizes = [30.0, 24.0, 20.0, 18.0, 16.0, 14.0]
+# Colours for levels 1 to 6; levels without one use the text colour.
+colors = []
+
+[spacing]
+# After paragraphs.
+paragraph = 4.0
+# After code blocks, block quotes, lists and tables.
+block = 6.0
+# Above and below headings of levels 1 to 6.
+heading_before = [16.0, 12.0, 10.0, 8.0, 8.0, 8.0]
+heading_after = [8.0, 6.0, 5.0, 4.0, 4.0, 4.0]
+"##,
+    r##"name = "Light"
+extends = "Dark"
+dark = false
+
+[colors]
+background = "#ffffff"
+surface = "#f7f7f7"
+text = "#3c3c3c"
+link = "#009bff"
+quote_bar = "#a0a0a0"
+code_background = "#f5f5f5"
+table_stroke = "#d0d0d0"
+"##,
+    r##"name = "Sepia"
+extends = "Light"
+code_theme = "InspiredGitHub"
+
+[colors]
+background = "#f4ecd8"
+surface = "#ebe0c6"
+text = "#433422"
+link = "#8a4b08"
+quote_bar = "#b49a6e"
+code_background = "#ebe0c6"
+table_stroke = "#cbb994"
+
+[headings]
+colors = ["#5b3a1a", "#5b3a1a"]
+"##,
+    r##"name = "Solarized Dark"
+extends = "Dark"
+code_theme = "Solarized (dark)"
+
+[colors]
+background = "#002b36"
+surface = "#073642"
+text = "#93a1a1"
+link = "#268bd2"
+quote_bar = "#586e75"
+code_background = "#073642"
+table_stroke = "#586e75"
+
+[headings]
+colors = ["#b58900", "#cb4b16", "#d33682"]
+"##,
+    r##"name = "Solarized Light"
+extends = "Light"
+code_theme = "Solarized (light)"
+
+[colors]
+background = "#fdf6e3"
+surface = "#eee8d5"
+text = "#586e75"
+link = "#268bd2"
+quote_bar = "#93a1a1"
+code_background = "#eee8d5"
+table_stroke = "#93a1a1"
+
+[headings]
+colors = ["#b58900", "#cb4b16", "#d33682"]
+"##,
+    r##"name = "Nord"
+extends = "Dark"
+code_theme = "base16-eighties.dark"
+
+[colors]
+background = "#2e3440"
+surface = "#3b4252"
+text = "#d8dee9"
+link = "#88c0d0"
+quote_bar = "#4c566a"
+code_background = "#3b4252"
+table_stroke = "#4c566a"
+
+[headings]
+colors = ["#8fbcbb", "#88c0d0", "#81a1c1"]
+"##,
+];
+
+fn themes_dir() -> Option<PathBuf> {
+    crate::config_dir().map(|dir| dir.join("themes"))
+}
+
+/// A colour written as `#rrggbb` or `#rrggbbaa`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color(pub Color32);
+
+impl Color {
+    fn parse(text: &str) -> Option<Self> {
+        let hex = text.strip_prefix('#')?;
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        match hex.len() {
+            6 => Some(Self(Color32::from_rgb(
+                channel(0)?,
+                channel(2)?,
+                channel(4)?,
+            ))),
+            8 => Some(Self(Color32::from_rgba_unmultiplied(
+                channel(0)?,
+                channel(2)?,
+                channel(4)?,
+                channel(6)?,
+            ))),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Self::parse(&text).ok_or_else(|| {
+            serde::de::Error::custom(format!("expected #rrggbb or #rrggbbaa, got {:?}", text))
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Colors {
+    pub background: Color,
+    pub surface: Color,
+    pub text: Color,
+    pub link: Color,
+    pub quote_bar: Color,
+    pub code_background: Color,
+    pub table_stroke: Color,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Headings {
+    pub sizes: [f32; 6],
+    #[serde(default)]
+    pub colors: Vec<Color>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Spacing {
+    pub paragraph: f32,
+    pub block: f32,
+    pub heading_before: [f32; 6],
+    pub heading_after: [f32; 6],
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub dark: bool,
+    pub code_theme: Option<String>,
+    pub colors: Colors,
+    pub headings: Headings,
+    pub spacing: Spacing,
+    /// The file it was loaded from; `None` for built-in themes.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+fn level_index(level: HeadingLevel) -> usize {
+    level as usize - 1
+}
+
+impl Theme {
+    /// The egui visuals for the whole app.
+    pub fn visuals(&self) -> egui::Visuals {
+        let mut v = if self.dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        let colors = &self.colors;
+        v.window_fill = colors.background.0;
+        v.panel_fill = colors.background.0;
+        v.extreme_bg_color = if self.dark {
+            v.extreme_bg_color
+        } else {
+            colors.background.0
+        };
+        v.faint_bg_color = colors.surface.0;
+        v.code_bg_color = colors.code_background.0;
+        v.hyperlink_color = colors.link.0;
+        v.widgets.noninteractive.bg_fill = colors.background.0;
+        v.widgets.noninteractive.fg_stroke.color = colors.text.0;
+        v.widgets.noninteractive.bg_stroke.color = colors.table_stroke.0;
+        v.override_text_color = Some(colors.text.0);
+        v
+    }
+
+    pub fn heading_font_id(&self, level: HeadingLevel) -> FontId {
+        FontId::new(
+            self.headings.sizes[level_index(level)],
+            crate::fonts::heading_family(),
+        )
+    }
+
+    /// `None` means the text colour.
+    pub fn heading_color(&self, level: HeadingLevel) -> Option<Color32> {
+        self.headings.colors.get(level_index(level)).map(|c| c.0)
+    }
+
+    pub fn heading_spacing(&self, level: HeadingLevel, before: bool) -> f32 {
+        let spacing = if before {
+            &self.spacing.heading_before
+        } else {
+            &self.spacing.heading_after
+        };
+        spacing[level_index(level)]
+    }
+}
+
+/// Recursively overlays `overrides` onto `base`.
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge(base, overrides)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Parses a theme definition on top of the theme it extends.
+fn parse(text: &str, themes: &[(String, toml::Table)]) -> Result<(String, toml::Table), String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    let name = match table.get("name") {
+        Some(toml::Value::String(name)) => name.clone(),
+        _ => return Err("missing `name`".to_string()),
+    };
+    let base_name = match (table.get("extends"), table.get("dark")) {
+        (Some(toml::Value::String(base)), _) => Some(base.as_str()),
+        (_, Some(toml::Value::Boolean(false))) => Some("Light"),
+        _ => Some("Dark").filter(|_| !themes.is_empty()),
+    };
+    let mut merged = match base_name {
+        Some(base_name) => themes
+            .iter()
+            .find(|(name, _)| name == base_name)
+            .map(|(_, table)| table.clone())
+            .ok_or_else(|| format!("unknown theme to extend: {}", base_name))?,
+        None => toml::Table::new(),
+    };
+    // The code theme pairing belongs to the theme that sets it.
+    merged.remove("code_theme");
+    merged.remove("extends");
+    merge(&mut merged, table);
+    Ok((name, merged))
+}
+
+fn to_theme(table: toml::Table) -> Result<Theme, String> {
+    toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| e.to_string())
+}
+
+/// Every `.toml` file in `dir` and when it was last modified, sorted.
+fn theme_files(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|e| e == "toml"))
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// The built-in themes and the user's.
+pub struct Themes {
+    pub themes: Vec<Theme>,
+    /// Theme files as of the last load, to notice edits.
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    loaded: bool,
+    /// Sent to when the theme files change.
+    changes: Option<Receiver<()>>,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Self {
+            themes: Self::built_in(),
+            files: Vec::new(),
+            loaded: false,
+            changes: None,
+        }
+    }
+}
+
+impl Themes {
+    fn built_in_tables() -> Vec<(String, toml::Table)> {
+        let mut tables = Vec::new();
+        for text in BUILT_IN {
+            let parsed = parse(text, &tables).expect("built-in theme parses");
+            tables.push(parsed);
+        }
+        tables
+    }
+
+    fn built_in() -> Vec<Theme> {
+        Self::built_in_tables()
+            .into_iter()
+            .map(|(_, table)| to_theme(table).expect("built-in theme is complete"))
+            .collect()
+    }
+
+    /// Built-in themes followed by those in the themes directory. Returns
+    /// the errors of files that failed to load.
+    fn load(&mut self) -> Vec<String> {
+        let mut tables = Self::built_in_tables();
+        let mut themes = Self::built_in();
+        let mut errors = Vec::new();
+        let files = themes_dir().map_or_else(Vec::new, |dir| theme_files(&dir));
+        for (path, _) in &files {
+            let loaded = fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| parse(&text, &tables))
+                .and_then(|(name, table)| {
+                    let theme = to_theme(table.clone())?;
+                    Ok((name, table, theme))
+                });
+            match loaded {
+                Ok((name, table, mut theme)) => {
+                    log::info!("Loaded theme '{}' from {}.", name, path.display());
+                    theme.path = Some(path.clone());
+                    // A file may replace a built-in theme of the same name.
+                    themes.retain(|t| t.name != name);
+                    tables.retain(|(existing, _)| *existing != name);
+                    themes.push(theme);
+                    tables.push((name, table));
+                }
+                Err(e) => {
+                    log::error!("Failed to load theme {}: {}", path.display(), e);
+                    let file = path.file_name().unwrap_or_default().to_string_lossy();
+                    errors.push(format!("{}: {}", file, e));
+                }
+            }
+        }
+        self.themes = themes;
+        self.files = files;
+        errors
+    }
+
+    /// Loads the theme files on the first call and reloads them whenever one
+    /// is added, removed or saved. Returns a status message if they were
+    /// reloaded.
+    pub fn poll(&mut self, ctx: &egui::Context) -> Option<String> {
+        let first = !self.loaded;
+        let changed = self
+            .changes
+            .as_ref()
+            .is_some_and(|changes| changes.try_iter().count() > 0);
+        if !first && !changed {
+            return None;
+        }
+        self.loaded = true;
+        let errors = self.load();
+        if first {
+            if let Some(dir) = themes_dir() {
+                let files = self.files.clone();
+                self.changes =
+                    crate::watch::changes("theme watch", ctx, files, move || theme_files(&dir));
+            }
+        }
+        if !errors.is_empty() {
+            Some(format!("Theme error in {}", errors.join("; ")))
+        } else if first {
+            None
+        } else {
+            Some("Themes reloaded.".to_string())
+        }
+    }
+
+    /// The theme called `name`, or the default for the mode if it's gone.
+    pub fn get(&self, name: &str, dark_mode: bool) -> &Theme {
+        self.themes
+            .iter()
+            .find(|theme| theme.name == name)
+            .or_else(|| self.themes.iter().find(|theme| theme.dark == dark_mode))
+            .unwrap_or(&self.themes[0])
+    }
+}
+
+/// Whether to use the dark or light theme.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Appearance {
+    /// Follow the operating system, switching when it does.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Appearance {
+    const ALL: [Self; 3] = [Self::System, Self::Light, Self::Dark];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::System => "💻 System",
+            Self::Light => "☀ Light",
+            Self::Dark => "🌙 Dark",
+        }
+    }
+
+    /// Whether dark mode is in effect, given the OS appearance if known.
+    /// Dark is assumed when the OS doesn't say.
+    pub fn is_dark(self, system: Option<eframe::Theme>) -> bool {
+        match self {
+            Self::System => system != Some(eframe::Theme::Light),
+            Self::Light => false,
+            Self::Dark => true,
+        }
+    }
+}
+
+/// Picker for the appearance, for the top bar.
+pub fn appearance_ui(ui: &mut egui::Ui, appearance: &mut Appearance) {
+    egui::ComboBox::from_id_source("appearance")
+        .selected_text(appearance.label())
+        .width(90.0)
+        .show_ui(ui, |ui| {
+            for option in Appearance::ALL {
+                ui.selectable_value(appearance, option, option.label());
+            }
+        })
+        .response
+        .on_hover_text("Dark or light theme, or follow the system");
+}
+
+/// Themes picked for dark and light mode.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeChoice {
+    pub dark: String,
+    pub light: String,
+}
+
+impl Default for ThemeChoice {
+    fn default() -> Self {
+        Self {
+            dark: "Dark".to_string(),
+            light: "Light".to_string(),
+        }
+    }
+}
+
+impl ThemeChoice {
+    pub fn get<'t>(&self, themes: &'t Themes, dark_mode: bool) -> &'t Theme {
+        if dark_mode {
+            themes.get(&self.dark, true)
+        } else {
+            themes.get(&self.light, false)
+        }
+    }
+}
+
+/// Menu contents for picking the themes. Returns a status message if a new
+/// theme file was created.
+pub fn theme_ui(ui: &mut egui::Ui, choice: &mut ThemeChoice, themes: &Themes) -> Option<String> {
+    for (label, selected, dark) in [
+        ("Dark mode", &mut choice.dark, true),
+        ("Light mode", &mut choice.light, false),
+    ] {
+        egui::ComboBox::from_label(label)
+            .selected_text(selected.as_str())
+            .width(200.0)
+            .show_ui(ui, |ui| {
+                // Themes made for the mode first.
+                let (matching, other): (Vec<&Theme>, Vec<&Theme>) =
+                    themes.themes.iter().partition(|theme| theme.dark == dark);
+                for theme in matching.into_iter().chain(other) {
+                    let label = match &theme.path {
+                        Some(_) => format!("{} (file)", theme.name),
+                        None => theme.name.clone(),
+                    };
+                    ui.selectable_value(selected, theme.name.clone(), label);
+                }
+            });
+    }
+    let dir = themes_dir()?;
+    ui.separator();
+    let mut message = None;
+    if ui
+        .button("📄 New Theme File")
+        .on_hover_text("Write a documented copy of the Dark theme to edit")
+        .clicked()
+    {
+        message = Some(match create_theme_file(&dir) {
+            Ok(path) => format!("Created {}. Edits apply when it's saved.", path.display()),
+            Err(e) => {
+                log::error!("Failed to create a theme file: {}", e);
+                format!("Failed to create a theme file: {}", e)
+            }
+        });
+        ui.close_menu();
+    }
+    ui.set_max_width(320.0);
+    ui.label(
+        egui::RichText::new(format!(
+            "Theme files (.toml) in {} are reloaded when saved.",
+            dir.display()
+        ))
+        .small()
+        .weak(),
+    );
+    message
+}
+
+/// Writes the template to a new file in `dir`, named so it doesn't clash.
+fn create_theme_file(dir: &Path) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let mut n = 1;
+    let (path, name) = loop {
+        let name = if n == 1 {
+            "My Theme".to_string()
+        } else {
+            format!("My Theme {}", n)
+        };
+        let path = dir.join(format!("{}.toml", name.to_lowercase().replace(' ', "-")));
+        if !path.exists() {
+            break (path, name);
+        }
+        n += 1;
+    };
+    let template = BUILT_IN[0].replacen("name = \"Dark\"", &format!("name = {:?}", name), 1);
+    fs::write(&path, template)?;
+    Ok(path)
+}
@@ -0,0 +1,45 @@
+//! Checks config files for edits on a background thread, so the UI is only
+//! woken when one has changed.
+
+use eframe::egui;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// How often the files are checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Calls `stamp` every second on a thread called `name`, sending on the
+/// returned channel and waking the UI whenever the result differs from the
+/// last one, starting from `loaded`. The thread stops once the receiver is
+/// dropped and the stamp changes again. `None` if the thread can't start.
+pub fn changes<T, F>(name: &str, ctx: &egui::Context, loaded: T, stamp: F) -> Option<Receiver<()>>
+where
+    T: PartialEq + Send + 'static,
+    F: Fn() -> T + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let ctx = ctx.clone();
+    let spawned = std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let mut last = loaded;
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let current = stamp();
+                if current != last {
+                    last = current;
+                    if sender.send(()).is_err() {
+                        return;
+                    }
+                    ctx.request_repaint();
+                }
+            }
+        });
+    match spawned {
+        Ok(_) => Some(receiver),
+        Err(e) => {
+            log::error!("Failed to start the {} thread: {}", name, e);
+            None
+        }
+    }
+}
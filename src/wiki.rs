@@ -0,0 +1,571 @@
+//! Obsidian-style `[[wiki links]]` and `![[embeds]]`, resolved by name across
+//! the folder containing the open note, and the backlinks pointing at a note.
+
+use crate::workspace::Changes;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+/// Stop indexing very large folders, e.g. a note opened from the home directory.
+const MAX_FILES: usize = 10_000;
+/// Notes bigger than this aren't read for backlinks or embedding.
+const MAX_NOTE_SIZE: u64 = 2 * 1024 * 1024;
+
+const NOTE_EXTENSIONS: &[&str] = &["md", "markdown"];
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "webp", "svg"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WikiLink {
+    /// Note or file name; empty for a heading in the same note (`[[#Heading]]`).
+    pub target: String,
+    pub heading: Option<String>,
+    pub alias: Option<String>,
+    /// `![[...]]` transcludes the target instead of linking to it.
+    pub embed: bool,
+}
+
+impl WikiLink {
+    fn parse(inner: &str, embed: bool) -> Self {
+        let (target, alias) = match inner.split_once('|') {
+            Some((target, alias)) => (target, Some(alias.trim().to_string())),
+            None => (inner, None),
+        };
+        let (target, heading) = match target.split_once('#') {
+            Some((target, heading)) => (target, Some(heading.trim().to_string())),
+            None => (target, None),
+        };
+        Self {
+            target: target.trim().to_string(),
+            heading: heading.filter(|h| !h.is_empty()),
+            alias: alias.filter(|a| !a.is_empty()),
+            embed,
+        }
+    }
+
+    /// Text shown for the link: the alias, or the target and heading.
+    pub fn label(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+        match (&self.heading, self.target.is_empty()) {
+            (Some(heading), true) => heading.clone(),
+            (Some(heading), false) => format!("{} › {}", self.target, heading),
+            (None, _) => self.target.clone(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Piece<'t> {
+    Text(&'t str),
+    Link(WikiLink),
+}
+
+/// Splits `[[...]]` and `![[...]]` out of plain text.
+pub fn split(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find("[[") {
+        let inner_start = open + 2;
+        let Some(close) = rest[inner_start..].find("]]") else {
+            break;
+        };
+        let inner = &rest[inner_start..inner_start + close];
+        if inner.trim().is_empty() || inner.contains(['[', '\n']) {
+            pieces.push(Piece::Text(&rest[..inner_start]));
+            rest = &rest[inner_start..];
+            continue;
+        }
+        let embed = rest[..open].ends_with('!');
+        let text_end = if embed { open - 1 } else { open };
+        if text_end > 0 {
+            pieces.push(Piece::Text(&rest[..text_end]));
+        }
+        pieces.push(Piece::Link(WikiLink::parse(inner, embed)));
+        rest = &rest[inner_start + close + 2..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    pieces
+}
+
+/// Anchor name for a heading, so `[[Note#Some Heading]]` matches `## Some heading`.
+pub fn slug(heading: &str) -> String {
+    let mut slug = String::new();
+    for c in heading.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches('-').to_string()
+}
+
+/// The part of `markdown` under the heading matching `heading`, up to the
+/// next heading of the same or a higher level.
+pub fn section<'m>(markdown: &'m str, heading: &str) -> Option<&'m str> {
+    let wanted = slug(heading);
+    let mut start = None;
+    let mut offset = 0;
+    for line in markdown.split_inclusive('\n') {
+        let level = line.chars().take_while(|&c| c == '#').count();
+        let is_heading = (1..=6).contains(&level) && line[level..].starts_with([' ', '\t']);
+        if is_heading {
+            match start {
+                Some((_, start_level)) if level <= start_level => {
+                    return start.map(|(s, _)| &markdown[s..offset]);
+                }
+                None if slug(line[level..].trim().trim_end_matches('#')) == wanted => {
+                    start = Some((offset, level));
+                }
+                _ => {}
+            }
+        }
+        offset += line.len();
+    }
+    start.map(|(s, _)| &markdown[s..])
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+pub fn is_note(path: &Path) -> bool {
+    has_extension(path, NOTE_EXTENSIONS)
+}
+
+pub fn is_image(path: &Path) -> bool {
+    has_extension(path, IMAGE_EXTENSIONS)
+}
+
+/// The text of a note, unless it's too big to embed or search for links.
+pub fn read_note(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_NOTE_SIZE {
+        return None;
+    }
+    fs::read_to_string(path).ok()
+}
+
+/// A note that links to the current one.
+#[derive(Clone, Debug)]
+pub struct Backlink {
+    pub path: PathBuf,
+    /// Lines containing the links, trimmed.
+    pub context: Vec<String>,
+}
+
+/// Every file under a folder, for resolving links by name. Notes are only
+/// read while looking for backlinks.
+#[derive(Clone, Default)]
+pub struct Vault {
+    pub root: PathBuf,
+    files: HashSet<PathBuf>,
+    /// Files by lowercase file name, for links that only give a name.
+    by_name: HashMap<String, Vec<PathBuf>>,
+}
+
+fn lowercase_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+impl Vault {
+    pub fn scan(root: &Path) -> Self {
+        let root = absolute(root);
+        let files = crate::workspace::files_under(&root, MAX_FILES);
+        let mut vault = Self {
+            root,
+            ..Default::default()
+        };
+        for path in files {
+            vault.add(path);
+        }
+        log::info!(
+            "Indexed {} files in {}.",
+            vault.files.len(),
+            vault.root.display()
+        );
+        vault
+    }
+
+    fn add(&mut self, path: PathBuf) {
+        if self.files.insert(path.clone()) {
+            self.by_name
+                .entry(lowercase_name(&path))
+                .or_default()
+                .push(path);
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if self.files.remove(path) {
+            let name = lowercase_name(path);
+            if let Some(paths) = self.by_name.get_mut(&name) {
+                paths.retain(|p| p != path);
+                if paths.is_empty() {
+                    self.by_name.remove(&name);
+                }
+            }
+        }
+    }
+
+    /// Follows notes added to or removed from a workspace folder.
+    pub fn update(&mut self, changes: &Changes) {
+        for path in &changes.changed {
+            if path.starts_with(&self.root) {
+                self.add(path.clone());
+            }
+        }
+        for path in &changes.removed {
+            self.remove(path);
+        }
+    }
+
+    /// Finds the backlinks to `current` on a background thread, scanning
+    /// `root` first unless `previous` already covers it, since a large
+    /// folder takes a while.
+    pub fn load_in_background(
+        root: &Path,
+        current: &Path,
+        previous: Option<Arc<Self>>,
+    ) -> Option<Receiver<(Arc<Self>, Vec<Backlink>)>> {
+        let (sender, receiver) = mpsc::channel();
+        let folder = absolute(root);
+        let current = current.to_path_buf();
+        let spawned = std::thread::Builder::new()
+            .name("vault scan".to_string())
+            .spawn(move || {
+                let vault = match previous {
+                    Some(vault) if vault.root == folder => vault,
+                    _ => Arc::new(Self::scan(&folder)),
+                };
+                let backlinks = vault.backlinks(&current);
+                sender.send((vault, backlinks)).ok();
+            });
+        match spawned {
+            Ok(_) => Some(receiver),
+            Err(e) => {
+                log::error!("Failed to start scanning {}: {}", root.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Finds the file a link from `from` points at: a path relative to the
+    /// linking note or the folder, or else the closest file with that name.
+    /// Note names may leave out the `.md` extension.
+    pub fn resolve(&self, target: &str, from: Option<&Path>) -> Option<&Path> {
+        let target = target.trim().replace('\\', "/");
+        if target.is_empty() {
+            return None;
+        }
+        let with_extension = |name: &str| {
+            let mut names = vec![name.to_string()];
+            if !is_note(Path::new(name)) {
+                names.extend(NOTE_EXTENSIONS.iter().map(|e| format!("{}.{}", name, e)));
+            }
+            names
+        };
+        let names = with_extension(&target);
+        let bases = from
+            .and_then(Path::parent)
+            .into_iter()
+            .chain([self.root.as_path()]);
+        for base in bases {
+            for name in &names {
+                if let Some(found) = self.files.get(&normalize(&base.join(name))) {
+                    return Some(found);
+                }
+            }
+        }
+        // By name anywhere in the folder; the shortest path wins.
+        names
+            .iter()
+            .map(|name| name.to_lowercase())
+            .flat_map(|name| {
+                let file_name = name.rsplit('/').next().unwrap_or(&name).to_string();
+                self.by_name
+                    .get(&file_name)
+                    .into_iter()
+                    .flatten()
+                    .filter(move |file| {
+                        let relative = file
+                            .strip_prefix(&self.root)
+                            .unwrap_or(file)
+                            .to_string_lossy()
+                            .replace('\\', "/")
+                            .to_lowercase();
+                        relative == name || relative.ends_with(&format!("/{}", name))
+                    })
+            })
+            .min_by_key(|file| (file.components().count(), file.as_path()))
+            .map(PathBuf::as_path)
+    }
+
+    /// Notes with wiki links or relative Markdown links to `current`.
+    pub fn backlinks(&self, current: &Path) -> Vec<Backlink> {
+        let current = absolute(current);
+        let mut notes: Vec<&PathBuf> = self
+            .files
+            .iter()
+            .filter(|path| is_note(path) && **path != current)
+            .collect();
+        notes.sort();
+        let mut backlinks = Vec::new();
+        for path in notes {
+            let Some(content) = read_note(path) else {
+                continue;
+            };
+            let context: Vec<String> = content
+                .lines()
+                .filter(|line| line.contains("[[") || line.contains("]("))
+                .filter(|line| self.line_targets(line, path).contains(&current))
+                .map(|line| line.trim().to_string())
+                .collect();
+            if !context.is_empty() {
+                backlinks.push(Backlink {
+                    path: path.clone(),
+                    context,
+                });
+            }
+        }
+        backlinks
+    }
+
+    fn line_targets(&self, line: &str, from: &Path) -> Vec<PathBuf> {
+        let mut targets: Vec<PathBuf> = split(line)
+            .into_iter()
+            .filter_map(|piece| match piece {
+                Piece::Link(link) => self.resolve(&link.target, Some(from)),
+                Piece::Text(_) => None,
+            })
+            .map(Path::to_path_buf)
+            .collect();
+        // `[text](other.md)` style links.
+        let mut rest = line;
+        while let Some(start) = rest.find("](") {
+            let destination = &rest[start + 2..];
+            let end = destination.find(')').unwrap_or(destination.len());
+            let destination = destination[..end].split('#').next().unwrap_or("");
+            let destination = destination.trim().replace("%20", " ");
+            if !destination.is_empty() && !destination.contains("://") {
+                if let Some(dir) = from.parent() {
+                    targets.push(normalize(&dir.join(destination)));
+                }
+            }
+            rest = &rest[start + 2..];
+        }
+        targets
+    }
+}
+
+/// `path` made absolute against the working directory. Unlike `fs::canonicalize`
+/// this doesn't produce `\\?\` paths on Windows.
+pub fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        normalize(path)
+    } else {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        normalize(&cwd.join(path))
+    }
+}
+
+/// Removes `.` and `..` components without touching the file system.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(
+        target: &str,
+        heading: Option<&str>,
+        alias: Option<&str>,
+        embed: bool,
+    ) -> Piece<'static> {
+        Piece::Link(WikiLink {
+            target: target.to_string(),
+            heading: heading.map(str::to_string),
+            alias: alias.map(str::to_string),
+            embed,
+        })
+    }
+
+    fn vault(files: &[&str]) -> Vault {
+        let mut vault = Vault {
+            root: PathBuf::from("/vault"),
+            ..Default::default()
+        };
+        for file in files {
+            vault.add(vault.root.join(file));
+        }
+        vault
+    }
+
+    #[test]
+    fn split_links() {
+        assert_eq!(
+            split("See [[Page#Some Heading|the page]] and ![[image.png]]."),
+            [
+                Piece::Text("See "),
+                link("Page", Some("Some Heading"), Some("the page"), false),
+                Piece::Text(" and "),
+                link("image.png", None, None, true),
+                Piece::Text("."),
+            ]
+        );
+        assert_eq!(
+            split("[[ Page | ]][[#Local]][[Page#]]"),
+            [
+                link("Page", None, None, false),
+                link("", Some("Local"), None, false),
+                link("Page", None, None, false),
+            ]
+        );
+        // Empty, nested and multi-line brackets aren't links.
+        assert_eq!(
+            split("[[]] [[a\nb]] [[open"),
+            [
+                Piece::Text("[["),
+                Piece::Text("]] [["),
+                Piece::Text("a\nb]] [[open")
+            ]
+        );
+        assert_eq!(split("[[[x]]]"), [Piece::Text("[["), Piece::Text("[x]]]")]);
+    }
+
+    #[test]
+    fn labels() {
+        let label = |text: &str| match &split(text)[0] {
+            Piece::Link(link) => link.label(),
+            piece => panic!("{:?}", piece),
+        };
+        assert_eq!(label("[[Page#Heading|Alias]]"), "Alias");
+        assert_eq!(label("[[Page#Heading]]"), "Page › Heading");
+        assert_eq!(label("[[#Heading]]"), "Heading");
+        assert_eq!(label("[[Page]]"), "Page");
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("Some Heading"), "some-heading");
+        assert_eq!(slug("  What's new? (v2.0)  "), "what-s-new-v2-0");
+        assert_eq!(slug("Ünïcode Überschrift"), "ünïcode-überschrift");
+        assert_eq!(slug("--"), "");
+    }
+
+    #[test]
+    fn sections() {
+        let markdown =
+            "# Title\nintro\n## Setup\nsteps\n### Details\nmore\n## Usage\nuse it\n# End\n";
+        assert_eq!(
+            section(markdown, "setup"),
+            Some("## Setup\nsteps\n### Details\nmore\n")
+        );
+        assert_eq!(section(markdown, "Details"), Some("### Details\nmore\n"));
+        assert_eq!(section(markdown, "usage"), Some("## Usage\nuse it\n"));
+        assert_eq!(section(markdown, "end"), Some("# End\n"));
+        assert_eq!(section(markdown, "missing"), None);
+        // Closing hashes and `#` without a space aren't part of the heading.
+        assert_eq!(
+            section("## Setup ##\na\n#tag\nb", "setup"),
+            Some("## Setup ##\na\n#tag\nb")
+        );
+        assert_eq!(section("#Setup\na", "setup"), None);
+    }
+
+    #[test]
+    fn resolve() {
+        let vault = vault(&[
+            "Index.md",
+            "notes/Page.md",
+            "notes/deep/Page.md",
+            "notes/image.png",
+            "other/page.markdown",
+            "archive/2020/Old.md",
+        ]);
+        let from = Path::new("/vault/notes/deep/Here.md");
+        let resolve = |target: &str, from: Option<&Path>| {
+            vault.resolve(target, from).map(|path| {
+                path.strip_prefix("/vault")
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+        };
+        // Relative to the linking note first, then to the folder.
+        assert_eq!(
+            resolve("Page", Some(from)).as_deref(),
+            Some("notes/deep/Page.md")
+        );
+        assert_eq!(
+            resolve("../Page.md", Some(from)).as_deref(),
+            Some("notes/Page.md")
+        );
+        assert_eq!(
+            resolve("./../image.png", Some(from)).as_deref(),
+            Some("notes/image.png")
+        );
+        assert_eq!(
+            resolve("other/page", Some(from)).as_deref(),
+            Some("other/page.markdown")
+        );
+        assert_eq!(
+            resolve("notes\\Page", None).as_deref(),
+            Some("notes/Page.md")
+        );
+        // Bare names anywhere, case-insensitively; the shortest path wins.
+        assert_eq!(resolve("Page", None).as_deref(), Some("notes/Page.md"));
+        assert_eq!(
+            resolve("old", Some(from)).as_deref(),
+            Some("archive/2020/Old.md")
+        );
+        assert_eq!(
+            resolve("2020/Old.md", None).as_deref(),
+            Some("archive/2020/Old.md")
+        );
+        assert_eq!(
+            resolve("image.png", None).as_deref(),
+            Some("notes/image.png")
+        );
+        assert_eq!(resolve("index", None).as_deref(), Some("Index.md"));
+        assert_eq!(resolve("image", None), None);
+        assert_eq!(resolve("0/Old", None), None);
+        assert_eq!(resolve(" ", None), None);
+    }
+
+    #[test]
+    fn update() {
+        let mut vault = vault(&["a.md", "b.md"]);
+        vault.update(&Changes {
+            changed: vec![
+                PathBuf::from("/vault/c.md"),
+                PathBuf::from("/elsewhere/d.md"),
+            ],
+            removed: vec![PathBuf::from("/vault/a.md")],
+        });
+        assert_eq!(vault.resolve("a", None), None);
+        assert_eq!(vault.resolve("c", None), Some(Path::new("/vault/c.md")));
+        assert_eq!(vault.resolve("d", None), None);
+        assert!(!vault.by_name.contains_key("a.md"));
+    }
+}
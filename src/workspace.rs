@@ -0,0 +1,843 @@
+//! A folder opened as a workspace: its Markdown files as a tree, honouring
+//! `.gitignore`, watched so the tree follows changes on disk. Only the
+//! folders that changed are scanned again.
+
+use eframe::egui;
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+/// How often the folder is rescanned when it can't be watched.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+/// Stop scanning very large folders, e.g. a home directory.
+const MAX_FILES: usize = 100_000;
+/// Opened by default when the folder is opened, in order of preference.
+const DEFAULT_DOCUMENTS: &[&str] = &["README.md", "readme.md", "Readme.md", "index.md"];
+
+/// A folder in the tree, holding only what leads to Markdown files.
+#[derive(Debug, Default)]
+pub struct Dir {
+    pub name: String,
+    pub path: PathBuf,
+    pub dirs: Vec<Dir>,
+    pub files: Vec<PathBuf>,
+}
+
+/// A Markdown file in the workspace, for quick-open.
+pub struct FileEntry {
+    pub path: PathBuf,
+    /// Path relative to the root with `/` separators.
+    pub relative: String,
+}
+
+/// Markdown files that changed since the last poll, for the search index.
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// Added files, and files that may have been edited.
+    pub changed: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Folders scanned again, each with its new tree or `None` if nothing in
+/// it is shown any more.
+type Subtrees = Vec<(PathBuf, Option<Dir>)>;
+
+enum Watch {
+    /// Started by the first poll, which brings the context to wake the UI.
+    NotStarted,
+    /// Watching a large folder takes a while, so it's set up on a thread.
+    Starting(Receiver<notify::Result<RecommendedWatcher>>),
+    Watching {
+        /// Watching stops when it's dropped.
+        _watcher: RecommendedWatcher,
+    },
+    /// Watching isn't possible, so the folder is rescanned now and then.
+    Failed,
+}
+
+pub struct Workspace {
+    pub root: PathBuf,
+    pub tree: Dir,
+    /// Every file in `tree`, indexed along with it in the background.
+    pub files: Vec<FileEntry>,
+    /// Whether the first scan has finished.
+    pub scanned: bool,
+    known: HashSet<PathBuf>,
+    pending: Option<Receiver<Subtrees>>,
+    watch: Watch,
+    events: Option<Receiver<notify::Result<notify::Event>>>,
+    /// Folders to scan again once the running scan has finished.
+    dirty: Vec<PathBuf>,
+    /// Files edited in place, which doesn't change the tree.
+    edited: Vec<PathBuf>,
+    last_scan: Instant,
+}
+
+impl Workspace {
+    /// Opens `root` and starts scanning it in the background.
+    pub fn open(root: &Path) -> Self {
+        let root = crate::wiki::absolute(root);
+        let mut workspace = Self {
+            tree: Dir {
+                name: display_name(&root),
+                path: root.clone(),
+                ..Default::default()
+            },
+            files: Vec::new(),
+            scanned: false,
+            known: HashSet::new(),
+            pending: None,
+            watch: Watch::NotStarted,
+            events: None,
+            dirty: vec![root.clone()],
+            edited: Vec::new(),
+            last_scan: Instant::now(),
+            root,
+        };
+        workspace.start_scan();
+        workspace
+    }
+
+    /// Scans the dirty folders on a background thread.
+    fn start_scan(&mut self) {
+        let mut dirs = std::mem::take(&mut self.dirty);
+        // Folders inside others are scanned along with them.
+        dirs.sort();
+        dirs.dedup_by(|dir, parent| dir.starts_with(parent));
+        let (sender, receiver) = mpsc::channel();
+        let root = self.root.clone();
+        let spawned = std::thread::Builder::new()
+            .name("workspace scan".to_string())
+            .spawn(move || {
+                let subtrees: Subtrees = dirs
+                    .into_iter()
+                    .map(|dir| {
+                        let subtree = scan_subtree(&root, &dir);
+                        (dir, subtree)
+                    })
+                    .collect();
+                sender.send(subtrees).ok();
+            });
+        match spawned {
+            Ok(_) => self.pending = Some(receiver),
+            Err(e) => log::error!("Failed to start scanning {}: {}", self.root.display(), e),
+        }
+        self.last_scan = Instant::now();
+    }
+
+    /// Starts watching the folder, waking the UI when something in it changes.
+    fn start_watching(&mut self, ctx: &egui::Context) {
+        let (event_sender, events) = mpsc::channel();
+        let (sender, receiver) = mpsc::channel();
+        let root = self.root.clone();
+        let ctx = ctx.clone();
+        let spawned = std::thread::Builder::new()
+            .name("workspace watch".to_string())
+            .spawn(move || {
+                let watched = root.clone();
+                let handler = move |event: notify::Result<notify::Event>| {
+                    let relevant = match &event {
+                        Ok(event) => {
+                            !matches!(event.kind, EventKind::Access(_))
+                                && event.paths.iter().any(|path| is_shown(&watched, path))
+                        }
+                        Err(_) => true,
+                    };
+                    if relevant && event_sender.send(event).is_ok() {
+                        ctx.request_repaint();
+                    }
+                };
+                let watcher = notify::recommended_watcher(handler).and_then(|mut watcher| {
+                    watcher.watch(&root, RecursiveMode::Recursive)?;
+                    Ok(watcher)
+                });
+                sender.send(watcher).ok();
+            });
+        match spawned {
+            Ok(_) => {
+                self.watch = Watch::Starting(receiver);
+                self.events = Some(events);
+            }
+            Err(e) => {
+                log::error!("Failed to start watching {}: {}", self.root.display(), e);
+                self.watch = Watch::Failed;
+            }
+        }
+    }
+
+    /// Notes what an event changed: a file edited in place, or folders to
+    /// scan again.
+    fn note_event(&mut self, event: notify::Event) {
+        if event.need_rescan() {
+            self.dirty.push(self.root.clone());
+            return;
+        }
+        for path in event.paths {
+            if !is_shown(&self.root, &path) {
+                continue;
+            }
+            let relevant = if path.exists() {
+                // Only Markdown files, folders and ignore rules are shown or
+                // change what is.
+                !path.is_file() || crate::wiki::is_note(&path) || path.ends_with(".gitignore")
+            } else {
+                self.known.contains(&path) || has_dir(&self.tree, &path)
+            };
+            if !relevant {
+                continue;
+            }
+            let modified = matches!(
+                event.kind,
+                EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Metadata(_) | ModifyKind::Any)
+            );
+            if modified && self.known.contains(&path) {
+                self.edited.push(path);
+            } else {
+                let dir = match path.parent() {
+                    Some(parent) if path != self.root => parent.to_path_buf(),
+                    _ => self.root.clone(),
+                };
+                self.dirty.push(dir);
+            }
+        }
+    }
+
+    /// Splices freshly scanned folders into the tree and lists the files
+    /// that changed with them.
+    fn apply(&mut self, subtrees: Subtrees) -> Changes {
+        let dirs: Vec<PathBuf> = subtrees.iter().map(|(dir, _)| dir.clone()).collect();
+        let mut spliced = false;
+        for (dir, subtree) in subtrees {
+            spliced |= splice(&mut self.tree, &dir, subtree);
+        }
+        if !spliced {
+            return Changes::default();
+        }
+        self.files = index(&self.root, &self.tree);
+        let known: HashSet<PathBuf> = self.files.iter().map(|f| f.path.clone()).collect();
+        let changes = Changes {
+            changed: self
+                .files
+                .iter()
+                .filter(|file| dirs.iter().any(|dir| file.path.starts_with(dir)))
+                .map(|file| file.path.clone())
+                .collect(),
+            removed: self.known.difference(&known).cloned().collect(),
+        };
+        self.known = known;
+        changes
+    }
+
+    /// Picks up changes on disk and finished scans, and starts new ones.
+    /// Returns the Markdown files that changed, so anything built from them
+    /// can catch up.
+    pub fn poll(&mut self, ctx: &egui::Context) -> Option<Changes> {
+        match &self.watch {
+            Watch::NotStarted => self.start_watching(ctx),
+            Watch::Starting(receiver) => match receiver.try_recv() {
+                Ok(Ok(watcher)) => {
+                    log::debug!("Watching {} for changes.", self.root.display());
+                    self.watch = Watch::Watching { _watcher: watcher };
+                }
+                Ok(Err(e)) => {
+                    log::warn!(
+                        "Can't watch {}, rescanning it every {} seconds instead: {}",
+                        self.root.display(),
+                        RESCAN_INTERVAL.as_secs(),
+                        e
+                    );
+                    self.watch = Watch::Failed;
+                    self.events = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.watch = Watch::Failed,
+            },
+            Watch::Watching { .. } | Watch::Failed => {}
+        }
+        let events: Vec<_> = self
+            .events
+            .as_ref()
+            .map(|events| events.try_iter().collect())
+            .unwrap_or_default();
+        for event in events {
+            match event {
+                Ok(event) => self.note_event(event),
+                Err(e) => {
+                    log::warn!("Error watching {}: {}", self.root.display(), e);
+                    self.dirty.push(self.root.clone());
+                }
+            }
+        }
+        let mut changes = Changes::default();
+        if let Some(receiver) = &self.pending {
+            match receiver.try_recv() {
+                Ok(subtrees) => {
+                    changes = self.apply(subtrees);
+                    self.scanned = true;
+                    self.pending = None;
+                }
+                Err(TryRecvError::Empty) => {
+                    ctx.request_repaint_after(Duration::from_millis(100));
+                }
+                Err(TryRecvError::Disconnected) => self.pending = None,
+            }
+        }
+        if self.pending.is_none() {
+            if matches!(self.watch, Watch::Failed) {
+                if self.last_scan.elapsed() >= RESCAN_INTERVAL {
+                    self.dirty.push(self.root.clone());
+                }
+                ctx.request_repaint_after(RESCAN_INTERVAL);
+            }
+            if !self.dirty.is_empty() {
+                self.start_scan();
+            }
+        }
+        changes.changed.append(&mut self.edited);
+        (!changes.is_empty()).then_some(changes)
+    }
+}
+
+/// Whether changes to `path` can matter to the tree: it isn't hidden or in a
+/// hidden folder below `root`, unless it's a `.gitignore`.
+fn is_shown(root: &Path, path: &Path) -> bool {
+    let hidden = path.strip_prefix(root).is_ok_and(|relative| {
+        relative
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    });
+    !hidden || path.ends_with(".gitignore")
+}
+
+/// Scans `dir` below `root` again, with the `.gitignore` rules of the
+/// folders above it. `None` if it's gone, ignored or has no Markdown files.
+fn scan_subtree(root: &Path, dir: &Path) -> Option<Dir> {
+    if dir == root {
+        return Some(scan(root));
+    }
+    let relative = dir.strip_prefix(root).ok()?;
+    let mut ignores = Vec::new();
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        if let Ok(content) = fs::read_to_string(current.join(".gitignore")) {
+            ignores.push(Gitignore::parse(&current, &content));
+        }
+        current.push(component);
+        if is_ignored(&ignores, &current, true) {
+            return None;
+        }
+    }
+    if !dir.is_dir() {
+        return None;
+    }
+    scan_dir(dir, &mut ignores, &mut 0)
+}
+
+fn sort_key(path: &Path) -> Option<std::ffi::OsString> {
+    path.file_name().map(|n| n.to_ascii_lowercase())
+}
+
+/// Puts `subtree` in place of the folder at `path`, adding the folders
+/// leading to it or pruning those left empty. Returns false if there was
+/// nothing to change.
+fn splice(tree: &mut Dir, path: &Path, subtree: Option<Dir>) -> bool {
+    if tree.path == path {
+        *tree = subtree.unwrap_or_else(|| Dir {
+            name: display_name(path),
+            path: path.to_path_buf(),
+            ..Default::default()
+        });
+        return true;
+    }
+    let Some(child) = path
+        .strip_prefix(&tree.path)
+        .ok()
+        .and_then(|relative| relative.components().next())
+        .map(|first| tree.path.join(first))
+    else {
+        return false;
+    };
+    match tree.dirs.iter().position(|dir| dir.path == child) {
+        Some(i) if child == path => {
+            match subtree {
+                Some(subtree) => tree.dirs[i] = subtree,
+                None => {
+                    tree.dirs.remove(i);
+                }
+            }
+            true
+        }
+        Some(i) => {
+            let spliced = splice(&mut tree.dirs[i], path, subtree);
+            let dir = &tree.dirs[i];
+            if dir.dirs.is_empty() && dir.files.is_empty() {
+                tree.dirs.remove(i);
+            }
+            spliced
+        }
+        None => {
+            let Some(subtree) = subtree else {
+                return false;
+            };
+            let dir = if child == path {
+                subtree
+            } else {
+                let mut dir = Dir {
+                    name: display_name(&child),
+                    path: child.clone(),
+                    ..Default::default()
+                };
+                splice(&mut dir, path, Some(subtree));
+                dir
+            };
+            let at = tree
+                .dirs
+                .partition_point(|d| sort_key(&d.path) < sort_key(&dir.path));
+            tree.dirs.insert(at, dir);
+            true
+        }
+    }
+}
+
+/// Whether the tree shows the folder at `path`.
+fn has_dir(tree: &Dir, path: &Path) -> bool {
+    if tree.path == path {
+        return true;
+    }
+    tree.dirs
+        .iter()
+        .find(|dir| path.starts_with(&dir.path))
+        .is_some_and(|dir| has_dir(dir, path))
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into_owned(),
+    )
+}
+
+/// The `README.md` or `index.md` directly inside `dir`.
+pub fn default_document(dir: &Path) -> Option<PathBuf> {
+    DEFAULT_DOCUMENTS
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Walks `root` for Markdown files, skipping hidden and ignored entries.
+fn scan(root: &Path) -> Dir {
+    let started = Instant::now();
+    let mut count = 0;
+    let mut ignores = Vec::new();
+    let tree = scan_dir(root, &mut ignores, &mut count).unwrap_or_else(|| Dir {
+        name: display_name(root),
+        path: root.to_path_buf(),
+        ..Default::default()
+    });
+    if count >= MAX_FILES {
+        log::warn!(
+            "Stopped scanning {} after {} files.",
+            root.display(),
+            MAX_FILES
+        );
+    }
+    log::debug!(
+        "Scanned {} ({} files) in {:?}.",
+        root.display(),
+        count,
+        started.elapsed()
+    );
+    tree
+}
+
+/// Flattens the tree into a list of files with their relative paths.
+fn index(root: &Path, tree: &Dir) -> Vec<FileEntry> {
+    let mut files = Vec::new();
+    let mut pending = vec![tree];
+    while let Some(dir) = pending.pop() {
+        pending.extend(&dir.dirs);
+        files.extend(dir.files.iter().map(|path| {
+            FileEntry {
+                path: path.clone(),
+                relative: path
+                    .strip_prefix(root)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+            }
+        }));
+    }
+    files
+}
+
+/// `None` if the folder has no Markdown files anywhere below it.
+fn scan_dir(dir: &Path, ignores: &mut Vec<Gitignore>, count: &mut usize) -> Option<Dir> {
+    let pushed = match fs::read_to_string(dir.join(".gitignore")) {
+        Ok(content) => {
+            ignores.push(Gitignore::parse(dir, &content));
+            true
+        }
+        Err(_) => false,
+    };
+    let mut entries: Vec<(PathBuf, bool)> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .filter_map(|entry| {
+                let kind = entry.file_type().ok()?;
+                Some((entry.path(), kind.is_dir()))
+            })
+            .collect(),
+        Err(e) => {
+            log::debug!("Skipping folder {}: {}", dir.display(), e);
+            Vec::new()
+        }
+    };
+    entries.sort_by_key(|(path, _)| path.file_name().map(|n| n.to_ascii_lowercase()));
+    let mut tree = Dir {
+        name: display_name(dir),
+        path: dir.to_path_buf(),
+        ..Default::default()
+    };
+    for (path, is_dir) in entries {
+        if *count >= MAX_FILES {
+            break;
+        }
+        if is_ignored(ignores, &path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            if let Some(child) = scan_dir(&path, ignores, count) {
+                tree.dirs.push(child);
+            }
+        } else if crate::wiki::is_note(&path) {
+            *count += 1;
+            tree.files.push(path);
+        }
+    }
+    if pushed {
+        ignores.pop();
+    }
+    (!tree.dirs.is_empty() || !tree.files.is_empty()).then_some(tree)
+}
+
+/// Every file under `root` up to `max`, skipping hidden and ignored entries
+/// like the tree does, but not only Markdown files.
+pub fn files_under(root: &Path, max: usize) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    collect_files(root, &mut Vec::new(), max, &mut files);
+    if files.len() >= max {
+        log::warn!("Stopped listing {} after {} files.", root.display(), max);
+    }
+    files.sort();
+    files
+}
+
+fn collect_files(dir: &Path, ignores: &mut Vec<Gitignore>, max: usize, files: &mut Vec<PathBuf>) {
+    let pushed = match fs::read_to_string(dir.join(".gitignore")) {
+        Ok(content) => {
+            ignores.push(Gitignore::parse(dir, &content));
+            true
+        }
+        Err(_) => false,
+    };
+    match fs::read_dir(dir) {
+        Ok(entries) => {
+            for entry in entries.flatten() {
+                if files.len() >= max {
+                    break;
+                }
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let Ok(kind) = entry.file_type() else {
+                    continue;
+                };
+                let path = entry.path();
+                if is_ignored(ignores, &path, kind.is_dir()) {
+                    continue;
+                }
+                if kind.is_dir() {
+                    collect_files(&path, ignores, max, files);
+                } else if kind.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+        Err(e) => log::debug!("Skipping folder {}: {}", dir.display(), e),
+    }
+    if pushed {
+        ignores.pop();
+    }
+}
+
+fn is_ignored(ignores: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for gitignore in ignores {
+        if let Some(matched) = gitignore.matches(path, is_dir) {
+            ignored = matched;
+        }
+    }
+    ignored
+}
+
+struct Rule {
+    pattern: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    /// Patterns with a slash match the path from the `.gitignore`'s folder;
+    /// others match the file name at any depth.
+    anchored: bool,
+}
+
+/// The rules of one `.gitignore` file.
+struct Gitignore {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Gitignore {
+    fn parse(base: &Path, content: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in content.lines() {
+            let mut line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let negated = line.starts_with('!');
+            if negated {
+                line = &line[1..];
+            }
+            let line = line.strip_prefix('\\').unwrap_or(line);
+            let dir_only = line.ends_with('/');
+            let line = line.trim_end_matches('/');
+            if line.is_empty() {
+                continue;
+            }
+            let anchored = line.contains('/');
+            rules.push(Rule {
+                pattern: line.trim_start_matches('/').chars().collect(),
+                negated,
+                dir_only,
+                anchored,
+            });
+        }
+        Self {
+            base: base.to_path_buf(),
+            rules,
+        }
+    }
+
+    /// `Some(true)` if the last matching rule ignores `path`, `Some(false)`
+    /// if it re-includes it, `None` if no rule matches.
+    fn matches(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let relative: Vec<char> = relative
+            .to_string_lossy()
+            .replace('\\', "/")
+            .chars()
+            .collect();
+        let name_start = relative
+            .iter()
+            .rposition(|&c| c == '/')
+            .map_or(0, |i| i + 1);
+        let mut result = None;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let text = if rule.anchored {
+                &relative[..]
+            } else {
+                &relative[name_start..]
+            };
+            if glob_match(&rule.pattern, text) {
+                result = Some(!rule.negated);
+            }
+        }
+        result
+    }
+}
+
+/// Matches a gitignore glob: `*` and `?` stay within a path component,
+/// `**` spans components, and `[a-z]` / `[!a-z]` match character classes.
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            match rest.strip_prefix(&['/']) {
+                // `**/` matches zero or more whole folders.
+                Some(rest) => {
+                    glob_match(rest, text)
+                        || (0..text.len())
+                            .any(|i| text[i] == '/' && glob_match(rest, &text[i + 1..]))
+                }
+                None => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+            }
+        }
+        Some('*') => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(&pattern[1..], &text[i..])),
+        Some('?') => {
+            text.first().is_some_and(|&c| c != '/') && glob_match(&pattern[1..], &text[1..])
+        }
+        Some('[') => match pattern.iter().skip(1).position(|&c| c == ']') {
+            Some(end) if end > 0 => {
+                let class = &pattern[1..end + 1];
+                let (negated, class) = match class.first() {
+                    Some('!' | '^') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+                let Some(&c) = text.first() else {
+                    return false;
+                };
+                let mut matched = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if class.get(i + 1) == Some(&'-') && i + 2 < class.len() {
+                        matched |= (class[i]..=class[i + 2]).contains(&c);
+                        i += 3;
+                    } else {
+                        matched |= class[i] == c;
+                        i += 1;
+                    }
+                }
+                matched != negated && c != '/' && glob_match(&pattern[end + 2..], &text[1..])
+            }
+            _ => text.first() == Some(&'[') && glob_match(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Draws the tree. Returns the file that was clicked, if any.
+pub fn tree_ui(
+    ui: &mut egui::Ui,
+    workspace: &Workspace,
+    current: Option<&Path>,
+) -> Option<PathBuf> {
+    let mut clicked = None;
+    if !workspace.scanned {
+        ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label(egui::RichText::new("Scanning…").weak());
+        });
+    } else if workspace.tree.files.is_empty() && workspace.tree.dirs.is_empty() {
+        ui.label(egui::RichText::new("No Markdown files in this folder.").weak());
+    }
+    dir_contents_ui(ui, &workspace.tree, current, &mut clicked);
+    clicked
+}
+
+fn dir_contents_ui(
+    ui: &mut egui::Ui,
+    dir: &Dir,
+    current: Option<&Path>,
+    clicked: &mut Option<PathBuf>,
+) {
+    for child in &dir.dirs {
+        // Keep the folder holding the open file expanded.
+        let contains_current = current.is_some_and(|path| path.starts_with(&child.path));
+        egui::CollapsingHeader::new(format!("📁 {}", child.name))
+            .id_source(&child.path)
+            .default_open(contains_current)
+            .show(ui, |ui| dir_contents_ui(ui, child, current, clicked));
+    }
+    for file in &dir.files {
+        let name = display_name(file);
+        let selected = current == Some(file.as_path());
+        if ui
+            .selectable_label(selected, format!("📄 {}", name))
+            .on_hover_text(file.display().to_string())
+            .clicked()
+        {
+            *clicked = Some(file.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        glob_match(&pattern, &text)
+    }
+
+    fn ignored(rules: &str, path: &str, is_dir: bool) -> bool {
+        let ignores = [Gitignore::parse(Path::new("/w"), rules)];
+        is_ignored(&ignores, &Path::new("/w").join(path), is_dir)
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob("*.md", "notes.md"));
+        assert!(!glob("*.md", "notes.markdown"));
+        assert!(!glob("*.md", "a/notes.md"));
+        assert!(glob("note?.md", "note1.md"));
+        assert!(!glob("note?.md", "note/.md"));
+        assert!(glob("**/build", "build"));
+        assert!(glob("**/build", "a/b/build"));
+        assert!(glob("docs/**", "docs/a/b.md"));
+        assert!(glob("a/**/b", "a/b"));
+        assert!(glob("a/**/b", "a/x/y/b"));
+        assert!(glob("[a-c]x", "bx"));
+        assert!(!glob("[a-c]x", "dx"));
+        assert!(glob("[!a-c]x", "dx"));
+        assert!(glob(r"\*.md", "*.md"));
+        assert!(!glob(r"\*.md", "a.md"));
+    }
+
+    #[test]
+    fn negated_patterns() {
+        let rules = "*.md\n!keep.md\n";
+        assert!(ignored(rules, "notes.md", false));
+        assert!(!ignored(rules, "keep.md", false));
+        assert!(!ignored(rules, "sub/keep.md", false));
+        // The last matching rule wins.
+        assert!(ignored("!keep.md\n*.md\n", "keep.md", false));
+        // A leading backslash makes `!` literal.
+        assert!(ignored(r"\!important.md", "!important.md", false));
+    }
+
+    #[test]
+    fn directory_patterns() {
+        assert!(ignored("build/", "build", true));
+        assert!(ignored("build/", "src/build", true));
+        assert!(!ignored("build/", "build", false));
+        assert!(ignored("build", "build", false));
+        // Patterns with a slash are anchored to the `.gitignore`'s folder.
+        assert!(ignored("/build", "build", true));
+        assert!(!ignored("/build", "src/build", true));
+        assert!(ignored("docs/drafts/", "docs/drafts", true));
+        assert!(!ignored("docs/drafts/", "other/docs/drafts", true));
+        assert!(!ignored("# build\n\n", "build", true));
+    }
+
+    #[test]
+    fn nested_gitignores() {
+        let ignores = [
+            Gitignore::parse(Path::new("/w"), "*.md\n"),
+            Gitignore::parse(Path::new("/w/sub"), "!*.md\n"),
+        ];
+        assert!(is_ignored(&ignores, Path::new("/w/a.md"), false));
+        assert!(!is_ignored(&ignores, Path::new("/w/sub/a.md"), false));
+    }
+}